@@ -0,0 +1,93 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+/*! This is an example of stepping through a WebGPU trace.
+ *  It prints each action before replaying it, and asks what to do with it.
+ *  The actions are read one at a time, so the trace has to be in RON.
+!*/
+
+use player::{
+    gfx_select, GlobalPlay as _, IdentityPassThroughFactory, PlaybackState, PlayerConfig,
+    StepControl, TraceReader,
+};
+use wgc::device::trace;
+
+use std::{
+    io::{self, BufRead as _, Write as _},
+    path::PathBuf,
+};
+
+fn prompt(index: usize, action: &trace::Action) -> StepControl {
+    println!("#{}: {:?}", index, action);
+    loop {
+        print!("[c]ontinue, [s]kip, or [a]bort? ");
+        io::stdout().flush().unwrap();
+        let mut line = String::new();
+        if io::stdin().lock().read_line(&mut line).unwrap() == 0 {
+            return StepControl::Abort;
+        }
+        match line.trim() {
+            "" | "c" => return StepControl::Continue,
+            "s" => return StepControl::Skip,
+            "a" => return StepControl::Abort,
+            _ => {}
+        }
+    }
+}
+
+fn main() {
+    env_logger::init();
+
+    let dir = std::env::args()
+        .nth(1)
+        .map(PathBuf::from)
+        .expect("Provide the dir path as the parameter");
+
+    let config = PlayerConfig {
+        use_headless_swapchain: true,
+        ..PlayerConfig::default()
+    };
+    // the IDs of the trace are reserved in a first pass over it,
+    // and the actions are read again to be replayed
+    let mut state = PlaybackState::new(config, &[]);
+    for action in TraceReader::open(&dir).unwrap().skip_invalid() {
+        state.reserve_ids(&action);
+    }
+    state.set_step_hook(prompt);
+    let actions = TraceReader::open(&dir).unwrap().skip_invalid();
+
+    let global =
+        wgc::hub::Global::new("player", IdentityPassThroughFactory, wgt::BackendBit::all());
+    let result = global.play_devices(actions, &dir, &mut state, |action| match action {
+        trace::Action::Init {
+            device,
+            desc,
+            backend,
+            ..
+        } => {
+            let adapter = global
+                .pick_adapter(
+                    &wgc::instance::RequestAdapterOptions {
+                        power_preference: wgt::PowerPreference::Default,
+                        compatible_surface: None,
+                    },
+                    wgc::instance::AdapterInputs::IdSet(
+                        &[wgc::id::TypedId::zip(0, 0, backend)],
+                        |id| id.backend(),
+                    ),
+                )
+                .ok_or("Unable to find an adapter for selected backend")?;
+            Ok(gfx_select!(adapter => global.adapter_request_device(adapter, &desc, None, device))??)
+        }
+        _ => unreachable!(),
+    });
+    if let Err(e) = result {
+        println!("{}", e);
+    }
+    for (device, _) in state.devices() {
+        gfx_select!(device => global.device_poll(device, true))
+            .unwrap()
+            .unwrap();
+    }
+}
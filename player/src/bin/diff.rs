@@ -0,0 +1,280 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+/*! This is a tool for comparing two WebGPU traces.
+ *  It walks both traces in lockstep and reports the actions that differ.
+!*/
+
+use wgc::device::trace;
+
+use std::path::{Path, PathBuf};
+
+const DEFAULT_MAX_DIFFERENCES: &str = "10";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum DataKind {
+    SpirV,
+    Wgsl,
+    Bytes,
+}
+
+/// Returns the binary files referenced by the action, in order.
+fn data_files(action: &trace::Action) -> Vec<(&str, DataKind)> {
+    use wgc::device::trace::Action as A;
+    match *action {
+        A::CreateShaderModule { ref source, .. } => vec![match *source {
+            trace::ShaderModuleSource::SpirV(ref data) => (data.as_str(), DataKind::SpirV),
+            trace::ShaderModuleSource::Wgsl(ref data) => (data.as_str(), DataKind::Wgsl),
+        }],
+        A::WriteBuffer { ref data, .. } | A::WriteTexture { ref data, .. } => {
+            vec![(data.as_str(), DataKind::Bytes)]
+        }
+        A::WriteBufferMany { ref writes, .. } => writes
+            .iter()
+            .map(|&(_, ref data)| (data.as_str(), DataKind::Bytes))
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Describes the first difference between the contents of two binary files,
+/// according to the `kind` of their contents.
+fn compare_data(kind: DataKind, a: &[u8], b: &[u8]) -> Option<String> {
+    if a == b {
+        return None;
+    }
+    Some(match kind {
+        DataKind::SpirV => {
+            let (chunks_a, chunks_b) = (a.chunks_exact(4), b.chunks_exact(4));
+            let (rest_a, rest_b) = (chunks_a.remainder(), chunks_b.remainder());
+            let word = |c: &[u8]| u32::from_le_bytes([c[0], c[1], c[2], c[3]]);
+            let wa = chunks_a.map(word).collect::<Vec<_>>();
+            let wb = chunks_b.map(word).collect::<Vec<_>>();
+            let offset = wa.iter().zip(&wb).take_while(|(x, y)| x == y).count();
+            if offset == wa.len() && offset == wb.len() {
+                format!(
+                    "SPIR-V differs in the trailing bytes after word {} ({:?} vs {:?})",
+                    offset, rest_a, rest_b
+                )
+            } else {
+                let mut diff = format!(
+                    "SPIR-V differs at word {} ({:?} vs {:?}), lengths {} and {} words",
+                    offset,
+                    wa.get(offset),
+                    wb.get(offset),
+                    wa.len(),
+                    wb.len()
+                );
+                if !rest_a.is_empty() || !rest_b.is_empty() {
+                    diff += &format!(
+                        ", followed by {} and {} trailing bytes",
+                        rest_a.len(),
+                        rest_b.len()
+                    );
+                }
+                diff
+            }
+        }
+        DataKind::Wgsl => {
+            let (ta, tb) = (String::from_utf8_lossy(a), String::from_utf8_lossy(b));
+            let mut lines_a = ta.lines();
+            let mut lines_b = tb.lines();
+            let mut line = 1;
+            loop {
+                match (lines_a.next(), lines_b.next()) {
+                    (Some(la), Some(lb)) if la == lb => line += 1,
+                    (la, lb) => {
+                        break format!(
+                            "WGSL differs at line {}:\n\t\t- {}\n\t\t+ {}",
+                            line,
+                            la.unwrap_or("<end of file>"),
+                            lb.unwrap_or("<end of file>")
+                        )
+                    }
+                }
+            }
+        }
+        DataKind::Bytes => {
+            let offset = a.iter().zip(b).take_while(|(x, y)| x == y).count();
+            format!(
+                "data differs at byte {}, sizes {} and {}",
+                offset,
+                a.len(),
+                b.len()
+            )
+        }
+    })
+}
+
+/// Describes the first difference between two lists of debug-printable
+/// items, like the commands of a submission or of a pass.
+fn compare_items<T: std::fmt::Debug>(what: &str, a: &[T], b: &[T]) -> Option<String> {
+    for (index, (item_a, item_b)) in a.iter().zip(b).enumerate() {
+        let (debug_a, debug_b) = (format!("{:?}", item_a), format!("{:?}", item_b));
+        if debug_a != debug_b {
+            return Some(format!(
+                "{} {}:\n\t\t- {}\n\t\t+ {}",
+                what, index, debug_a, debug_b
+            ));
+        }
+    }
+    if a.len() != b.len() {
+        return Some(format!("{} and {} {}s", a.len(), b.len(), what));
+    }
+    None
+}
+
+/// Describes the first difference between the commands of two submissions.
+///
+/// The commands of the passes, like the draws and the dispatches, are
+/// compared one by one, so that the difference is narrowed down to
+/// a single command instead of the whole pass.
+fn compare_commands(a: &[trace::Command], b: &[trace::Command]) -> Option<String> {
+    use wgc::device::trace::Command as C;
+    for (index, pair) in a.iter().zip(b).enumerate() {
+        let pass_diff = match pair {
+            (C::RunComputePass { base: base_a, .. }, C::RunComputePass { base: base_b, .. }) => {
+                compare_items("compute command", &base_a.commands, &base_b.commands)
+            }
+            (C::RunRenderPass { base: base_a, .. }, C::RunRenderPass { base: base_b, .. }) => {
+                compare_items("render command", &base_a.commands, &base_b.commands)
+            }
+            _ => None,
+        };
+        if let Some(diff) = pass_diff {
+            return Some(format!("command {}, {}", index, diff));
+        }
+    }
+    compare_items("command", a, b)
+}
+
+/// Describes the difference between two actions at the same position.
+fn compare_actions(
+    a: &trace::Action,
+    dir_a: &Path,
+    b: &trace::Action,
+    dir_b: &Path,
+) -> Option<String> {
+    let (name_a, name_b) = (player::action_name(a), player::action_name(b));
+    if name_a != name_b {
+        return Some(format!("{} vs {}", name_a, name_b));
+    }
+    if let (
+        trace::Action::Submit(_, _, _, commands_a),
+        trace::Action::Submit(_, _, _, commands_b),
+    ) = (a, b)
+    {
+        if let Some(diff) = compare_commands(commands_a, commands_b) {
+            return Some(format!("{}: {}", name_a, diff));
+        }
+    }
+    let (files_a, files_b) = (data_files(a), data_files(b));
+    // binary files are named differently between traces
+    let mut debug_a = format!("{:?}", a);
+    for &(file, _) in files_a.iter() {
+        debug_a = debug_a.replace(file, "<data>");
+    }
+    let mut debug_b = format!("{:?}", b);
+    for &(file, _) in files_b.iter() {
+        debug_b = debug_b.replace(file, "<data>");
+    }
+    if debug_a != debug_b {
+        return Some(format!("{}:\n\t\t- {}\n\t\t+ {}", name_a, debug_a, debug_b));
+    }
+    let read = |dir: &Path, file: &str| {
+        player::read_data(&dir.join(file))
+            .unwrap_or_else(|e| panic!("Unable to read '{}' from {:?}: {}", file, dir, e))
+    };
+    for (index, (&(file_a, kind_a), &(file_b, kind_b))) in files_a.iter().zip(&files_b).enumerate()
+    {
+        // only the writes of `WriteBufferMany` have several files
+        let what = if files_a.len() > 1 {
+            format!("{} write {}", name_a, index)
+        } else {
+            name_a.to_string()
+        };
+        if kind_a != kind_b {
+            return Some(format!("{}: {:?} vs {:?} data", what, kind_a, kind_b));
+        }
+        if let Some(diff) = compare_data(kind_a, &read(dir_a, file_a), &read(dir_b, file_b)) {
+            return Some(format!("{}: {}", what, diff));
+        }
+    }
+    None
+}
+
+fn main() {
+    env_logger::init();
+
+    let matches = clap::App::new("diff")
+        .about("Reports the actions that differ between two WebGPU traces")
+        .arg(
+            clap::Arg::with_name("max")
+                .long("max")
+                .value_name("count")
+                .help("Stops after this many differences")
+                .default_value(DEFAULT_MAX_DIFFERENCES),
+        )
+        .arg(
+            clap::Arg::with_name("trace-a")
+                .required(true)
+                .help("Directory of the first trace"),
+        )
+        .arg(
+            clap::Arg::with_name("trace-b")
+                .required(true)
+                .help("Directory of the second trace"),
+        )
+        .get_matches();
+    let max_differences = clap::value_t!(matches, "max", usize).unwrap_or_else(|e| e.exit());
+    let dirs = [
+        PathBuf::from(matches.value_of("trace-a").unwrap()),
+        PathBuf::from(matches.value_of("trace-b").unwrap()),
+    ];
+
+    let mut actions_a = player::TraceReader::open(&dirs[0])
+        .unwrap()
+        .map(Result::unwrap);
+    let mut actions_b = player::TraceReader::open(&dirs[1])
+        .unwrap()
+        .map(Result::unwrap);
+
+    let mut count = 0;
+    let mut index = 0;
+    let (length_a, length_b) = loop {
+        let (a, b) = match (actions_a.next(), actions_b.next()) {
+            (Some(a), Some(b)) => (a, b),
+            // one of the traces ended, only the length of the other is left
+            (a, b) => {
+                break (
+                    index + a.map_or(0, |_| 1 + actions_a.by_ref().count()),
+                    index + b.map_or(0, |_| 1 + actions_b.by_ref().count()),
+                )
+            }
+        };
+        let diff = compare_actions(&a, &dirs[0], &b, &dirs[1]);
+        index += 1;
+        if let Some(diff) = diff {
+            count += 1;
+            println!("#{}: {}", index - 1, diff);
+            if count == max_differences {
+                println!("Stopping after {} differences", count);
+                break (
+                    index + actions_a.by_ref().count(),
+                    index + actions_b.by_ref().count(),
+                );
+            }
+        }
+    };
+    if length_a != length_b {
+        count += 1;
+        println!("Traces have {} and {} actions", length_a, length_b);
+    }
+
+    if count == 0 {
+        println!("Traces are identical");
+    } else {
+        std::process::exit(1);
+    }
+}
@@ -5,14 +5,234 @@
 /*! This is a player for WebGPU traces.
 !*/
 
-use player::{gfx_select, GlobalPlay as _, IdentityPassThroughFactory};
+use player::{
+    gfx_select, ComparisonResult, DryRunPlayer, GlobalPlay as _, IdentityPassThroughFactory,
+    PlaybackState, PlayerConfig, PlayerError, TraceReader,
+};
 use wgc::device::trace;
 
 use std::{
+    collections::BTreeMap,
     fs,
     path::{Path, PathBuf},
+    time::{Duration, Instant},
 };
 
+/// Capture and replay times of the actions, grouped by their kind.
+#[derive(Default)]
+struct Timings {
+    /// Last action replayed, with its recording and replay times, waiting
+    /// for the recording time of the next one.
+    last: Option<(&'static str, u64, Duration)>,
+    per_action: BTreeMap<&'static str, (usize, u64, Duration)>,
+}
+
+impl Timings {
+    /// Records the replay time of an action recorded at `cpu_time_us`,
+    /// which is `None` if the trace was recorded without timing.
+    ///
+    /// The capture time of an action is the time from recording it
+    /// to recording the next one.
+    fn record(&mut self, name: &'static str, cpu_time_us: Option<u64>, replay: Duration) {
+        let cpu_time_us = match cpu_time_us {
+            Some(cpu_time_us) => cpu_time_us,
+            None => return,
+        };
+        if let Some((last, last_cpu_time_us, last_replay)) =
+            self.last.replace((name, cpu_time_us, replay))
+        {
+            self.add(
+                last,
+                cpu_time_us.saturating_sub(last_cpu_time_us),
+                last_replay,
+            );
+        }
+    }
+
+    fn add(&mut self, name: &'static str, capture_us: u64, replay: Duration) {
+        let entry = self
+            .per_action
+            .entry(name)
+            .or_insert((0, 0, Duration::default()));
+        entry.0 += 1;
+        entry.1 += capture_us;
+        entry.2 += replay;
+    }
+
+    fn print(&mut self) {
+        match self.last.take() {
+            Some((name, _, replay)) => self.add(name, 0, replay),
+            None => return,
+        }
+        println!(
+            "{:<24} {:>8} {:>12} {:>12}",
+            "Action", "Count", "Capture ms", "Replay ms"
+        );
+        for (name, &(count, capture_us, replay)) in self.per_action.iter() {
+            println!(
+                "{:<24} {:>8} {:>12.3} {:>12.3}",
+                name,
+                count,
+                capture_us as f64 / 1000.0,
+                replay.as_secs_f64() * 1000.0
+            );
+        }
+    }
+}
+
+/// Actions streamed from the trace, along with their recording times.
+struct Actions {
+    reader: TraceReader<fs::File>,
+    next: Option<(trace::Action, Option<u64>)>,
+}
+
+impl Actions {
+    fn pop(&mut self) -> Option<(trace::Action, Option<u64>)> {
+        if let Some(next) = self.next.take() {
+            return Some(next);
+        }
+        let action = self.reader.next_valid()?;
+        Some((action, self.reader.cpu_time_us()))
+    }
+
+    fn peek(&mut self) -> Option<&trace::Action> {
+        if self.next.is_none() {
+            self.next = self.pop();
+        }
+        self.next.as_ref().map(|&(ref action, _)| action)
+    }
+}
+
+fn report(result: Result<(), Vec<PlayerError>>, keep_going: bool, failures: &mut Vec<PlayerError>) {
+    for e in result.err().unwrap_or_default() {
+        if keep_going {
+            log::error!("{}", e);
+            failures.push(e);
+        } else {
+            panic!("{}", e);
+        }
+    }
+}
+
+fn summarize(config: &PlayerConfig, failures: &[PlayerError]) {
+    if !config.validate_only {
+        return;
+    }
+    if failures.is_empty() {
+        println!("All actions passed validation");
+        return;
+    }
+    println!("{} action(s) failed validation:", failures.len());
+    for e in failures {
+        println!("\t{}", e);
+    }
+    std::process::exit(1);
+}
+
+fn summarize_comparisons(comparisons: &[ComparisonResult]) {
+    if comparisons.is_empty() {
+        return;
+    }
+    let failed = comparisons.iter().filter(|c| !c.passed()).count();
+    println!(
+        "{} of {} frame(s) match the references",
+        comparisons.len() - failed,
+        comparisons.len()
+    );
+    for c in comparisons.iter().filter(|c| !c.passed()) {
+        println!("\t{}", c);
+    }
+    if failed != 0 {
+        std::process::exit(1);
+    }
+}
+
+/// Switches the replay to the device of an `Action::Init`,
+/// creating the device the first time it's seen.
+fn switch_device(
+    global: &wgc::hub::Global<IdentityPassThroughFactory>,
+    state: &mut PlaybackState,
+    mut action: trace::Action,
+    compatible_surface: Option<wgc::id::SurfaceId>,
+) -> wgc::id::DeviceId {
+    if let Some(backend) = state.config.backend {
+        player::substitute_backend(&mut action, backend);
+    }
+    match action {
+        trace::Action::Init {
+            device,
+            desc,
+            backend,
+            adapter_info,
+            adapter_features,
+            adapter_limits,
+        } => {
+            if !state.switch_device(device, backend) {
+                return device;
+            }
+            if let Err(e) = player::check_backend(backend) {
+                panic!("Unable to replay the trace: {}", e);
+            }
+            log::info!(
+                "Initializing device {:?} for backend: {:?}",
+                device,
+                backend
+            );
+            let options = wgc::instance::RequestAdapterOptions {
+                power_preference: wgt::PowerPreference::Default,
+                compatible_surface,
+            };
+            let ids = [wgc::id::TypedId::zip(0, 0, backend)];
+            let inputs = || wgc::instance::AdapterInputs::IdSet(&ids, |id| id.backend());
+            let adapter = global
+                .pick_adapter_filtered(&options, inputs(), |info| {
+                    player::is_recorded_adapter(&adapter_info, info)
+                })
+                .or_else(|| {
+                    log::warn!(
+                        "Recorded adapter '{}' ({:?}, 0x{:X}:0x{:X}) is not available",
+                        adapter_info.name,
+                        adapter_info.device_type,
+                        adapter_info.vendor,
+                        adapter_info.device
+                    );
+                    global.pick_adapter(&options, inputs())
+                })
+                .expect("Unable to find an adapter for selected backend");
+
+            let info = gfx_select!(adapter => global.adapter_get_info(adapter)).unwrap();
+            println!(
+                "Replaying on '{}' ({:?}), recorded on '{}' ({:?})",
+                info.name, info.device_type, adapter_info.name, adapter_info.device_type
+            );
+            let features = gfx_select!(adapter => global.adapter_features(adapter)).unwrap();
+            let limits = gfx_select!(adapter => global.adapter_limits(adapter)).unwrap();
+            if features != adapter_features || limits != adapter_limits {
+                log::info!(
+                    "Trace was recorded on an adapter with {:?} and {:?}",
+                    adapter_features,
+                    adapter_limits
+                );
+            }
+            if let Err(e) = player::check_device_requirements(&desc, features, &limits) {
+                panic!(
+                    "Adapter '{}' is unable to replay the trace: {}",
+                    info.name, e
+                );
+            }
+            gfx_select!(adapter => global.adapter_request_device(
+                adapter,
+                &desc,
+                None,
+                device
+            ))
+            .unwrap()
+            .expect("Failed to request device")
+        }
+        _ => panic!("Expected Action::Init"),
+    }
+}
+
 fn main() {
     #[cfg(feature = "winit")]
     use winit::{event_loop::EventLoop, window::WindowBuilder};
@@ -27,16 +247,113 @@ fn main() {
     //TODO: setting for the backend bits
     //TODO: setting for the target frame, or controls
 
-    let dir = match std::env::args().nth(1) {
-        Some(arg) if Path::new(&arg).is_dir() => PathBuf::from(arg),
-        _ => panic!("Provide the dir path as the parameter"),
-    };
+    let mut config = PlayerConfig::default();
+    let mut dir = None;
+    let mut keep_going = false;
+    let mut dry_run = false;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--headless" => config.use_headless_swapchain = true,
+            "--keep-going" => keep_going = true,
+            "--dry-run" => dry_run = true,
+            "--stats" => config.collect_stats = true,
+            "--leaks" => config.report_leaks = true,
+            "--parallel" => config.parallel_creation = true,
+            "--parallel-encode" => config.parallel_encoding = true,
+            "--capture" => {
+                let capture_dir = args
+                    .next()
+                    .expect("Provide the capture dir after --capture");
+                config.capture_dir = Some(PathBuf::from(capture_dir));
+                config.use_headless_swapchain = true;
+            }
+            "--compare" => {
+                let reference_dir = args
+                    .next()
+                    .expect("Provide the reference dir after --compare");
+                config.reference_dir = Some(PathBuf::from(reference_dir));
+                config.use_headless_swapchain = true;
+            }
+            "--tolerance" => {
+                config.tolerance = args
+                    .next()
+                    .and_then(|value| value.parse().ok())
+                    .expect("Provide a tolerance from 0 to 255 after --tolerance");
+            }
+            "--backend" => {
+                let backend = match args.next().as_deref() {
+                    Some("vulkan") => wgt::Backend::Vulkan,
+                    Some("metal") => wgt::Backend::Metal,
+                    Some("dx12") => wgt::Backend::Dx12,
+                    Some("dx11") => wgt::Backend::Dx11,
+                    _ => panic!("Provide one of vulkan, metal, dx12, or dx11 after --backend"),
+                };
+                config.backend = Some(backend);
+            }
+            "--validate" => {
+                config.validate_only = true;
+                keep_going = true;
+            }
+            _ if Path::new(&arg).is_dir() => dir = Some(PathBuf::from(arg)),
+            _ => panic!("Unknown argument '{}'", arg),
+        }
+    }
+    let dir = dir.expect("Provide the dir path as the parameter");
 
     log::info!("Loading trace '{:?}'", dir);
-    let file = fs::File::open(dir.join(trace::FILE_NAME)).unwrap();
-    let mut actions: Vec<trace::Action> = ron::de::from_reader(file).unwrap();
-    actions.reverse(); // allows us to pop from the top
-    log::info!("Found {} actions", actions.len());
+    let mut reader = TraceReader::open(&dir).unwrap();
+    log::info!(
+        "Trace was recorded by wgpu-core {} on {:?}",
+        reader.header().wgpu_core_git_hash,
+        reader.header().backend
+    );
+    match player::load_metadata(&dir) {
+        Ok(Some(metadata)) => {
+            log::info!(
+                "Trace was captured on {} ({}) at {} seconds since the Unix epoch",
+                metadata.os,
+                metadata.arch,
+                metadata.capture_time
+            );
+            for difference in player::environment_differences(&metadata) {
+                log::warn!("Trace was {}", difference);
+            }
+        }
+        Ok(None) => {}
+        Err(e) => log::warn!("Unable to read the trace metadata: {}", e),
+    }
+    if dry_run {
+        let mut player = DryRunPlayer::new();
+        while let Some(action) = reader.next_valid() {
+            player.process_at_line(&action, reader.line());
+        }
+        let errors = player.finish();
+        if errors.is_empty() {
+            println!("Trace is consistent");
+            return;
+        }
+        println!("{} problem(s) found:", errors.len());
+        for e in errors {
+            println!("\t{}", e);
+        }
+        std::process::exit(1);
+    }
+    // the IDs of the trace are reserved in a first pass over it,
+    // and the actions are streamed from a second one to be replayed
+    let mut state = PlaybackState::new(config, &[]);
+    let mut action_count = 0;
+    while let Some(action) = reader.next_valid() {
+        state.reserve_ids(&action);
+        action_count += 1;
+    }
+    log::info!("Found {} actions", action_count);
+    let mut actions = Actions {
+        reader: TraceReader::open(&dir).unwrap(),
+        next: None,
+    };
+    let mut timings = Timings::default();
+    let mut failures = Vec::new();
 
     #[cfg(feature = "winit")]
     let event_loop = {
@@ -52,56 +369,70 @@ fn main() {
 
     let global =
         wgc::hub::Global::new("player", IdentityPassThroughFactory, wgt::BackendBit::all());
-    let mut command_buffer_id_manager = wgc::hub::IdentityManager::default();
 
     #[cfg(feature = "winit")]
     let surface =
         global.instance_create_surface(&window, wgc::id::TypedId::zip(0, 1, wgt::Backend::Empty));
 
-    let device = match actions.pop() {
-        Some(trace::Action::Init { desc, backend }) => {
-            log::info!("Initializing the device for backend: {:?}", backend);
-            let adapter = global
-                .pick_adapter(
-                    &wgc::instance::RequestAdapterOptions {
-                        power_preference: wgt::PowerPreference::Default,
-                        #[cfg(feature = "winit")]
-                        compatible_surface: Some(surface),
-                        #[cfg(not(feature = "winit"))]
-                        compatible_surface: None,
-                    },
-                    wgc::instance::AdapterInputs::IdSet(
-                        &[wgc::id::TypedId::zip(0, 0, backend)],
-                        |id| id.backend(),
-                    ),
-                )
-                .expect("Unable to find an adapter for selected backend");
-
-            let info = gfx_select!(adapter => global.adapter_get_info(adapter));
-            log::info!("Picked '{}'", info.name);
-            gfx_select!(adapter => global.adapter_request_device(
-                adapter,
-                &desc,
-                None,
-                wgc::id::TypedId::zip(1, 0, wgt::Backend::Empty)
-            ))
-            .expect("Failed to request device")
-        }
-        _ => panic!("Expected Action::Init"),
-    };
+    #[cfg(feature = "winit")]
+    let compatible_surface = Some(surface);
+    #[cfg(not(feature = "winit"))]
+    let compatible_surface = None;
+    let mut device = switch_device(
+        &global,
+        &mut state,
+        actions.pop().unwrap().0,
+        compatible_surface,
+    );
     log::info!("Executing actions");
     #[cfg(not(feature = "winit"))]
     {
         #[cfg(feature = "renderdoc")]
         rd.start_frame_capture(std::ptr::null(), std::ptr::null());
 
-        while let Some(action) = actions.pop() {
-            gfx_select!(device => global.process(device, action, &dir, &mut command_buffer_id_manager));
+        while let Some((action, cpu_time_us)) = actions.pop() {
+            if let trace::Action::Init { .. } = action {
+                device = switch_device(&global, &mut state, action, compatible_surface);
+                continue;
+            }
+            let mut batch = vec![action];
+            let mut cpu_times_us = vec![cpu_time_us];
+            while actions.peek().map_or(false, |next| {
+                player::fits_batch(&state.config, &batch[0], next)
+            }) {
+                let (action, cpu_time_us) = actions.pop().unwrap();
+                batch.push(action);
+                cpu_times_us.push(cpu_time_us);
+            }
+            let names = batch.iter().map(player::action_name).collect::<Vec<_>>();
+            let start = Instant::now();
+            let result =
+                gfx_select!(device => global.process_batch(device, batch, &dir, &mut state))
+                    .unwrap();
+            // actions of a batch are replayed together, so they share its time
+            let elapsed = start.elapsed() / names.len() as u32;
+            for (&name, &cpu_time_us) in names.iter().zip(&cpu_times_us) {
+                timings.record(name, cpu_time_us, elapsed);
+            }
+            report(result, keep_going, &mut failures);
         }
 
         #[cfg(feature = "renderdoc")]
         rd.end_frame_capture(std::ptr::null(), std::ptr::null());
-        gfx_select!(device => global.device_poll(device, true)).unwrap();
+        for (device, _) in state.devices() {
+            gfx_select!(device => global.device_poll(device, true))
+                .unwrap()
+                .unwrap();
+        }
+        timings.print();
+        if let Some(stats) = state.take_stats() {
+            print!("{}", stats);
+        }
+        if let Some(leaks) = state.leak_report() {
+            print!("{}", leaks);
+        }
+        summarize_comparisons(state.comparisons());
+        summarize(&state.config, &failures);
     }
     #[cfg(feature = "winit")]
     {
@@ -119,23 +450,32 @@ fn main() {
                 }
                 Event::RedrawRequested(_) => loop {
                     match actions.pop() {
-                        Some(trace::Action::CreateSwapChain { id, desc }) => {
+                        Some((trace::Action::CreateSwapChain { id, desc }, _)) => {
                             log::info!("Initializing the swapchain");
+                            state.track(&trace::Action::CreateSwapChain { id, desc: desc.clone() });
                             assert_eq!(id.to_surface_id(), surface);
                             window.set_inner_size(winit::dpi::PhysicalSize::new(
                                 desc.width,
                                 desc.height,
                             ));
-                            gfx_select!(device => global.device_create_swap_chain(device, surface, &desc));
+                            let _ = gfx_select!(device => global.device_create_swap_chain(device, surface, &desc)).unwrap();
                         }
-                        Some(trace::Action::PresentSwapChain(id)) => {
+                        Some((trace::Action::PresentSwapChain(id), _)) => {
                             frame_count += 1;
                             log::debug!("Presenting frame {}", frame_count);
-                            gfx_select!(device => global.swap_chain_present(id));
+                            state.track(&trace::Action::PresentSwapChain(id));
+                            let _ = gfx_select!(device => global.swap_chain_present(id)).unwrap();
                             break;
                         }
-                        Some(action) => {
-                            gfx_select!(device => global.process(device, action, &dir, &mut command_buffer_id_manager));
+                        Some((action @ trace::Action::Init { .. }, _)) => {
+                            device = switch_device(&global, &mut state, action, compatible_surface);
+                        }
+                        Some((action, cpu_time_us)) => {
+                            let name = player::action_name(&action);
+                            let start = Instant::now();
+                            let result = gfx_select!(device => global.process(device, action, &dir, &mut state)).unwrap();
+                            timings.record(name, cpu_time_us, start.elapsed());
+                            report(result.map_err(|e| vec![e]), keep_going, &mut failures);
                         }
                         None => break,
                     }
@@ -157,7 +497,19 @@ fn main() {
                 },
                 Event::LoopDestroyed => {
                     log::info!("Closing");
-                    gfx_select!(device => global.device_poll(device, true));
+                    for (device, _) in state.devices() {
+                        gfx_select!(device => global.device_poll(device, true))
+                            .unwrap()
+                            .unwrap();
+                    }
+                    timings.print();
+                    if let Some(stats) = state.take_stats() {
+                        print!("{}", stats);
+                    }
+                    if let Some(leaks) = state.leak_report() {
+                        print!("{}", leaks);
+                    }
+                    summarize(&state.config, &failures);
                 }
                 _ => {}
             }
@@ -0,0 +1,40 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+/*! This is a tool for making a WebGPU trace out of another one.
+ *  It either strips the destruction of resources, keeping all of them alive,
+ *  or moves it right after their last use, and writes the result into a new
+ *  trace that the player can replay.
+!*/
+
+use std::path::{Path, PathBuf};
+
+fn main() {
+    env_logger::init();
+
+    let mut dirs = Vec::new();
+    let mut transform = None;
+    for arg in std::env::args().skip(1) {
+        match arg.as_str() {
+            "--strip-destroys" => transform = Some(player::strip_destroys as fn(_) -> _),
+            "--destroy-early" => transform = Some(player::destroy_after_last_use as fn(_) -> _),
+            _ if dirs.is_empty() && Path::new(&arg).is_dir() => dirs.push(PathBuf::from(arg)),
+            _ if dirs.len() == 1 => dirs.push(PathBuf::from(arg)),
+            _ => panic!("Unknown argument '{}'", arg),
+        }
+    }
+    let transform = transform.expect("Provide either '--strip-destroys' or '--destroy-early'");
+    if dirs.len() != 2 {
+        panic!("Provide the source and the destination dir paths as the parameters");
+    }
+
+    // the transforms look at the whole trace, so all of its actions are read
+    let reader = player::TraceReader::open(&dirs[0]).unwrap();
+    let backend = reader.header().backend;
+    let actions = reader.collect::<Result<Vec<_>, _>>().unwrap();
+    let count = actions.len();
+    let actions = transform(actions);
+    println!("{} actions, {} before", actions.len(), count);
+    player::save_trace(&dirs[1], backend, actions, &dirs[0]).unwrap();
+}
@@ -0,0 +1,120 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+/*! This is a tool for inspecting the contents of a WebGPU trace.
+ *  It prints the summary of `player::inspect_trace`: the counts of actions
+ *  and resources, the sizes of binary data, and the device requirements.
+!*/
+
+use player::TraceSummary;
+
+use std::path::{Path, PathBuf};
+
+fn print(summary: &TraceSummary) {
+    println!("Actions: {}", summary.action_count);
+    for (name, count) in summary.per_action.iter() {
+        println!("\t{:<24} {:>8}", name, count);
+    }
+    println!(
+        "Frames: {}, submissions: {}",
+        summary.frame_count,
+        summary.submit_count()
+    );
+    println!(
+        "Binary data: {} bytes ({} of buffer data, {} of shaders)",
+        summary.buffer_data_bytes + summary.shader_bytes,
+        summary.buffer_data_bytes,
+        summary.shader_bytes
+    );
+    println!("Largest binary files:");
+    for &(ref file, size) in summary.largest_blobs.iter() {
+        println!("\t{:<40} {:>12}", file, size);
+    }
+    println!("Unique shader modules: {}", summary.unique_shaders);
+    println!(
+        "Compute pipelines: {} created, {} destroyed",
+        summary.compute_pipelines.0, summary.compute_pipelines.1
+    );
+    println!(
+        "Render pipelines: {} created, {} destroyed",
+        summary.render_pipelines.0, summary.render_pipelines.1
+    );
+    println!(
+        "Dispatches: {} direct, {} indirect",
+        summary.dispatches.0, summary.dispatches.1
+    );
+    println!("Render bundle executions: {}", summary.bundle_executions);
+    for (i, desc) in summary.devices.iter().enumerate() {
+        println!(
+            "Device {} requires {:?} and {:?}",
+            i, desc.features, desc.limits
+        );
+    }
+}
+
+fn print_json(summary: &TraceSummary) {
+    let largest_blobs = summary
+        .largest_blobs
+        .iter()
+        .map(|&(ref file, size)| serde_json::json!({ "file": file, "bytes": size }))
+        .collect::<Vec<_>>();
+    let devices = summary
+        .devices
+        .iter()
+        .map(|desc| {
+            serde_json::json!({
+                "features": format!("{:?}", desc.features),
+                "limits": format!("{:?}", desc.limits),
+            })
+        })
+        .collect::<Vec<_>>();
+    let json = serde_json::json!({
+        "action_count": summary.action_count,
+        "per_action": summary.per_action,
+        "frame_count": summary.frame_count,
+        "submit_count": summary.submit_count(),
+        "binary_bytes": summary.buffer_data_bytes + summary.shader_bytes,
+        "buffer_data_bytes": summary.buffer_data_bytes,
+        "shader_bytes": summary.shader_bytes,
+        "largest_blobs": largest_blobs,
+        "unique_shader_modules": summary.unique_shaders,
+        "compute_pipelines": {
+            "created": summary.compute_pipelines.0,
+            "destroyed": summary.compute_pipelines.1,
+        },
+        "render_pipelines": {
+            "created": summary.render_pipelines.0,
+            "destroyed": summary.render_pipelines.1,
+        },
+        "dispatches": {
+            "direct": summary.dispatches.0,
+            "indirect": summary.dispatches.1,
+        },
+        "bundle_executions": summary.bundle_executions,
+        "devices": devices,
+    });
+    println!("{}", serde_json::to_string_pretty(&json).unwrap());
+}
+
+fn main() {
+    env_logger::init();
+
+    let mut dir = None;
+    let mut json = false;
+    for arg in std::env::args().skip(1) {
+        match arg.as_str() {
+            "--json" => json = true,
+            _ if Path::new(&arg).is_dir() => dir = Some(PathBuf::from(arg)),
+            _ => panic!("Unknown argument '{}'", arg),
+        }
+    }
+    let dir = dir.expect("Provide the dir path as the parameter");
+
+    let summary = player::inspect_trace(&dir).unwrap();
+    if json {
+        print_json(&summary);
+    } else {
+        print(&summary);
+    }
+}
@@ -10,35 +10,64 @@
  *   so that we don't accidentally try to use the same ID.
 !*/
 
+use rayon::iter::{IntoParallelIterator as _, ParallelIterator as _};
 use wgc::device::trace;
 
-use std::{ffi::CString, fmt::Debug, fs, marker::PhantomData, path::Path, ptr};
+use wgc::id::TypedId as _;
 
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    error::Error,
+    ffi::CString,
+    fmt::{self, Debug},
+    fs, io,
+    marker::PhantomData,
+    ops::Range,
+    path::{Path, PathBuf},
+    ptr, slice,
+    time::{Duration, Instant},
+};
+
+// Used by `gfx_select!`, so that it works in crates that don't depend
+// on `wgpu-core` and `wgpu-types` under these names.
+#[doc(hidden)]
+pub use wgc;
+#[doc(hidden)]
+pub use wgt;
+
+/// Re-exports of the items needed to replay a trace.
+///
+/// Only the items that are expected to stay as they are belong here.
+pub mod prelude {
+    pub use crate::{gfx_select, GlobalPlay, IdentityPassThrough, IdentityPassThroughFactory};
+}
+
+/// Calls the method of `$global` that is generic over the backend,
+/// with the backend of `$id`.
+///
+/// Evaluates to `Ok` with the result of the call, or to
+/// `Err(UnsupportedBackend)` if `check_backend` rejects the backend.
 #[macro_export]
 macro_rules! gfx_select {
     ($id:expr => $global:ident.$method:ident( $($param:expr),+ )) => {
         match $id.backend() {
-            #[cfg(not(any(target_os = "ios", target_os = "macos")))]
-            wgt::Backend::Vulkan => $global.$method::<wgc::backend::Vulkan>( $($param),+ ),
+            #[cfg(not(any(target_os = "ios", target_os = "macos", target_arch = "wasm32")))]
+            $crate::wgt::Backend::Vulkan => Ok($global.$method::<$crate::wgc::backend::Vulkan>( $($param),+ )),
             #[cfg(any(target_os = "ios", target_os = "macos"))]
-            wgt::Backend::Metal => $global.$method::<wgc::backend::Metal>( $($param),+ ),
+            $crate::wgt::Backend::Metal => Ok($global.$method::<$crate::wgc::backend::Metal>( $($param),+ )),
             #[cfg(windows)]
-            wgt::Backend::Dx12 => $global.$method::<wgc::backend::Dx12>( $($param),+ ),
+            $crate::wgt::Backend::Dx12 => Ok($global.$method::<$crate::wgc::backend::Dx12>( $($param),+ )),
             #[cfg(windows)]
-            wgt::Backend::Dx11 => $global.$method::<wgc::backend::Dx11>( $($param),+ ),
-            _ => unreachable!()
+            $crate::wgt::Backend::Dx11 => Ok($global.$method::<$crate::wgc::backend::Dx11>( $($param),+ )),
+            other => Err($crate::UnsupportedBackend { backend: other }),
         }
     };
 }
 
 struct Label(Option<CString>);
 impl Label {
-    fn new(text: &str) -> Self {
-        Self(if text.is_empty() {
-            None
-        } else {
-            Some(CString::new(text).expect("invalid label"))
-        })
+    fn new(text: Option<&str>) -> Self {
+        Self(text.map(|text| CString::new(text).expect("invalid label")))
     }
 
     fn as_ptr(&self) -> *const std::os::raw::c_char {
@@ -55,14 +84,29 @@ pub struct IdentityPassThrough<I>(PhantomData<I>);
 impl<I: Clone + Debug + wgc::id::TypedId> wgc::hub::IdentityHandler<I> for IdentityPassThrough<I> {
     type Input = I;
     fn process(&self, id: I, backend: wgt::Backend) -> I {
-        let (index, epoch, _backend) = id.unzip();
-        I::zip(index, epoch, backend)
+        rezip(id, backend)
     }
+    // The IDs come from the trace, and their indices were recycled by the
+    // identity manager of the application that recorded it, so there is
+    // nothing to reclaim here. See `PlaybackState::index_high_water_mark`.
     fn free(&self, _id: I) {}
 }
 
 pub struct IdentityPassThroughFactory;
 
+extern "C" fn map_callback(status: wgc::resource::BufferMapAsyncStatus, _user_data: *mut u8) {
+    match status {
+        wgc::resource::BufferMapAsyncStatus::Success => (),
+        _ => log::error!("Unable to map a buffer: {:?}", status),
+    }
+}
+
+/// Stores the status of the mapping into the `BufferMapAsyncStatus`
+/// that `user_data` points to.
+extern "C" fn map_status_callback(status: wgc::resource::BufferMapAsyncStatus, user_data: *mut u8) {
+    unsafe { *(user_data as *mut wgc::resource::BufferMapAsyncStatus) = status };
+}
+
 impl<I: Clone + Debug + wgc::id::TypedId> wgc::hub::IdentityHandlerFactory<I>
     for IdentityPassThroughFactory
 {
@@ -73,367 +117,4935 @@ impl<I: Clone + Debug + wgc::id::TypedId> wgc::hub::IdentityHandlerFactory<I>
 }
 impl wgc::hub::GlobalIdentityHandlerFactory for IdentityPassThroughFactory {}
 
-pub trait GlobalPlay {
-    fn encode_commands<B: wgc::hub::GfxBackend>(
-        &self,
-        encoder: wgc::id::CommandEncoderId,
-        commands: Vec<trace::Command>,
-    ) -> wgc::id::CommandBufferId;
-    fn process<B: wgc::hub::GfxBackend>(
-        &self,
-        device: wgc::id::DeviceId,
-        action: trace::Action,
-        dir: &Path,
-        comb_manager: &mut wgc::hub::IdentityManager,
-    );
+/// Returns the `id` with its backend replaced by `backend`.
+///
+/// The core only looks at the index and the epoch of the IDs it's given,
+/// and the IDs it creates are rezipped by `IdentityPassThrough`, so the
+/// IDs of a trace can be replayed on another backend than the recorded one
+/// as long as the IDs that pick the backend, the ones of the devices, are
+/// rezipped consistently. See `PlayerConfig::backend`.
+pub fn rezip<I: wgc::id::TypedId>(id: I, backend: wgt::Backend) -> I {
+    let (index, epoch, _backend) = id.unzip();
+    I::zip(index, epoch, backend)
 }
 
-impl GlobalPlay for wgc::hub::Global<IdentityPassThroughFactory> {
-    fn encode_commands<B: wgc::hub::GfxBackend>(
-        &self,
-        encoder: wgc::id::CommandEncoderId,
-        commands: Vec<trace::Command>,
-    ) -> wgc::id::CommandBufferId {
-        for command in commands {
-            match command {
-                trace::Command::CopyBufferToBuffer {
-                    src,
-                    src_offset,
-                    dst,
-                    dst_offset,
-                    size,
-                } => self
-                    .command_encoder_copy_buffer_to_buffer::<B>(
-                        encoder, src, src_offset, dst, dst_offset, size,
-                    )
-                    .unwrap(),
-                trace::Command::CopyBufferToTexture { src, dst, size } => self
-                    .command_encoder_copy_buffer_to_texture::<B>(encoder, &src, &dst, &size)
-                    .unwrap(),
-                trace::Command::CopyTextureToBuffer { src, dst, size } => self
-                    .command_encoder_copy_texture_to_buffer::<B>(encoder, &src, &dst, &size)
-                    .unwrap(),
-                trace::Command::CopyTextureToTexture { src, dst, size } => self
-                    .command_encoder_copy_texture_to_texture::<B>(encoder, &src, &dst, &size)
-                    .unwrap(),
-                trace::Command::RunComputePass { base } => {
-                    self.command_encoder_run_compute_pass_impl::<B>(encoder, base.as_ref())
-                        .unwrap();
-                }
-                trace::Command::RunRenderPass {
-                    base,
-                    target_colors,
-                    target_depth_stencil,
-                } => {
-                    self.command_encoder_run_render_pass_impl::<B>(
-                        encoder,
-                        base.as_ref(),
-                        &target_colors,
-                        target_depth_stencil.as_ref(),
-                    )
-                    .unwrap();
-                }
-            }
+/// Backend that the player can't replay on, because `wgpu-core` doesn't
+/// provide it on this platform. There is no GL backend in `wgpu-core` yet,
+/// and the browser implements WebGPU itself.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct UnsupportedBackend {
+    pub backend: wgt::Backend,
+}
+
+impl fmt::Display for UnsupportedBackend {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "backend {:?} is not supported by the player",
+            self.backend
+        )
+    }
+}
+
+impl Error for UnsupportedBackend {}
+
+/// Checks that `gfx_select!` can dispatch to `backend` on this platform.
+pub fn check_backend(backend: wgt::Backend) -> Result<(), UnsupportedBackend> {
+    match backend {
+        #[cfg(not(any(target_os = "ios", target_os = "macos", target_arch = "wasm32")))]
+        wgt::Backend::Vulkan => Ok(()),
+        #[cfg(any(target_os = "ios", target_os = "macos"))]
+        wgt::Backend::Metal => Ok(()),
+        #[cfg(windows)]
+        wgt::Backend::Dx12 | wgt::Backend::Dx11 => Ok(()),
+        other => Err(UnsupportedBackend { backend: other }),
+    }
+}
+
+/// Makes an `Action::Init` create its device on `backend` instead of
+/// the recorded one. Other actions are left as they are.
+pub fn substitute_backend(action: &mut trace::Action, backend: wgt::Backend) {
+    if let trace::Action::Init {
+        ref mut device,
+        backend: ref mut recorded,
+        ..
+    } = *action
+    {
+        *device = rezip(*device, backend);
+        *recorded = backend;
+    }
+}
+
+/// Returns true if `info` describes the same physical adapter as the one
+/// recorded by `Action::Init`, which may be reached through another backend.
+pub fn is_recorded_adapter(
+    recorded: &wgc::instance::AdapterInfo,
+    info: &wgc::instance::AdapterInfo,
+) -> bool {
+    info.vendor == recorded.vendor
+        && info.device == recorded.device
+        && info.device_type == recorded.device_type
+        && info.name == recorded.name
+}
+
+/// Checks that an adapter with the given `features` and `limits` is able to
+/// create the device recorded by `Action::Init`.
+///
+/// Returns a description of everything that is missing otherwise.
+pub fn check_device_requirements(
+    desc: &wgt::DeviceDescriptor,
+    features: wgt::Features,
+    limits: &wgt::Limits,
+) -> Result<(), String> {
+    let mut missing = Vec::new();
+    if !features.contains(desc.features) {
+        missing.push(format!("features {:?}", desc.features - features));
+    }
+    let checks = [
+        (
+            "max_bind_groups",
+            limits.max_bind_groups,
+            desc.limits.max_bind_groups,
+        ),
+        (
+            "max_dynamic_uniform_buffers_per_pipeline_layout",
+            limits.max_dynamic_uniform_buffers_per_pipeline_layout,
+            desc.limits.max_dynamic_uniform_buffers_per_pipeline_layout,
+        ),
+        (
+            "max_dynamic_storage_buffers_per_pipeline_layout",
+            limits.max_dynamic_storage_buffers_per_pipeline_layout,
+            desc.limits.max_dynamic_storage_buffers_per_pipeline_layout,
+        ),
+        (
+            "max_sampled_textures_per_shader_stage",
+            limits.max_sampled_textures_per_shader_stage,
+            desc.limits.max_sampled_textures_per_shader_stage,
+        ),
+        (
+            "max_samplers_per_shader_stage",
+            limits.max_samplers_per_shader_stage,
+            desc.limits.max_samplers_per_shader_stage,
+        ),
+        (
+            "max_storage_buffers_per_shader_stage",
+            limits.max_storage_buffers_per_shader_stage,
+            desc.limits.max_storage_buffers_per_shader_stage,
+        ),
+        (
+            "max_storage_textures_per_shader_stage",
+            limits.max_storage_textures_per_shader_stage,
+            desc.limits.max_storage_textures_per_shader_stage,
+        ),
+        (
+            "max_uniform_buffers_per_shader_stage",
+            limits.max_uniform_buffers_per_shader_stage,
+            desc.limits.max_uniform_buffers_per_shader_stage,
+        ),
+        (
+            "max_uniform_buffer_binding_size",
+            limits.max_uniform_buffer_binding_size,
+            desc.limits.max_uniform_buffer_binding_size,
+        ),
+        (
+            "max_push_constant_size",
+            limits.max_push_constant_size,
+            desc.limits.max_push_constant_size,
+        ),
+    ];
+    for &(name, supported, required) in checks.iter() {
+        if supported < required {
+            missing.push(format!("{} = {} (supported {})", name, required, supported));
         }
-        self.command_encoder_finish::<B>(encoder, &wgt::CommandBufferDescriptor { todo: 0 })
-            .unwrap()
     }
+    if missing.is_empty() {
+        Ok(())
+    } else {
+        Err(format!("missing {}", missing.join(", ")))
+    }
+}
 
-    fn process<B: wgc::hub::GfxBackend>(
-        &self,
-        device: wgc::id::DeviceId,
-        action: trace::Action,
-        dir: &Path,
-        comb_manager: &mut wgc::hub::IdentityManager,
-    ) {
-        use wgc::device::trace::Action as A;
-        match action {
-            A::Init { .. } => panic!("Unexpected Action::Init: has to be the first action only"),
-            A::CreateSwapChain { .. } | A::PresentSwapChain(_) => {
-                panic!("Unexpected SwapChain action: winit feature is not enabled")
+fn check_header(header: &trace::TraceHeader) -> Result<(), String> {
+    if header.version == trace::FORMAT_VERSION {
+        Ok(())
+    } else {
+        Err(format!(
+            "trace format version {} (wgpu-core {}) is not supported, \
+             the player expects version {}",
+            header.version,
+            header.wgpu_core_git_hash,
+            trace::FORMAT_VERSION
+        ))
+    }
+}
+
+/// Actions of a trace, along with their recording times if those are known.
+#[derive(Debug)]
+pub struct LoadedTrace {
+    pub header: trace::TraceHeader,
+    pub actions: Vec<trace::Action>,
+    /// Recording time of each action, in microseconds since the start of
+    /// the trace.
+    pub cpu_times_us: Option<Vec<u64>>,
+}
+
+impl LoadedTrace {
+    fn new(header: trace::TraceHeader) -> Self {
+        LoadedTrace {
+            cpu_times_us: if header.timed { Some(Vec::new()) } else { None },
+            header,
+            actions: Vec::new(),
+        }
+    }
+
+    fn push(&mut self, action: trace::Action, cpu_time_us: Option<u64>) {
+        self.actions.push(action);
+        if let (Some(cpu_times_us), Some(cpu_time_us)) = (self.cpu_times_us.as_mut(), cpu_time_us) {
+            cpu_times_us.push(cpu_time_us);
+        }
+    }
+}
+
+/// Contents of a RON trace file: the header followed by the entries,
+/// which are parsed as `TimedAction`s or `Action`s depending on the header.
+struct TraceFile(LoadedTrace);
+
+impl<'de> serde::Deserialize<'de> for TraceFile {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct Visitor;
+
+        impl<'de> serde::de::Visitor<'de> for Visitor {
+            type Value = TraceFile;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a trace header followed by the actions")
             }
-            A::CreateBuffer { id, desc } => {
-                let label = Label::new(&desc.label);
-                self.device_maintain_ids::<B>(device);
-                self.device_create_buffer::<B>(device, &desc.map_label(|_| label.as_ptr()), id);
+
+            fn visit_seq<A: serde::de::SeqAccess<'de>>(
+                self,
+                mut seq: A,
+            ) -> Result<Self::Value, A::Error> {
+                // the header has to be checked before the actions are parsed,
+                // since their format depends on the version
+                let header = seq
+                    .next_element::<trace::TraceHeader>()
+                    .map_err(|e| {
+                        serde::de::Error::custom(format!(
+                            "unable to read the trace header, the trace may be too old: {}",
+                            e
+                        ))
+                    })?
+                    .ok_or_else(|| serde::de::Error::custom("the trace is empty"))?;
+                check_header(&header).map_err(serde::de::Error::custom)?;
+                let mut loaded = LoadedTrace::new(header);
+                if loaded.header.timed {
+                    while let Some(entry) = seq.next_element::<trace::TimedAction>()? {
+                        loaded.push(entry.action, Some(entry.cpu_time_us));
+                    }
+                } else {
+                    while let Some(action) = seq.next_element()? {
+                        loaded.push(action, None);
+                    }
+                }
+                Ok(TraceFile(loaded))
             }
-            A::DestroyBuffer(id) => {
-                self.buffer_destroy::<B>(id);
+        }
+
+        deserializer.deserialize_seq(Visitor)
+    }
+}
+
+/// Reads the trace from `dir`, with or without the timing information.
+///
+/// The format is picked by the trace file present: `trace.bin` is read
+/// as bincode, and `trace.ron` otherwise.
+pub fn load_trace(dir: &Path) -> Result<LoadedTrace, Box<dyn Error>> {
+    let binary_path = dir.join(trace::BINARY_FILE_NAME);
+    if binary_path.exists() {
+        return load_bincode_trace(&fs::read(binary_path)?);
+    }
+    let mut string = fs::read_to_string(dir.join(trace::FILE_NAME))?;
+    // the trace of an application that didn't exit cleanly
+    // misses the closing bracket, so it ends with the file
+    if !string.trim_end().ends_with(']') {
+        string.push(']');
+    }
+    Ok(ron::de::from_str::<TraceFile>(&string)?.0)
+}
+
+/// Reads the `TraceMetadata` written next to the trace in `dir`,
+/// or returns `None` if the trace was recorded without it.
+pub fn load_metadata(dir: &Path) -> Result<Option<trace::TraceMetadata>, Box<dyn Error>> {
+    let path = dir.join(trace::METADATA_FILE_NAME);
+    if !path.exists() {
+        return Ok(None);
+    }
+    Ok(Some(serde_json::from_str(&fs::read_to_string(path)?)?))
+}
+
+/// Describes how the environment the trace was captured in differs from
+/// the one replaying it. The adapters are compared when creating devices.
+pub fn environment_differences(metadata: &trace::TraceMetadata) -> Vec<String> {
+    let mut differences = Vec::new();
+    if metadata.os != std::env::consts::OS {
+        differences.push(format!(
+            "captured on {}, replayed on {}",
+            metadata.os,
+            std::env::consts::OS
+        ));
+    }
+    if metadata.arch != std::env::consts::ARCH {
+        differences.push(format!(
+            "captured on architecture {}, replayed on {}",
+            metadata.arch,
+            std::env::consts::ARCH
+        ));
+    }
+    differences
+}
+
+fn load_bincode_trace(data: &[u8]) -> Result<LoadedTrace, Box<dyn Error>> {
+    let mut entries = Vec::new();
+    let mut rest = data;
+    while !rest.is_empty() {
+        if rest.len() < 8 {
+            return Err("truncated entry size in the binary trace".into());
+        }
+        let mut size = [0; 8];
+        size.copy_from_slice(&rest[..8]);
+        let size = u64::from_le_bytes(size) as usize;
+        if rest.len() - 8 < size {
+            return Err("truncated entry in the binary trace".into());
+        }
+        entries.push(&rest[8..8 + size]);
+        rest = &rest[8 + size..];
+    }
+    let (&header, entries) = entries.split_first().ok_or("the trace is empty")?;
+    let header = bincode::deserialize::<trace::TraceHeader>(header).map_err(|e| {
+        format!(
+            "unable to read the trace header, the trace may be too old: {}",
+            e
+        )
+    })?;
+    check_header(&header)?;
+
+    let mut loaded = LoadedTrace::new(header);
+    for entry in entries {
+        if loaded.header.timed {
+            let timed = bincode::deserialize::<trace::TimedAction>(entry)?;
+            loaded.push(timed.action, Some(timed.cpu_time_us));
+        } else {
+            loaded.push(bincode::deserialize(entry)?, None);
+        }
+    }
+    Ok(loaded)
+}
+
+/// Reads the actions of a trace one at a time, so that the whole trace
+/// never has to be in memory, unlike with `load_trace`.
+///
+/// Each entry is split off the input on its own, and parsed when it's
+/// reached by the iterator, with ron's `Deserializer` or with bincode.
+/// The entries of a timed trace are read as their actions, see `cpu_time_us`.
+pub struct TraceReader<R: io::Read> {
+    inner: io::BufReader<R>,
+    binary: bool,
+    header: trace::TraceHeader,
+    cpu_time_us: Option<u64>,
+    /// Number of entries read after the header.
+    entry_count: usize,
+    /// Line of a RON trace that the input is at, and the one where
+    /// the last entry read starts.
+    line: usize,
+    entry_line: Option<usize>,
+}
+
+impl TraceReader<fs::File> {
+    /// Opens the trace in `dir`, picking the format like `load_trace` does.
+    pub fn open(dir: &Path) -> Result<Self, Box<dyn Error>> {
+        let binary_path = dir.join(trace::BINARY_FILE_NAME);
+        if binary_path.exists() {
+            Self::new_binary(fs::File::open(binary_path)?)
+        } else {
+            Self::new(fs::File::open(dir.join(trace::FILE_NAME))?)
+        }
+    }
+}
+
+impl<R: io::Read> TraceReader<R> {
+    /// Reads the header of a RON trace, leaving the actions for the iterator.
+    pub fn new(reader: R) -> Result<Self, Box<dyn Error>> {
+        use io::Read as _;
+
+        let mut inner = io::BufReader::new(reader);
+        let mut line = 1;
+        loop {
+            match (&mut inner).bytes().next().transpose()? {
+                Some(b'[') => break,
+                Some(b'\n') => line += 1,
+                Some(byte) if byte.is_ascii_whitespace() => {}
+                _ => return Err("the trace doesn't start with '['".into()),
             }
-            A::CreateTexture { id, desc } => {
-                let label = Label::new(&desc.label);
-                self.device_maintain_ids::<B>(device);
-                self.device_create_texture::<B>(device, &desc.map_label(|_| label.as_ptr()), id);
+        }
+        let entry = read_ron_entry(&mut (&mut inner).bytes())?.ok_or("the trace is empty")?;
+        line += count_lines(&entry);
+        let header = parse_ron_entry::<trace::TraceHeader>(&entry).map_err(|e| {
+            format!(
+                "unable to read the trace header, the trace may be too old: {}",
+                e
+            )
+        })?;
+        check_header(&header)?;
+        Ok(TraceReader {
+            inner,
+            binary: false,
+            header,
+            cpu_time_us: None,
+            entry_count: 0,
+            line,
+            entry_line: None,
+        })
+    }
+
+    /// Reads the header of a binary trace, leaving the actions for the iterator.
+    pub fn new_binary(reader: R) -> Result<Self, Box<dyn Error>> {
+        let mut inner = io::BufReader::new(reader);
+        let entry = read_binary_entry(&mut inner)?.ok_or("the trace is empty")?;
+        let header = bincode::deserialize::<trace::TraceHeader>(&entry).map_err(|e| {
+            format!(
+                "unable to read the trace header, the trace may be too old: {}",
+                e
+            )
+        })?;
+        check_header(&header)?;
+        Ok(TraceReader {
+            inner,
+            binary: true,
+            header,
+            cpu_time_us: None,
+            entry_count: 0,
+            line: 0,
+            entry_line: None,
+        })
+    }
+
+    pub fn header(&self) -> &trace::TraceHeader {
+        &self.header
+    }
+
+    /// Recording time of the last action read, in microseconds since the
+    /// start of the trace, or `None` if it was recorded without timing.
+    pub fn cpu_time_us(&self) -> Option<u64> {
+        self.cpu_time_us
+    }
+
+    /// Line of the RON trace where the last action read starts, or `None`
+    /// if the trace is binary.
+    pub fn line(&self) -> Option<usize> {
+        self.entry_line
+    }
+
+    /// Reads the next action that can be parsed, skipping the other ones
+    /// with a warning, like the actions that a newer `wgpu-core` added to
+    /// the same version of the format. Reading stops at an I/O error.
+    pub fn next_valid(&mut self) -> Option<trace::Action> {
+        loop {
+            match self.next()? {
+                Ok(action) => return Some(action),
+                Err(e) if e.is::<io::Error>() => {
+                    log::error!("Unable to read the trace: {}", e);
+                    return None;
+                }
+                Err(e) => log::warn!("Skipping the action #{}: {}", self.entry_count - 1, e),
             }
-            A::DestroyTexture(id) => {
-                self.texture_destroy::<B>(id);
+        }
+    }
+
+    /// Iterates over the actions returned by `next_valid`.
+    pub fn skip_invalid(mut self) -> impl Iterator<Item = trace::Action> {
+        std::iter::from_fn(move || self.next_valid())
+    }
+
+    fn parse_entry(&self, entry: &[u8]) -> Result<(trace::Action, Option<u64>), Box<dyn Error>> {
+        match (self.binary, self.header.timed) {
+            (true, true) => {
+                let timed = bincode::deserialize::<trace::TimedAction>(entry)?;
+                Ok((timed.action, Some(timed.cpu_time_us)))
             }
-            A::CreateTextureView {
-                id,
-                parent_id,
-                desc,
-            } => {
-                let label = desc.as_ref().map_or(Label(None), |d| Label::new(&d.label));
-                self.device_maintain_ids::<B>(device);
-                self.texture_create_view::<B>(
-                    parent_id,
-                    desc.map(|d| d.map_label(|_| label.as_ptr())).as_ref(),
-                    id,
-                );
+            (true, false) => Ok((bincode::deserialize(entry)?, None)),
+            (false, true) => {
+                let timed = parse_ron_entry::<trace::TimedAction>(entry)?;
+                Ok((timed.action, Some(timed.cpu_time_us)))
             }
-            A::DestroyTextureView(id) => {
-                self.texture_view_destroy::<B>(id);
+            (false, false) => Ok((parse_ron_entry(entry)?, None)),
+        }
+    }
+}
+
+impl<R: io::Read> Iterator for TraceReader<R> {
+    type Item = Result<trace::Action, Box<dyn Error>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        use io::Read as _;
+
+        let entry = if self.binary {
+            read_binary_entry(&mut self.inner)
+        } else {
+            read_ron_entry(&mut (&mut self.inner).bytes())
+        };
+        let entry = match entry {
+            Ok(entry) => entry?,
+            Err(e) => return Some(Err(Box::new(e))),
+        };
+        self.entry_count += 1;
+        if !self.binary {
+            let leading = entry
+                .iter()
+                .take_while(|byte| byte.is_ascii_whitespace())
+                .filter(|&&byte| byte == b'\n')
+                .count();
+            self.entry_line = Some(self.line + leading);
+            self.line += count_lines(&entry);
+        }
+        Some(self.parse_entry(&entry).map(|(action, cpu_time_us)| {
+            self.cpu_time_us = cpu_time_us;
+            action
+        }))
+    }
+}
+
+fn count_lines(text: &[u8]) -> usize {
+    text.iter().filter(|&&byte| byte == b'\n').count()
+}
+
+/// Parses a single value with ron's `Deserializer`, which has to be followed
+/// by nothing but whitespace and comments.
+fn parse_ron_entry<T: serde::de::DeserializeOwned>(entry: &[u8]) -> Result<T, ron::de::Error> {
+    let mut deserializer = ron::de::Deserializer::from_bytes(entry)?;
+    let value = T::deserialize(&mut deserializer)?;
+    deserializer.end()?;
+    Ok(value)
+}
+
+/// Reads the next size-prefixed entry of a binary trace, or returns `None`
+/// at the end of the input.
+fn read_binary_entry<R: io::Read>(reader: &mut R) -> io::Result<Option<Vec<u8>>> {
+    use io::Read as _;
+
+    let mut size = [0; 8];
+    let mut filled = 0;
+    while filled < size.len() {
+        match reader.read(&mut size[filled..]) {
+            Ok(0) if filled == 0 => return Ok(None),
+            Ok(0) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "truncated entry size in the binary trace",
+                ))
             }
-            A::CreateSampler { id, desc } => {
-                let label = Label::new(&desc.label);
-                self.device_maintain_ids::<B>(device);
-                self.device_create_sampler::<B>(device, &desc.map_label(|_| label.as_ptr()), id);
+            Ok(count) => filled += count,
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    let size = u64::from_le_bytes(size);
+    let mut entry = Vec::new();
+    reader.by_ref().take(size).read_to_end(&mut entry)?;
+    if (entry.len() as u64) < size {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "truncated entry in the binary trace",
+        ));
+    }
+    Ok(Some(entry))
+}
+
+/// Lexical state of `RonScanner`.
+#[derive(Clone, Copy, Debug)]
+enum RonToken {
+    Code,
+    /// After a `/` that may start a comment.
+    Slash,
+    LineComment,
+    BlockComment {
+        star: bool,
+    },
+    String {
+        escaped: bool,
+    },
+    Char {
+        escaped: bool,
+    },
+    /// After the `r` and the `#`s that may start a raw string.
+    RawStringStart {
+        hashes: usize,
+    },
+    RawString {
+        hashes: usize,
+    },
+    /// After the `"` and the `#`s that may end a raw string.
+    RawStringEnd {
+        hashes: usize,
+        seen: usize,
+    },
+}
+
+/// Finds where an entry of a RON list ends, telling the brackets and commas
+/// of the list apart from the ones in strings, chars and comments.
+///
+/// Parsing is left to ron, which doesn't tell where a value it parsed ends.
+struct RonScanner {
+    token: RonToken,
+    depth: usize,
+    /// Whether the last byte of code was part of an identifier or number.
+    in_word: bool,
+    /// Whether anything but whitespace and comments was seen.
+    has_value: bool,
+}
+
+impl RonScanner {
+    fn new() -> Self {
+        RonScanner {
+            token: RonToken::Code,
+            depth: 0,
+            in_word: false,
+            has_value: false,
+        }
+    }
+
+    /// Feeds the next `byte` of the input, returning true if it's
+    /// the comma or the bracket ending the entry.
+    fn feed(&mut self, byte: u8) -> bool {
+        use RonToken as T;
+        self.token = match self.token {
+            T::Code => return self.code(byte),
+            T::Slash => match byte {
+                b'/' => T::LineComment,
+                b'*' => T::BlockComment { star: false },
+                _ => return self.code(byte),
+            },
+            T::LineComment if byte == b'\n' => T::Code,
+            T::LineComment => T::LineComment,
+            T::BlockComment { star: true } if byte == b'/' => T::Code,
+            T::BlockComment { .. } => T::BlockComment { star: byte == b'*' },
+            T::String { escaped: false } if byte == b'"' => T::Code,
+            T::String { escaped } => T::String {
+                escaped: !escaped && byte == b'\\',
+            },
+            T::Char { escaped: false } if byte == b'\'' => T::Code,
+            T::Char { escaped } => T::Char {
+                escaped: !escaped && byte == b'\\',
+            },
+            T::RawStringStart { hashes } => match byte {
+                b'#' => T::RawStringStart { hashes: hashes + 1 },
+                b'"' => T::RawString { hashes },
+                // the `r` was an identifier, like the red channel of a color
+                _ => return self.code(byte),
+            },
+            T::RawString { hashes: 0 } if byte == b'"' => T::Code,
+            T::RawString { hashes } if byte == b'"' => T::RawStringEnd { hashes, seen: 0 },
+            T::RawString { hashes } => T::RawString { hashes },
+            T::RawStringEnd { hashes, seen } => match byte {
+                b'#' if seen + 1 == hashes => T::Code,
+                b'#' => T::RawStringEnd {
+                    hashes,
+                    seen: seen + 1,
+                },
+                b'"' => T::RawStringEnd { hashes, seen: 0 },
+                _ => T::RawString { hashes },
+            },
+        };
+        false
+    }
+
+    fn code(&mut self, byte: u8) -> bool {
+        let in_word = self.in_word;
+        self.in_word = byte.is_ascii_alphanumeric() || byte == b'_';
+        self.token = match byte {
+            b'r' if !in_word => RonToken::RawStringStart { hashes: 0 },
+            b'"' => RonToken::String { escaped: false },
+            b'\'' => RonToken::Char { escaped: false },
+            b'/' => RonToken::Slash,
+            _ => RonToken::Code,
+        };
+        match byte {
+            b'(' | b'[' | b'{' => self.depth += 1,
+            b')' | b'}' => self.depth = self.depth.saturating_sub(1),
+            b']' | b',' if self.depth == 0 => return true,
+            b']' => self.depth -= 1,
+            _ => {}
+        }
+        if byte != b'/' && !byte.is_ascii_whitespace() {
+            self.has_value = true;
+        }
+        false
+    }
+}
+
+/// Reads the text of the next entry of a RON list, without the comma
+/// after it, or returns `None` at the end of the list.
+fn read_ron_entry<I: Iterator<Item = io::Result<u8>>>(
+    bytes: &mut I,
+) -> io::Result<Option<Vec<u8>>> {
+    let mut entry = Vec::new();
+    let mut scanner = RonScanner::new();
+    // the trace of an application that didn't exit cleanly
+    // misses the closing bracket, so it ends with the input
+    while let Some(byte) = bytes.next().transpose()? {
+        if scanner.feed(byte) {
+            break;
+        }
+        entry.push(byte);
+    }
+    if scanner.has_value {
+        Ok(Some(entry))
+    } else {
+        Ok(None)
+    }
+}
+
+/// Reads a binary file of the trace, decompressing it if the name ends
+/// with `.gz`, and verifying the CRC-32 if the name contains one.
+pub fn read_data(path: &Path) -> std::io::Result<Vec<u8>> {
+    let data = trace::read_binary(path)?;
+    if let Some(expected) = data_crc(path) {
+        let actual = crc32fast::hash(&data);
+        if actual != expected {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!(
+                    "{:?} is corrupted: CRC-32 is {:08X}, expected {:08X}",
+                    path, actual, expected
+                ),
+            ));
+        }
+    }
+    Ok(data)
+}
+
+/// Binary file of the trace holding less data than the action using it
+/// needs, which happens if the capturing process crashed while writing it.
+#[derive(Clone, Debug)]
+pub struct BlobTooSmall {
+    pub file: String,
+    pub expected: wgt::BufferAddress,
+    pub got: usize,
+}
+
+impl fmt::Display for BlobTooSmall {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{:?} has {} bytes, but {} are needed",
+            self.file, self.got, self.expected
+        )
+    }
+}
+
+impl Error for BlobTooSmall {}
+
+/// Returns the part of the data read from `file` that is written to
+/// the `range` of a buffer.
+fn blob_range<'a>(
+    file: &str,
+    data: &'a [u8],
+    range: &Range<wgt::BufferAddress>,
+) -> Result<&'a [u8], ActionError> {
+    if range.end < range.start {
+        return Err(format!("range {:?} of {:?} is inverted", range, file).into());
+    }
+    let size = range.end - range.start;
+    if (data.len() as wgt::BufferAddress) < size {
+        return Err(Box::new(BlobTooSmall {
+            file: file.to_string(),
+            expected: size,
+            got: data.len(),
+        }));
+    }
+    // the size fits in `usize`, since it's not above the length of the data
+    Ok(&data[..size as usize])
+}
+
+/// Returns the CRC-32 embedded in a binary file name, like
+/// `data1.crc32_AABBCCDD.bin`.
+fn data_crc(path: &Path) -> Option<u32> {
+    const PREFIX: &str = ".crc32_";
+    let name = path.file_name()?.to_str()?;
+    let start = name.find(PREFIX)? + PREFIX.len();
+    let digits = name.get(start..start + 8)?;
+    u32::from_str_radix(digits, 16).ok()
+}
+
+/// Writes the `actions` as a new RON trace into `dir`, recorded on `backend`,
+/// copying the binary files they use from `source_dir`.
+///
+/// This is meant for the traces made out of another one, with
+/// `filter_actions` for example. Recording times are not kept.
+pub fn save_trace(
+    dir: &Path,
+    backend: wgt::Backend,
+    actions: Vec<trace::Action>,
+    source_dir: &Path,
+) -> io::Result<()> {
+    fs::create_dir_all(dir)?;
+    let mut files = HashSet::new();
+    for action in actions.iter() {
+        use wgc::device::trace::Action as A;
+        match *action {
+            A::WriteBuffer { ref data, .. } | A::WriteTexture { ref data, .. } => {
+                files.insert(data.as_str());
             }
-            A::DestroySampler(id) => {
-                self.sampler_destroy::<B>(id);
+            A::WriteBufferMany { ref writes, .. } => {
+                files.extend(writes.iter().map(|&(_, ref data)| data.as_str()));
             }
-            A::GetSwapChainTexture { id, parent_id } => {
-                if let Some(id) = id {
-                    self.swap_chain_get_current_texture_view::<B>(parent_id, id)
-                        .unwrap()
-                        .view_id
-                        .unwrap();
+            A::CreateShaderModule { ref source, .. } => match *source {
+                trace::ShaderModuleSource::SpirV(ref data)
+                | trace::ShaderModuleSource::Wgsl(ref data) => {
+                    files.insert(data.as_str());
                 }
+            },
+            _ => {}
+        }
+    }
+    if dir != source_dir {
+        for file in files {
+            fs::copy(source_dir.join(file), dir.join(file))?;
+        }
+    }
+    let mut trace = trace::Trace::new(dir, backend, trace::TraceFormat::Ron)?;
+    for action in actions {
+        trace.add(action);
+    }
+    trace.flush(true)
+}
+
+/// Number of the largest binary files listed by `TraceSummary::largest_blobs`.
+const LARGEST_BLOB_COUNT: usize = 5;
+
+/// Inventory of a trace, see `inspect_trace`.
+#[derive(Debug, Default)]
+pub struct TraceSummary {
+    pub action_count: usize,
+    /// Number of actions of each kind, named by `action_name`.
+    pub per_action: BTreeMap<&'static str, usize>,
+    /// Number of `PresentSwapChain` actions.
+    pub frame_count: usize,
+    /// Size of the buffer and texture data written by the actions.
+    pub buffer_data_bytes: u64,
+    /// Size of the shader modules, counting the identical ones once.
+    pub shader_bytes: u64,
+    pub unique_shaders: usize,
+    /// Numbers of compute pipelines created and destroyed.
+    pub compute_pipelines: (usize, usize),
+    /// Numbers of render pipelines created and destroyed.
+    pub render_pipelines: (usize, usize),
+    /// Numbers of direct and indirect dispatches of the compute passes.
+    pub dispatches: (usize, usize),
+    /// Number of render bundles executed by the render passes.
+    pub bundle_executions: usize,
+    /// Descriptor of each device, with the features and limits it requires.
+    pub devices: Vec<wgt::DeviceDescriptor>,
+    /// Largest binary files of the trace, with their sizes, from the largest.
+    pub largest_blobs: Vec<(String, u64)>,
+}
+
+impl TraceSummary {
+    /// Tallies the `actions` of a trace, with the binary files in `dir`.
+    pub fn collect(actions: &[trace::Action], dir: &Path) -> io::Result<Self> {
+        use wgc::{
+            command::{ComputeCommand, RenderCommand},
+            device::trace::Action as A,
+        };
+        let mut summary = TraceSummary {
+            action_count: actions.len(),
+            ..TraceSummary::default()
+        };
+        // identical binary data is stored in the same file
+        let mut blobs = HashMap::new();
+        let mut blob_size = |file: &str| -> io::Result<u64> {
+            if let Some(&size) = blobs.get(file) {
+                return Ok(size);
             }
-            A::CreateBindGroupLayout {
-                id,
-                ref label,
-                ref entries,
-            } => {
-                self.device_create_bind_group_layout::<B>(
-                    device,
-                    &wgt::BindGroupLayoutDescriptor {
-                        label: Some(label),
-                        entries,
-                    },
-                    id,
-                )
-                .unwrap();
-            }
-            A::DestroyBindGroupLayout(id) => {
-                self.bind_group_layout_destroy::<B>(id);
-            }
-            A::CreatePipelineLayout {
-                id,
-                bind_group_layouts,
-                push_constant_ranges,
-            } => {
-                self.device_maintain_ids::<B>(device);
-                self.device_create_pipeline_layout::<B>(
-                    device,
-                    &wgt::PipelineLayoutDescriptor {
-                        bind_group_layouts: &bind_group_layouts,
-                        push_constant_ranges: &push_constant_ranges,
-                    },
-                    id,
-                )
-                .unwrap();
-            }
-            A::DestroyPipelineLayout(id) => {
-                self.pipeline_layout_destroy::<B>(id);
+            let size = fs::metadata(dir.join(file))
+                .map_err(|e| {
+                    io::Error::new(
+                        e.kind(),
+                        format!("unable to find {:?} in {:?}: {}", file, dir, e),
+                    )
+                })?
+                .len();
+            blobs.insert(file.to_string(), size);
+            Ok(size)
+        };
+        let mut shader_files = HashSet::new();
+        for action in actions {
+            *summary.per_action.entry(action_name(action)).or_insert(0) += 1;
+            match *action {
+                A::Init { ref desc, .. } => summary.devices.push(desc.clone()),
+                A::PresentSwapChain(_) => summary.frame_count += 1,
+                A::WriteBuffer { ref data, .. } | A::WriteTexture { ref data, .. } => {
+                    summary.buffer_data_bytes += blob_size(data)?;
+                }
+                A::WriteBufferMany { ref writes, .. } => {
+                    for &(_, ref data) in writes {
+                        summary.buffer_data_bytes += blob_size(data)?;
+                    }
+                }
+                A::CreateShaderModule { ref source, .. } => {
+                    let data = match *source {
+                        trace::ShaderModuleSource::SpirV(ref data)
+                        | trace::ShaderModuleSource::Wgsl(ref data) => data,
+                    };
+                    let size = blob_size(data)?;
+                    if shader_files.insert(data.as_str()) {
+                        summary.shader_bytes += size;
+                    }
+                }
+                A::CreateComputePipeline { .. } => summary.compute_pipelines.0 += 1,
+                A::DestroyComputePipeline(_) => summary.compute_pipelines.1 += 1,
+                A::CreateRenderPipeline { .. } => summary.render_pipelines.0 += 1,
+                A::DestroyRenderPipeline(_) => summary.render_pipelines.1 += 1,
+                A::Submit(_, _, _, ref commands) => {
+                    for command in commands {
+                        match *command {
+                            trace::Command::RunComputePass { ref base, .. } => {
+                                for command in base.commands.iter() {
+                                    match *command {
+                                        ComputeCommand::Dispatch(_) => summary.dispatches.0 += 1,
+                                        ComputeCommand::DispatchIndirect { .. } => {
+                                            summary.dispatches.1 += 1
+                                        }
+                                        _ => {}
+                                    }
+                                }
+                            }
+                            trace::Command::RunRenderPass { ref base, .. } => {
+                                for command in base.commands.iter() {
+                                    if let RenderCommand::ExecuteBundle(_) = *command {
+                                        summary.bundle_executions += 1;
+                                    }
+                                }
+                            }
+                            _ => {}
+                        }
+                    }
+                }
+                _ => {}
             }
-            A::CreateBindGroup {
+        }
+        summary.unique_shaders = shader_files.len();
+        summary.largest_blobs = blobs.into_iter().collect();
+        summary
+            .largest_blobs
+            .sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        summary.largest_blobs.truncate(LARGEST_BLOB_COUNT);
+        Ok(summary)
+    }
+
+    /// Number of `Submit` actions.
+    pub fn submit_count(&self) -> usize {
+        self.per_action.get("Submit").cloned().unwrap_or(0)
+    }
+}
+
+/// Lists the contents of the trace in `dir` without replaying it,
+/// so that no adapter is needed.
+pub fn inspect_trace(dir: &Path) -> Result<TraceSummary, Box<dyn Error>> {
+    let loaded = load_trace(dir)?;
+    Ok(TraceSummary::collect(&loaded.actions, dir)?)
+}
+
+/// Options controlling how a trace is replayed.
+#[derive(Clone, Debug, Default)]
+pub struct PlayerConfig {
+    /// Replace swap chains with off-screen textures, so that traces captured
+    /// from windowed applications can be replayed without a display.
+    ///
+    /// The swap chain actions passed to `GlobalPlay::process` are always
+    /// replayed this way, since there is no window to present to,
+    /// but a warning is logged unless this is set.
+    pub use_headless_swapchain: bool,
+    /// Run resource creation and command encoding through validation, but
+    /// don't submit any work or upload any data to the GPU.
+    pub validate_only: bool,
+    /// Measure the time spent on each action, see `PlaybackState::take_stats`.
+    pub collect_stats: bool,
+    /// Create the shader modules and pipelines of the batches passed to
+    /// `GlobalPlay::process_batch` on multiple threads.
+    pub parallel_creation: bool,
+    /// Encode the submissions of the batches passed to
+    /// `GlobalPlay::process_batch` on multiple threads.
+    pub parallel_encoding: bool,
+    /// Save the frames presented to the off-screen swap chains into this
+    /// directory, as `frame0.png`, `frame1.png`, and so on.
+    pub capture_dir: Option<PathBuf>,
+    /// Compare the frames presented to the off-screen swap chains against
+    /// the images of the same names in this directory, failing the
+    /// `PresentSwapChain` of any frame that doesn't match its reference.
+    /// The results are kept in `PlaybackState::comparisons`.
+    pub reference_dir: Option<PathBuf>,
+    /// Largest difference of a color channel, out of 255, that is tolerated
+    /// when comparing frames to the references.
+    pub tolerance: u8,
+    /// Report the resources that are still alive at the end of the replay,
+    /// see `PlaybackState::leak_report`.
+    pub report_leaks: bool,
+    /// Save this texture into `capture_dir` after each `Submit`, as
+    /// `submit1.png`, `submit2.png`, and so on, named after the submission
+    /// index. See `capture_output_texture` for the supported textures.
+    pub capture_output: Option<wgc::id::TextureId>,
+    /// Create the devices on this backend instead of the one recorded by
+    /// `Action::Init`, see `substitute_backend`. The shaders have to be
+    /// translatable to the shading language of the backend.
+    pub backend: Option<wgt::Backend>,
+}
+
+/// Number of replayed actions of a kind, and the time spent on them.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ActionStats {
+    pub count: usize,
+    pub total: Duration,
+}
+
+/// Time spent on replaying the actions, grouped by their kind.
+#[derive(Debug, Default)]
+pub struct ReplayStats {
+    pub per_action: BTreeMap<&'static str, ActionStats>,
+    /// Index, kind, and duration of the slowest action.
+    pub slowest: Option<(usize, &'static str, Duration)>,
+}
+
+impl ReplayStats {
+    fn record(&mut self, index: usize, name: &'static str, duration: Duration) {
+        let stats = self.per_action.entry(name).or_default();
+        stats.count += 1;
+        stats.total += duration;
+        match self.slowest {
+            Some((_, _, slowest)) if slowest >= duration => {}
+            _ => self.slowest = Some((index, name, duration)),
+        }
+    }
+}
+
+impl fmt::Display for ReplayStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{:<24} {:>8} {:>12}", "Action", "Count", "Total ms")?;
+        for (name, stats) in self.per_action.iter() {
+            writeln!(
+                f,
+                "{:<24} {:>8} {:>12.3}",
+                name,
+                stats.count,
+                stats.total.as_secs_f64() * 1000.0
+            )?;
+        }
+        if let Some((index, name, duration)) = self.slowest {
+            writeln!(
+                f,
+                "Slowest: action #{} ({}) took {:.3} ms",
+                index,
+                name,
+                duration.as_secs_f64() * 1000.0
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Resources that a trace created, but never destroyed.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct LeakReport {
+    /// IDs of the leaked resources of each kind, sorted.
+    pub resources: BTreeMap<ResourceKind, Vec<RawId>>,
+}
+
+impl LeakReport {
+    /// Returns the number of leaked resources of all kinds.
+    pub fn count(&self) -> usize {
+        self.resources.values().map(Vec::len).sum()
+    }
+}
+
+impl fmt::Display for LeakReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{} resource(s) were never destroyed", self.count())?;
+        for (kind, ids) in self.resources.iter() {
+            // padding is ignored by the `Debug` output of the kind
+            let name = format!("{:?}", kind);
+            writeln!(f, "{:<24} {:>8} {:?}", name, ids.len(), ids)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+struct HeadlessSwapChain {
+    desc: wgt::SwapChainDescriptor,
+    texture: wgc::id::TextureId,
+    acquired_view: Option<wgc::id::TextureViewId>,
+}
+
+/// What to do with the action that is about to be replayed.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum StepControl {
+    /// Replay the action.
+    Continue,
+    /// Don't replay the action, and go on with the next one.
+    Skip,
+    /// Stop the replay, failing the action.
+    Abort,
+}
+
+struct StepHook(Box<dyn FnMut(usize, &trace::Action) -> StepControl>);
+
+impl Debug for StepHook {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("StepHook")
+    }
+}
+
+/// State of the replay that is carried between actions.
+#[derive(Debug)]
+pub struct PlaybackState {
+    pub config: PlayerConfig,
+    /// IDs of the command buffers created by the player on its own, which
+    /// are recycled once the command buffers are submitted or destroyed.
+    /// The encoders of the trace are created with their recorded IDs instead.
+    comb_manager: wgc::hub::IdentityManager,
+    /// Number of command buffer indices allocated by `comb_manager`.
+    comb_index_count: u32,
+    /// One more than the highest index of the resources created by the trace
+    /// so far, which the storages of the core grow to.
+    index_count: u32,
+    /// First command buffer index that is not used by the trace itself,
+    /// which the indices allocated by `comb_manager` are offset by.
+    free_comb_index: u32,
+    /// Next texture index that is not used by the trace itself.
+    free_texture_index: u32,
+    /// Allocator of the IDs of the readback buffers created by the player,
+    /// which reuses the indices of the destroyed ones.
+    buffer_manager: wgc::hub::IdentityManager,
+    /// First buffer index that is not used by the trace itself, which
+    /// the indices allocated by `buffer_manager` are offset by.
+    free_buffer_index: u32,
+    /// Number of frames read back for `PlayerConfig::capture_dir` or
+    /// `PlayerConfig::reference_dir` so far.
+    captured_frame_count: usize,
+    comparisons: Vec<ComparisonResult>,
+    headless_swap_chains: HashMap<wgc::id::SwapChainId, HeadlessSwapChain>,
+    /// Buffers that are mapped for reading by the replay.
+    mapped_buffers: HashSet<wgc::id::BufferId>,
+    /// Formats and sizes of the textures created by the trace, to read them back.
+    texture_descs: HashMap<wgc::id::TextureId, (wgt::TextureFormat, wgt::Extent3d)>,
+    /// Index of the next action to be processed.
+    action_index: usize,
+    /// Submission index of the last replayed `Submit`.
+    last_submission_index: Option<usize>,
+    stats: Option<ReplayStats>,
+    step_hook: Option<StepHook>,
+    /// Resources that are alive, to catch the actions referring to others.
+    live: DryRunPlayer,
+    /// Devices created by the `Action::Init` actions so far, with their backends.
+    devices: HashMap<wgc::id::DeviceId, wgt::Backend>,
+    /// Device of the last `Action::Init`, which the actions are replayed on.
+    current_device: Option<wgc::id::DeviceId>,
+}
+
+impl PlaybackState {
+    pub fn new(config: PlayerConfig, actions: &[trace::Action]) -> Self {
+        let mut state = PlaybackState {
+            comb_manager: wgc::hub::IdentityManager::default(),
+            comb_index_count: 0,
+            index_count: 0,
+            free_comb_index: 0,
+            free_texture_index: 0,
+            buffer_manager: wgc::hub::IdentityManager::default(),
+            free_buffer_index: 0,
+            captured_frame_count: 0,
+            comparisons: Vec::new(),
+            headless_swap_chains: HashMap::new(),
+            mapped_buffers: HashSet::new(),
+            texture_descs: HashMap::new(),
+            action_index: 0,
+            last_submission_index: None,
+            stats: if config.collect_stats {
+                Some(ReplayStats::default())
+            } else {
+                None
+            },
+            step_hook: None,
+            live: DryRunPlayer::new(),
+            devices: HashMap::new(),
+            current_device: None,
+            config,
+        };
+        for action in actions {
+            state.reserve_ids(action);
+        }
+        state
+    }
+
+    /// Keeps the IDs created by the `action` out of the ones the player
+    /// creates on its own, so that they don't collide.
+    ///
+    /// `PlaybackState::new` does this for the actions it's given. When the
+    /// actions are streamed with a `TraceReader` instead, all of them have
+    /// to go through this before the replay starts.
+    pub fn reserve_ids(&mut self, action: &trace::Action) {
+        match *action {
+            trace::Action::CreateTexture { id, .. } => {
+                self.free_texture_index = self.free_texture_index.max(id.unzip().0 + 1);
+            }
+            trace::Action::CreateBuffer { id, .. } => {
+                self.free_buffer_index = self.free_buffer_index.max(id.unzip().0 + 1);
+            }
+            trace::Action::CreateCommandEncoder { id, .. } => {
+                self.free_comb_index = self.free_comb_index.max(id.unzip().0 + 1);
+            }
+            _ => {}
+        }
+    }
+
+    /// Makes the `device` of an `Action::Init` the one the following actions
+    /// are replayed on. Returns true if the device wasn't seen before, in
+    /// which case it has to be created on the `backend` by the caller.
+    pub fn switch_device(&mut self, device: wgc::id::DeviceId, backend: wgt::Backend) -> bool {
+        self.current_device = Some(device);
+        self.devices.insert(device, backend).is_none()
+    }
+
+    /// Device the actions are currently replayed on, if any.
+    pub fn current_device(&self) -> Option<wgc::id::DeviceId> {
+        self.current_device
+    }
+
+    /// Devices created so far, with the backends they were recorded on.
+    pub fn devices(&self) -> impl Iterator<Item = (wgc::id::DeviceId, wgt::Backend)> + '_ {
+        self.devices
+            .iter()
+            .map(|(&device, &backend)| (device, backend))
+    }
+
+    /// Sets the `hook` to be called with the index of each action before
+    /// it's replayed by `GlobalPlay::process`, deciding what to do with it.
+    pub fn set_step_hook(
+        &mut self,
+        hook: impl FnMut(usize, &trace::Action) -> StepControl + 'static,
+    ) {
+        self.step_hook = Some(StepHook(Box::new(hook)));
+    }
+
+    /// Returns the statistics gathered so far, if `PlayerConfig::collect_stats`
+    /// is enabled, and starts over.
+    pub fn take_stats(&mut self) -> Option<ReplayStats> {
+        self.stats.as_mut().map(std::mem::take)
+    }
+
+    /// Returns the results of comparing the frames to the references
+    /// in `PlayerConfig::reference_dir`, in the order they were presented.
+    pub fn comparisons(&self) -> &[ComparisonResult] {
+        &self.comparisons
+    }
+
+    /// Returns the resources that were created by the actions replayed so far,
+    /// but not destroyed, if `PlayerConfig::report_leaks` is enabled.
+    pub fn leak_report(&self) -> Option<LeakReport> {
+        if self.config.report_leaks {
+            Some(self.live.leaks())
+        } else {
+            None
+        }
+    }
+
+    /// Notes the resources created or destroyed by an `action` that was
+    /// replayed without going through `GlobalPlay::process`, such as the
+    /// swap chain actions handled by a window's event loop.
+    pub fn track(&mut self, action: &trace::Action) {
+        self.live.process(action);
+    }
+
+    /// Returns the number of distinct command buffer IDs used by the player
+    /// on its own so far. Since the IDs are recycled, it stays bounded by
+    /// the number of such command buffers that exist at the same time.
+    pub fn command_buffer_index_count(&self) -> u32 {
+        self.comb_index_count
+    }
+
+    /// Returns the high-water mark of the indices of the resources created
+    /// by the trace so far. The IDs are passed through from the trace, and
+    /// the recording application reuses the indices of the destroyed
+    /// resources, so it stays bounded by the resources alive at once.
+    pub fn index_high_water_mark(&self) -> u32 {
+        self.index_count
+    }
+
+    fn alloc_comb_id(&mut self, backend: wgt::Backend) -> wgc::id::CommandEncoderId {
+        let id: wgc::id::CommandEncoderId = self.comb_manager.alloc(backend);
+        let (index, epoch, backend) = id.unzip();
+        self.comb_index_count = self.comb_index_count.max(index + 1);
+        wgc::id::TypedId::zip(self.free_comb_index + index, epoch, backend)
+    }
+
+    fn free_comb_id(&mut self, id: wgc::id::CommandEncoderId) {
+        let (index, epoch, backend) = id.unzip();
+        let id: wgc::id::CommandEncoderId =
+            wgc::id::TypedId::zip(index - self.free_comb_index, epoch, backend);
+        self.comb_manager.free(id);
+    }
+
+    fn alloc_texture_id(&mut self, backend: wgt::Backend) -> wgc::id::TextureId {
+        let index = self.free_texture_index;
+        self.free_texture_index += 1;
+        wgc::id::TypedId::zip(index, 1, backend)
+    }
+
+    fn alloc_buffer_id(&mut self, backend: wgt::Backend) -> wgc::id::BufferId {
+        let id: wgc::id::BufferId = self.buffer_manager.alloc(backend);
+        let (index, epoch, backend) = id.unzip();
+        wgc::id::TypedId::zip(self.free_buffer_index + index, epoch, backend)
+    }
+
+    fn free_buffer_id(&mut self, id: wgc::id::BufferId) {
+        let (index, epoch, backend) = id.unzip();
+        let id: wgc::id::BufferId =
+            wgc::id::TypedId::zip(index - self.free_buffer_index, epoch, backend);
+        self.buffer_manager.free(id);
+    }
+}
+
+/// Substitutes the swap chain `id` with an off-screen texture of the same
+/// size and format, which `GetSwapChainTexture` then creates views of.
+fn create_headless_swap_chain<B: wgc::hub::GfxBackend>(
+    global: &wgc::hub::Global<IdentityPassThroughFactory>,
+    device: wgc::id::DeviceId,
+    id: wgc::id::SwapChainId,
+    desc: &wgt::SwapChainDescriptor,
+    state: &mut PlaybackState,
+) {
+    log::info!("Emulating swap chain {:?} with an off-screen texture", id);
+    if let Some(old) = state.headless_swap_chains.remove(&id) {
+        if let Some(view) = old.acquired_view {
+            global.texture_view_destroy::<B>(view);
+        }
+        global.texture_destroy::<B>(old.texture);
+    }
+    let texture = state.alloc_texture_id(device.backend());
+    global.device_maintain_ids::<B>(device);
+    global.device_create_texture::<B>(
+        device,
+        &wgt::TextureDescriptor {
+            label: ptr::null(),
+            size: wgt::Extent3d {
+                width: desc.width,
+                height: desc.height,
+                depth: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgt::TextureDimension::D2,
+            format: desc.format,
+            usage: desc.usage | wgt::TextureUsage::COPY_SRC,
+        },
+        texture,
+    );
+    state.headless_swap_chains.insert(
+        id,
+        HeadlessSwapChain {
+            desc: desc.clone(),
+            texture,
+            acquired_view: None,
+        },
+    );
+}
+
+/// Ends the frame of the swap chain `id` substituted by
+/// `create_headless_swap_chain`, releasing the view of its texture.
+/// The frame is saved first if `PlayerConfig::capture_dir` is set.
+fn present_headless_swap_chain<B: wgc::hub::GfxBackend>(
+    global: &wgc::hub::Global<IdentityPassThroughFactory>,
+    device: wgc::id::DeviceId,
+    id: wgc::id::SwapChainId,
+    state: &mut PlaybackState,
+) -> Result<(), ActionError> {
+    if !state.headless_swap_chains.contains_key(&id) {
+        return Err("presenting a swap chain that wasn't created".into());
+    }
+    let needs_frame = state.config.capture_dir.is_some() || state.config.reference_dir.is_some();
+    let result = if needs_frame && !state.config.validate_only {
+        check_headless_frame::<B>(global, device, id, state)
+    } else {
+        Ok(())
+    };
+    let sc = state.headless_swap_chains.get_mut(&id).unwrap();
+    if let Some(view) = sc.acquired_view.take() {
+        global.texture_view_destroy::<B>(view);
+    }
+    result
+}
+
+/// Reads the frame of the headless swap chain `id` back, then saves it
+/// to `PlayerConfig::capture_dir` and compares it to the reference
+/// in `PlayerConfig::reference_dir`, whichever of them are set.
+fn check_headless_frame<B: wgc::hub::GfxBackend>(
+    global: &wgc::hub::Global<IdentityPassThroughFactory>,
+    device: wgc::id::DeviceId,
+    id: wgc::id::SwapChainId,
+    state: &mut PlaybackState,
+) -> Result<(), ActionError> {
+    let frame = read_headless_frame::<B>(global, device, id, state)?;
+    let index = state.captured_frame_count;
+    state.captured_frame_count += 1;
+    let name = format!("frame{}.png", index);
+    if let Some(ref dir) = state.config.capture_dir {
+        let path = dir.join(&name);
+        log::info!("Saving frame of swap chain {:?} to {:?}", id, path);
+        frame.save(&path)?;
+    }
+    if let Some(ref dir) = state.config.reference_dir {
+        let reference = Frame::load(&dir.join(&name))?;
+        let result = compare_frames(index, &frame, &reference, state.config.tolerance)?;
+        state.comparisons.push(result.clone());
+        if !result.passed() {
+            return Err(Box::new(result));
+        }
+    }
+    Ok(())
+}
+
+/// Reads the texture of the headless swap chain `id` back.
+fn read_headless_frame<B: wgc::hub::GfxBackend>(
+    global: &wgc::hub::Global<IdentityPassThroughFactory>,
+    device: wgc::id::DeviceId,
+    id: wgc::id::SwapChainId,
+    state: &mut PlaybackState,
+) -> Result<Frame, ActionError> {
+    let sc = &state.headless_swap_chains[&id];
+    let (texture, width, height, format) =
+        (sc.texture, sc.desc.width, sc.desc.height, sc.desc.format);
+    read_texture::<B>(global, device, texture, width, height, format, state)
+}
+
+/// Saves the first mip level of the `texture` created by the trace as a PNG
+/// at `path`, after waiting for the work submitted so far.
+///
+/// Only textures with 8-bit RGBA or BGRA pixels are supported. The texture
+/// needs the `COPY_SRC` usage.
+pub fn capture_output_texture<B: wgc::hub::GfxBackend>(
+    global: &wgc::hub::Global<IdentityPassThroughFactory>,
+    device: wgc::id::DeviceId,
+    texture: wgc::id::TextureId,
+    path: &Path,
+    state: &mut PlaybackState,
+) -> Result<(), ActionError> {
+    let (format, size) = match state.texture_descs.get(&texture) {
+        Some(&desc) => desc,
+        None => return Err(format!("texture {:?} wasn't created by the trace", texture).into()),
+    };
+    let frame = read_texture::<B>(
+        global,
+        device,
+        texture,
+        size.width,
+        size.height,
+        format,
+        state,
+    )?;
+    log::info!("Saving texture {:?} to {:?}", texture, path);
+    frame.save(path)
+}
+
+/// Reads the first layer of the first mip level of `texture` back,
+/// which is `width` by `height` pixels of the given `format`.
+fn read_texture<B: wgc::hub::GfxBackend>(
+    global: &wgc::hub::Global<IdentityPassThroughFactory>,
+    device: wgc::id::DeviceId,
+    texture: wgc::id::TextureId,
+    width: u32,
+    height: u32,
+    format: wgt::TextureFormat,
+    state: &mut PlaybackState,
+) -> Result<Frame, ActionError> {
+    let is_bgra = match format {
+        wgt::TextureFormat::Rgba8Unorm | wgt::TextureFormat::Rgba8UnormSrgb => false,
+        wgt::TextureFormat::Bgra8Unorm | wgt::TextureFormat::Bgra8UnormSrgb => true,
+        other => return Err(format!("unable to capture frames of {:?}", other).into()),
+    };
+    // rows of the copy are padded to the alignment, unlike rows of the image
+    let row_size = width * 4;
+    let alignment = wgt::COPY_BYTES_PER_ROW_ALIGNMENT;
+    let bytes_per_row = (row_size + alignment - 1) / alignment * alignment;
+    let size = bytes_per_row as wgt::BufferAddress * height as wgt::BufferAddress;
+
+    let buffer = state.alloc_buffer_id(device.backend());
+    global.device_maintain_ids::<B>(device);
+    global.device_create_buffer::<B>(
+        device,
+        &wgt::BufferDescriptor {
+            label: ptr::null(),
+            size,
+            usage: wgt::BufferUsage::MAP_READ | wgt::BufferUsage::COPY_DST,
+            mapped_at_creation: false,
+        },
+        buffer,
+    );
+    let copy = trace::Command::CopyTextureToBuffer {
+        src: wgc::command::TextureCopyView {
+            texture,
+            mip_level: 0,
+            origin: wgt::Origin3d::ZERO,
+        },
+        dst: wgc::command::BufferCopyView {
+            buffer,
+            layout: wgt::TextureDataLayout {
+                offset: 0,
+                bytes_per_row,
+                rows_per_image: 0,
+            },
+        },
+        size: wgt::Extent3d {
+            width,
+            height,
+            depth: 1,
+        },
+    };
+    let result = submit_commands::<B>(global, device, vec![copy], state)
+        .and_then(|()| read_buffer::<B>(global, device, buffer, size, bytes_per_row, row_size));
+    global.buffer_destroy::<B>(buffer);
+    state.free_buffer_id(buffer);
+    let mut pixels = result?;
+    if is_bgra {
+        for pixel in pixels.chunks_mut(4) {
+            pixel.swap(0, 2);
+        }
+    }
+    Ok(Frame {
+        width,
+        height,
+        pixels,
+    })
+}
+
+/// Maps the `size` bytes of the readback `buffer` and returns the rows
+/// of `row_size` bytes that were copied into it every `bytes_per_row`.
+fn read_buffer<B: wgc::hub::GfxBackend>(
+    global: &wgc::hub::Global<IdentityPassThroughFactory>,
+    device: wgc::id::DeviceId,
+    buffer: wgc::id::BufferId,
+    size: wgt::BufferAddress,
+    bytes_per_row: u32,
+    row_size: u32,
+) -> Result<Vec<u8>, ActionError> {
+    let mut status = wgc::resource::BufferMapAsyncStatus::Unknown;
+    global.buffer_map_async::<B>(
+        buffer,
+        0..size,
+        wgc::resource::BufferMapOperation {
+            host: wgc::device::HostMap::Read,
+            callback: map_status_callback,
+            user_data: &mut status as *mut wgc::resource::BufferMapAsyncStatus as *mut u8,
+        },
+    );
+    global.device_poll::<B>(device, true)?;
+    match status {
+        wgc::resource::BufferMapAsyncStatus::Success => {}
+        _ => return Err(format!("unable to map buffer {:?}: {:?}", buffer, status).into()),
+    }
+    let row_count = size / bytes_per_row as wgt::BufferAddress;
+    let mut pixels = Vec::with_capacity(row_size as usize * row_count as usize);
+    {
+        let ptr = global.buffer_get_mapped_range::<B>(buffer, 0, None);
+        let mapped = unsafe { slice::from_raw_parts(ptr, size as usize) };
+        for row in mapped.chunks(bytes_per_row as usize) {
+            pixels.extend_from_slice(&row[..row_size as usize]);
+        }
+    }
+    global.buffer_unmap::<B>(buffer);
+    Ok(pixels)
+}
+
+/// Image with 8-bit RGBA pixels, stored row by row.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Frame {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+}
+
+#[cfg(feature = "png")]
+impl Frame {
+    /// Loads a PNG image with 8-bit RGB or RGBA pixels.
+    pub fn load(path: &Path) -> Result<Self, ActionError> {
+        let file = fs::File::open(path)
+            .map_err(|e| format!("unable to open the image {:?}: {}", path, e))?;
+        let mut decoder = png::Decoder::new(file);
+        // palettes and smaller bit depths are turned into 8-bit channels
+        decoder.set_transformations(png::Transformations::EXPAND);
+        let (info, mut reader) = decoder.read_info()?;
+        let mut data = vec![0; info.buffer_size()];
+        reader.next_frame(&mut data)?;
+        let pixels = match (info.color_type, info.bit_depth) {
+            (png::ColorType::RGBA, png::BitDepth::Eight) => data,
+            (png::ColorType::RGB, png::BitDepth::Eight) => data
+                .chunks(3)
+                .flat_map(|rgb| vec![rgb[0], rgb[1], rgb[2], 0xFF])
+                .collect(),
+            (color, depth) => {
+                return Err(format!(
+                    "unsupported pixels of the image {:?}: {:?} with {:?} bits",
+                    path, color, depth
+                )
+                .into())
+            }
+        };
+        Ok(Frame {
+            width: info.width,
+            height: info.height,
+            pixels,
+        })
+    }
+
+    /// Saves the image as a PNG at `path`.
+    pub fn save(&self, path: &Path) -> Result<(), ActionError> {
+        let file = io::BufWriter::new(fs::File::create(path)?);
+        let mut encoder = png::Encoder::new(file, self.width, self.height);
+        encoder.set_color(png::ColorType::RGBA);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder.write_header()?.write_image_data(&self.pixels)?;
+        Ok(())
+    }
+}
+
+#[cfg(not(feature = "png"))]
+impl Frame {
+    /// Fails, since PNG images are only supported with the "png" feature.
+    pub fn load(path: &Path) -> Result<Self, ActionError> {
+        Err(format!(
+            "unable to load the image {:?}: built without the \"png\" feature",
+            path
+        )
+        .into())
+    }
+
+    /// Fails, since PNG images are only supported with the "png" feature.
+    pub fn save(&self, path: &Path) -> Result<(), ActionError> {
+        Err(format!(
+            "unable to save the image {:?}: built without the \"png\" feature",
+            path
+        )
+        .into())
+    }
+}
+
+/// Difference between a replayed frame and its reference image.
+#[derive(Clone, Debug)]
+pub struct ComparisonResult {
+    /// Index of the frame, as in the `frame0.png` name of the reference.
+    pub frame: usize,
+    /// Largest difference of a color channel, over all the pixels.
+    pub max_delta: u8,
+    /// Largest difference of a color channel, averaged over the pixels.
+    pub mean_delta: f64,
+    /// Coordinates of the first pixel that differs by `max_delta`.
+    pub worst_pixel: (u32, u32),
+    /// Largest `max_delta` the frame passes with.
+    pub tolerance: u8,
+}
+
+impl ComparisonResult {
+    pub fn passed(&self) -> bool {
+        self.max_delta <= self.tolerance
+    }
+}
+
+impl fmt::Display for ComparisonResult {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "frame {} differs by up to {} at {:?} ({:.3} on average), the tolerance is {}",
+            self.frame, self.max_delta, self.worst_pixel, self.mean_delta, self.tolerance
+        )
+    }
+}
+
+impl Error for ComparisonResult {}
+
+/// Compares the `frame` with the given `index` to its `reference`,
+/// which must have the same size.
+pub fn compare_frames(
+    index: usize,
+    frame: &Frame,
+    reference: &Frame,
+    tolerance: u8,
+) -> Result<ComparisonResult, ActionError> {
+    if (frame.width, frame.height) != (reference.width, reference.height) {
+        return Err(format!(
+            "frame {} is {}x{}, but the reference is {}x{}",
+            index, frame.width, frame.height, reference.width, reference.height
+        )
+        .into());
+    }
+    let mut result = ComparisonResult {
+        frame: index,
+        max_delta: 0,
+        mean_delta: 0.0,
+        worst_pixel: (0, 0),
+        tolerance,
+    };
+    let mut delta_sum = 0u64;
+    let pairs = frame.pixels.chunks(4).zip(reference.pixels.chunks(4));
+    for (i, (pixel, expected)) in pairs.enumerate() {
+        let delta = pixel
+            .iter()
+            .zip(expected)
+            .map(|(&a, &b)| if a > b { a - b } else { b - a })
+            .max()
+            .unwrap_or(0);
+        delta_sum += delta as u64;
+        if delta > result.max_delta {
+            result.max_delta = delta;
+            result.worst_pixel = (i as u32 % frame.width, i as u32 / frame.width);
+        }
+    }
+    let pixel_count = frame.width as u64 * frame.height as u64;
+    if pixel_count != 0 {
+        result.mean_delta = delta_sum as f64 / pixel_count as f64;
+    }
+    Ok(result)
+}
+
+/// Error reported by the core for a single action. It can be passed back
+/// from the threads replaying a batch, see `GlobalPlay::process_batch`.
+pub type ActionError = Box<dyn Error + Send + Sync>;
+
+/// Failure to replay an action of the trace.
+#[derive(Debug)]
+pub struct PlayerError {
+    /// Index of the action, counting from the first one passed to
+    /// `GlobalPlay::process`, which is the one following `Action::Init`.
+    pub action_index: usize,
+    /// Name of the action variant.
+    pub action: &'static str,
+    pub cause: ActionError,
+}
+
+impl fmt::Display for PlayerError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "action #{} ({}) failed: {}",
+            self.action_index, self.action, self.cause
+        )
+    }
+}
+
+impl PlayerError {
+    /// Returns the resource that the action refers to but isn't alive,
+    /// if that's what failed the action.
+    pub fn dangling_id(&self) -> Option<&DanglingId> {
+        self.cause.downcast_ref()
+    }
+
+    /// Returns the reason why a copy command doesn't fit in its textures,
+    /// if that's what failed the action.
+    pub fn invalid_copy(&self) -> Option<&wgc::command::CopyError> {
+        self.cause.downcast_ref()
+    }
+}
+
+impl Error for PlayerError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(self.cause.as_ref())
+    }
+}
+
+/// Unwraps the `CopyError` of a failed copy command, so that it's reported
+/// by `PlayerError::invalid_copy`.
+fn copy_error(error: wgc::command::TransferError) -> ActionError {
+    match error {
+        wgc::command::TransferError::Copy(cause) => cause.into(),
+        other => other.into(),
+    }
+}
+
+/// Returns the name of the action variant.
+pub fn action_name(action: &trace::Action) -> &'static str {
+    use wgc::device::trace::Action as A;
+    match *action {
+        A::Init { .. } => "Init",
+        A::CreateBuffer { .. } => "CreateBuffer",
+        A::DestroyBuffer(_) => "DestroyBuffer",
+        A::CreateTexture { .. } => "CreateTexture",
+        A::DestroyTexture(_) => "DestroyTexture",
+        A::CreateTextureView { .. } => "CreateTextureView",
+        A::DestroyTextureView(_) => "DestroyTextureView",
+        A::CreateSampler { .. } => "CreateSampler",
+        A::DestroySampler(_) => "DestroySampler",
+        A::CreateSurface { .. } => "CreateSurface",
+        A::CreateSwapChain { .. } => "CreateSwapChain",
+        A::GetSwapChainTexture { .. } => "GetSwapChainTexture",
+        A::PresentSwapChain(_) => "PresentSwapChain",
+        A::CreateBindGroupLayout { .. } => "CreateBindGroupLayout",
+        A::DestroyBindGroupLayout(_) => "DestroyBindGroupLayout",
+        A::CreatePipelineLayout { .. } => "CreatePipelineLayout",
+        A::DestroyPipelineLayout(_) => "DestroyPipelineLayout",
+        A::CreateBindGroup { .. } => "CreateBindGroup",
+        A::DestroyBindGroup(_) => "DestroyBindGroup",
+        A::CreateShaderModule { .. } => "CreateShaderModule",
+        A::DestroyShaderModule(_) => "DestroyShaderModule",
+        A::CreateComputePipeline { .. } => "CreateComputePipeline",
+        A::DestroyComputePipeline(_) => "DestroyComputePipeline",
+        A::CreateRenderPipeline { .. } => "CreateRenderPipeline",
+        A::DestroyRenderPipeline(_) => "DestroyRenderPipeline",
+        A::CreateRenderBundle { .. } => "CreateRenderBundle",
+        A::DestroyRenderBundle(_) => "DestroyRenderBundle",
+        A::CreateQuerySet { .. } => "CreateQuerySet",
+        A::DestroyQuerySet(_) => "DestroyQuerySet",
+        A::CreateCommandEncoder { .. } => "CreateCommandEncoder",
+        A::DropCommandEncoder(_) => "DropCommandEncoder",
+        A::WriteBuffer { .. } => "WriteBuffer",
+        A::WriteBufferMany { .. } => "WriteBufferMany",
+        A::ClearBuffer { .. } => "ClearBuffer",
+        A::WriteTexture { .. } => "WriteTexture",
+        A::MapBuffer { .. } => "MapBuffer",
+        A::UnmapBuffer(_) => "UnmapBuffer",
+        A::WriteTimestamp { .. } => "WriteTimestamp",
+        A::ResolveQuerySet { .. } => "ResolveQuerySet",
+        A::Submit(..) => "Submit",
+        A::FrameBoundary { .. } => "FrameBoundary",
+        A::Poll { .. } => "Poll",
+        A::ResourceSnapshot { .. } => "ResourceSnapshot",
+    }
+}
+
+/// Phase of `GlobalPlay::play_range`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum PlaybackPhase {
+    /// Creating the resources needed by the range, without submitting work.
+    Setup,
+    /// Replaying the requested range of actions.
+    Replay,
+    /// Destroying the resources that are still alive after the range.
+    Teardown,
+}
+
+/// Receives notifications about the progress of `GlobalPlay::play_range`.
+pub trait PlaybackObserver {
+    fn on_phase(&self, _phase: PlaybackPhase) {}
+    /// Called before each action of the requested range is replayed.
+    fn on_action(&self, _index: usize, _action: &trace::Action) {}
+    /// Called when the frame `frame_index` of the recorded application ends,
+    /// as marked by `Action::FrameBoundary`.
+    fn on_frame_boundary(&self, _frame_index: u64) {}
+    /// Called after the submission `submission_index` of the requested range
+    /// is replayed, and its output saved if `PlayerConfig::capture_output`
+    /// is set.
+    fn on_after_submit(&self, _submission_index: usize) {}
+}
+
+/// Returns the range of `actions` making up the `frames`.
+///
+/// Frames are delimited by `Action::PresentSwapChain`: each frame starts
+/// right after the previous present and ends with its own present, so it
+/// includes the `GetSwapChainTexture` acquiring the frame's texture.
+/// The actions after the last present form the last, unfinished, frame.
+pub fn frame_range(actions: &[trace::Action], frames: Range<usize>) -> Range<usize> {
+    let mut frame_starts = vec![0];
+    frame_starts.extend(
+        actions
+            .iter()
+            .enumerate()
+            .filter_map(|(index, action)| match *action {
+                trace::Action::PresentSwapChain(_) => Some(index + 1),
+                _ => None,
+            }),
+    );
+    let frame_start = |frame: usize| frame_starts.get(frame).cloned().unwrap_or(actions.len());
+    let start = frame_start(frames.start);
+    start..frame_start(frames.end).max(start)
+}
+
+/// Returns the `actions` of a trace for which `predicate` is true,
+/// to make another trace out of them with `save_trace`.
+pub fn filter_actions(
+    mut actions: Vec<trace::Action>,
+    mut predicate: impl FnMut(&trace::Action) -> bool,
+) -> Vec<trace::Action> {
+    actions.retain(|action| predicate(action));
+    actions
+}
+
+/// Returns true if the `action` destroys a resource, like `DestroyBuffer`.
+/// Dropping a command encoder doesn't count.
+fn is_resource_destroy(action: &trace::Action) -> bool {
+    match *action {
+        trace::Action::DropCommandEncoder(_) => false,
+        ref action => is_destroy(action),
+    }
+}
+
+/// Removes the actions destroying resources from a trace, so that all
+/// the resources it creates are kept alive until the end, to reproduce
+/// the issues that only happen with many resources around.
+///
+/// The core holds a single resource at each index, so the destruction of
+/// a resource is kept if its index is reused by a resource created later.
+pub fn strip_destroys(actions: Vec<trace::Action>) -> Vec<trace::Action> {
+    let mut created_later = HashSet::new();
+    let mut kept = HashSet::new();
+    for (position, action) in actions.iter().enumerate().rev() {
+        if let Some((kind, (index, _epoch))) = destroyed_resource(action) {
+            if created_later.contains(&(kind, index)) {
+                kept.insert(position);
+            }
+        }
+        if let Some((kind, (index, _epoch))) = created_resource(action) {
+            created_later.insert((kind, index));
+        }
+    }
+    actions
+        .into_iter()
+        .enumerate()
+        .filter(|&(position, ref action)| !is_resource_destroy(action) || kept.contains(&position))
+        .map(|(_, action)| action)
+        .collect()
+}
+
+/// Returns the resource created by the `action`, if any.
+/// Swap chains are left out, since they can be created again.
+fn created_resource(action: &trace::Action) -> Option<(ResourceKind, RawId)> {
+    use wgc::device::trace::Action as A;
+    use ResourceKind as K;
+    Some(match *action {
+        A::CreateBuffer { id, .. } => (K::Buffer, raw_id(id)),
+        A::CreateTexture { id, .. } => (K::Texture, raw_id(id)),
+        A::CreateTextureView { id, .. } => (K::TextureView, raw_id(id)),
+        A::GetSwapChainTexture { id: Some(id), .. } => (K::TextureView, raw_id(id)),
+        A::CreateSampler { id, .. } => (K::Sampler, raw_id(id)),
+        A::CreateBindGroupLayout { id, .. } => (K::BindGroupLayout, raw_id(id)),
+        A::CreatePipelineLayout { id, .. } => (K::PipelineLayout, raw_id(id)),
+        A::CreateBindGroup { id, .. } => (K::BindGroup, raw_id(id)),
+        A::CreateShaderModule { id, .. } => (K::ShaderModule, raw_id(id)),
+        A::CreateComputePipeline { id, .. } => (K::ComputePipeline, raw_id(id)),
+        A::CreateRenderPipeline { id, .. } => (K::RenderPipeline, raw_id(id)),
+        A::CreateRenderBundle { id, .. } => (K::RenderBundle, raw_id(id)),
+        A::CreateQuerySet { id, .. } => (K::QuerySet, raw_id(id)),
+        A::CreateCommandEncoder { id, .. } => (K::CommandEncoder, raw_id(id)),
+        _ => return None,
+    })
+}
+
+/// Returns the resource destroyed by the `action`, if any.
+fn destroyed_resource(action: &trace::Action) -> Option<(ResourceKind, RawId)> {
+    use wgc::device::trace::Action as A;
+    use ResourceKind as K;
+    Some(match *action {
+        A::DestroyBuffer(id) => (K::Buffer, raw_id(id)),
+        A::DestroyTexture(id) => (K::Texture, raw_id(id)),
+        A::DestroyTextureView(id) => (K::TextureView, raw_id(id)),
+        A::DestroySampler(id) => (K::Sampler, raw_id(id)),
+        A::DestroyBindGroupLayout(id) => (K::BindGroupLayout, raw_id(id)),
+        A::DestroyPipelineLayout(id) => (K::PipelineLayout, raw_id(id)),
+        A::DestroyBindGroup(id) => (K::BindGroup, raw_id(id)),
+        A::DestroyShaderModule(id) => (K::ShaderModule, raw_id(id)),
+        A::DestroyComputePipeline(id) => (K::ComputePipeline, raw_id(id)),
+        A::DestroyRenderPipeline(id) => (K::RenderPipeline, raw_id(id)),
+        A::DestroyRenderBundle(id) => (K::RenderBundle, raw_id(id)),
+        A::DestroyQuerySet(id) => (K::QuerySet, raw_id(id)),
+        A::DropCommandEncoder(id) => (K::CommandEncoder, raw_id(id)),
+        _ => return None,
+    })
+}
+
+/// Returns the action destroying the resource `id` of the `kind`,
+/// if resources of that kind are destroyed explicitly.
+fn destroy_action(kind: ResourceKind, id: RawId, backend: wgt::Backend) -> Option<trace::Action> {
+    use wgc::device::trace::Action as A;
+    use ResourceKind as K;
+    let (index, epoch) = id;
+    Some(match kind {
+        K::Buffer => A::DestroyBuffer(wgc::id::TypedId::zip(index, epoch, backend)),
+        K::Texture => A::DestroyTexture(wgc::id::TypedId::zip(index, epoch, backend)),
+        K::TextureView => A::DestroyTextureView(wgc::id::TypedId::zip(index, epoch, backend)),
+        K::Sampler => A::DestroySampler(wgc::id::TypedId::zip(index, epoch, backend)),
+        K::BindGroupLayout => {
+            A::DestroyBindGroupLayout(wgc::id::TypedId::zip(index, epoch, backend))
+        }
+        K::PipelineLayout => A::DestroyPipelineLayout(wgc::id::TypedId::zip(index, epoch, backend)),
+        K::BindGroup => A::DestroyBindGroup(wgc::id::TypedId::zip(index, epoch, backend)),
+        K::ShaderModule => A::DestroyShaderModule(wgc::id::TypedId::zip(index, epoch, backend)),
+        K::ComputePipeline => {
+            A::DestroyComputePipeline(wgc::id::TypedId::zip(index, epoch, backend))
+        }
+        K::RenderPipeline => A::DestroyRenderPipeline(wgc::id::TypedId::zip(index, epoch, backend)),
+        K::RenderBundle => A::DestroyRenderBundle(wgc::id::TypedId::zip(index, epoch, backend)),
+        K::QuerySet => A::DestroyQuerySet(wgc::id::TypedId::zip(index, epoch, backend)),
+        K::SwapChain | K::CommandEncoder => return None,
+    })
+}
+
+/// Moves the destruction of each resource of a trace right after the last
+/// action referring to it, destroying the leaked resources as well, to
+/// reproduce the issues of resources that are released early.
+///
+/// The core keeps the resources used by others alive, so only the direct
+/// references count: a buffer can be destroyed once the bind groups using
+/// it are created. The views acquired from swap chains are left alone,
+/// since presenting releases them. A buffer mapped by `MapBuffer` is
+/// destroyed before the mapping completes, unless it's used afterwards.
+pub fn destroy_after_last_use(actions: Vec<trace::Action>) -> Vec<trace::Action> {
+    let mut player = DryRunPlayer::new();
+    player.record_last_use = true;
+    let mut swap_chain_views = HashSet::new();
+    for action in actions.iter() {
+        player.process(action);
+        if let trace::Action::GetSwapChainTexture { id: Some(id), .. } = *action {
+            swap_chain_views.insert(raw_id(id));
+        }
+    }
+    let mut last_uses = player
+        .last_use
+        .into_iter()
+        .filter(|&((kind, id), _)| {
+            kind != ResourceKind::TextureView || !swap_chain_views.contains(&id)
+        })
+        .filter_map(|((kind, id), (index, backend))| {
+            destroy_action(kind, id, backend).map(|action| (index, kind, id, action))
+        })
+        .collect::<Vec<_>>();
+    // the destructions following the same action are ordered by resource
+    last_uses.sort_by_key(|&(index, kind, id, _)| (index, kind, id));
+
+    let mut destroys = last_uses.into_iter().peekable();
+    let mut result = Vec::with_capacity(actions.len());
+    for (index, action) in actions.into_iter().enumerate() {
+        if !is_resource_destroy(&action) {
+            result.push(action);
+        }
+        while destroys.peek().map_or(false, |&(last, ..)| last == index) {
+            result.push(destroys.next().unwrap().3);
+        }
+    }
+    result
+}
+
+fn is_destroy(action: &trace::Action) -> bool {
+    destroyed_resource(action).is_some()
+}
+
+/// Returns true if the `action` creates a resource that doesn't depend on
+/// anything but the resources created before it, and is slow enough to be
+/// worth creating on another thread, see `GlobalPlay::process_batch`.
+pub fn is_concurrent_creation(action: &trace::Action) -> bool {
+    match *action {
+        trace::Action::CreateShaderModule { .. }
+        | trace::Action::CreateComputePipeline { .. }
+        | trace::Action::CreateRenderPipeline { .. } => true,
+        _ => false,
+    }
+}
+
+/// Returns true if the `action` submits work, which only refers to the
+/// resources created before it, or creates an encoder for such work,
+/// see `GlobalPlay::process_batch`.
+pub fn is_submission(action: &trace::Action) -> bool {
+    match *action {
+        trace::Action::CreateCommandEncoder { .. } | trace::Action::Submit(..) => true,
+        _ => false,
+    }
+}
+
+/// Returns true if the `next` action can be replayed in the same
+/// `GlobalPlay::process_batch` as the `first` one, according to the `config`.
+pub fn fits_batch(config: &PlayerConfig, first: &trace::Action, next: &trace::Action) -> bool {
+    if config.parallel_creation && is_concurrent_creation(first) {
+        is_concurrent_creation(next)
+    } else if config.parallel_encoding && is_submission(first) {
+        is_submission(next)
+    } else {
+        false
+    }
+}
+
+/// Does the bookkeeping of an `action` that is about to be replayed,
+/// returning its index, or `None` if the step hook skips it.
+fn begin_action(
+    action: &trace::Action,
+    state: &mut PlaybackState,
+) -> Result<Option<usize>, PlayerError> {
+    let action_index = state.action_index;
+    state.action_index += 1;
+    let name = action_name(action);
+    if let Some(StepHook(ref mut hook)) = state.step_hook {
+        match hook(action_index, action) {
+            StepControl::Continue => {}
+            StepControl::Skip => return Ok(None),
+            StepControl::Abort => {
+                return Err(PlayerError {
+                    action_index,
+                    action: name,
+                    cause: "replay was aborted".into(),
+                })
+            }
+        }
+    }
+    if let Some(dangling) = state.live.process_checked(action) {
+        return Err(PlayerError {
+            action_index,
+            action: name,
+            cause: Box::new(dangling),
+        });
+    }
+    if let Some((_, (index, _epoch))) = created_resource(action) {
+        state.index_count = state.index_count.max(index + 1);
+    }
+    Ok(Some(action_index))
+}
+
+pub trait GlobalPlay {
+    fn encode_commands<B: wgc::hub::GfxBackend>(
+        &self,
+        encoder: wgc::id::CommandEncoderId,
+        commands: Vec<trace::Command>,
+    ) -> Result<wgc::id::CommandBufferId, ActionError>;
+    /// Replays a single `action`, once `process` has checked it.
+    fn replay_action<B: wgc::hub::GfxBackend>(
+        &self,
+        device: wgc::id::DeviceId,
+        action: trace::Action,
+        dir: &Path,
+        state: &mut PlaybackState,
+    ) -> Result<(), ActionError>;
+    /// Replays an `action`, after checking that the resources it refers to
+    /// are alive and asking the step hook of the `state` about it.
+    fn process<B: wgc::hub::GfxBackend>(
+        &self,
+        device: wgc::id::DeviceId,
+        action: trace::Action,
+        dir: &Path,
+        state: &mut PlaybackState,
+    ) -> Result<(), PlayerError> {
+        let action_index = match begin_action(&action, state)? {
+            Some(index) => index,
+            None => return Ok(()),
+        };
+        let name = action_name(&action);
+        let start = state.stats.as_ref().map(|_| Instant::now());
+        let result = self.replay_action::<B>(device, action, dir, state);
+        if let (Some(stats), Some(start)) = (state.stats.as_mut(), start) {
+            stats.record(action_index, name, start.elapsed());
+        }
+        result.map_err(|cause| PlayerError {
+            action_index,
+            action: name,
+            cause,
+        })
+    }
+    /// Replays a batch of consecutive `actions`.
+    ///
+    /// If `PlayerConfig::parallel_creation` is enabled and the batch only
+    /// creates shader modules and pipelines, as told by `is_concurrent_creation`,
+    /// they are created on a thread pool: first all the shader modules, then
+    /// all the pipelines. If `PlayerConfig::parallel_encoding` is enabled and
+    /// the batch only has submissions, their command buffers are encoded on a
+    /// thread pool, then submitted in order. Otherwise, the actions are
+    /// replayed one by one.
+    /// The IDs are taken from the trace, so they don't depend on the order
+    /// of creation. Anything else, like a write or a destruction, has to
+    /// be passed in a batch of its own, which keeps it ordered with respect
+    /// to the batches around it. `fits_batch` tells which actions can be
+    /// batched together.
+    ///
+    /// An action that fails doesn't stop the other ones of the batch, and
+    /// the errors of all of them are returned.
+    ///
+    /// By default, the actions are always replayed one by one.
+    fn process_batch<B: wgc::hub::GfxBackend>(
+        &self,
+        device: wgc::id::DeviceId,
+        actions: Vec<trace::Action>,
+        dir: &Path,
+        state: &mut PlaybackState,
+    ) -> Result<(), Vec<PlayerError>> {
+        process_sequentially::<B, _>(self, device, actions, dir, state)
+    }
+    /// Replays the `range` of actions of a trace loaded from `dir`.
+    ///
+    /// The actions before the range are replayed without submitting any work,
+    /// so that the resources used by the range exist. Only the `Destroy*`
+    /// actions are replayed after the range. Indices don't count the leading
+    /// `Action::Init`, which is skipped.
+    fn play_range<B: wgc::hub::GfxBackend>(
+        &self,
+        device: wgc::id::DeviceId,
+        mut actions: Vec<trace::Action>,
+        dir: &Path,
+        range: Range<usize>,
+        config: PlayerConfig,
+        observer: Option<&dyn PlaybackObserver>,
+    ) -> Result<(), PlayerError> {
+        if let Some(trace::Action::Init { .. }) = actions.first() {
+            actions.remove(0);
+        }
+        if range.start > range.end || range.end > actions.len() {
+            return Err(PlayerError {
+                action_index: range.start,
+                action: actions.get(range.start).map_or("None", action_name),
+                cause: format!("invalid range {:?} of {} actions", range, actions.len()).into(),
+            });
+        }
+
+        let mut state = PlaybackState::new(config, &actions);
+        let mut current_phase = None;
+        for (index, action) in actions.into_iter().enumerate() {
+            let phase = if index < range.start {
+                PlaybackPhase::Setup
+            } else if index < range.end {
+                PlaybackPhase::Replay
+            } else {
+                PlaybackPhase::Teardown
+            };
+            if current_phase != Some(phase) {
+                current_phase = Some(phase);
+                if let Some(observer) = observer {
+                    observer.on_phase(phase);
+                }
+            }
+            // the encoders of the submissions that are skipped are only dropped
+            let action = match action {
+                trace::Action::Submit(_, _, encoder, _) if phase != PlaybackPhase::Replay => {
+                    trace::Action::DropCommandEncoder(encoder)
+                }
+                action => action,
+            };
+            let skip = match phase {
+                PlaybackPhase::Setup => match action {
+                    trace::Action::WriteTimestamp { .. }
+                    | trace::Action::ResolveQuerySet { .. }
+                    | trace::Action::MapBuffer { .. }
+                    | trace::Action::UnmapBuffer(_)
+                    | trace::Action::Poll { .. } => true,
+                    _ => false,
+                },
+                PlaybackPhase::Replay => false,
+                PlaybackPhase::Teardown => !is_destroy(&action),
+            };
+            if skip {
+                continue;
+            }
+            if phase == PlaybackPhase::Replay {
+                if let Some(observer) = observer {
+                    observer.on_action(index, &action);
+                    if let trace::Action::FrameBoundary { frame_index } = action {
+                        observer.on_frame_boundary(frame_index);
+                    }
+                }
+            }
+            let submission_index = match action {
+                trace::Action::Submit(submission_index, ..) => Some(submission_index),
+                _ => None,
+            };
+            state.action_index = index;
+            match self.process::<B>(device, action, dir, &mut state) {
+                // resources created after the range don't exist
+                Err(ref e) if phase == PlaybackPhase::Teardown && e.dangling_id().is_some() => {}
+                result => result?,
+            }
+            if let (Some(observer), Some(submission_index)) = (observer, submission_index) {
+                observer.on_after_submit(submission_index);
+            }
+        }
+        Ok(())
+    }
+    /// Replays the `frames` of a trace loaded from `dir`, as split by
+    /// `frame_range`, in the same way as `play_range` does.
+    fn replay_range<B: wgc::hub::GfxBackend>(
+        &self,
+        device: wgc::id::DeviceId,
+        mut actions: Vec<trace::Action>,
+        dir: &Path,
+        frames: Range<usize>,
+        config: PlayerConfig,
+        observer: Option<&dyn PlaybackObserver>,
+    ) -> Result<(), PlayerError> {
+        if let Some(trace::Action::Init { .. }) = actions.first() {
+            actions.remove(0);
+        }
+        let range = frame_range(&actions, frames);
+        log::info!("Replaying actions {:?}", range);
+        self.play_range::<B>(device, actions, dir, range, config, observer)
+    }
+
+    /// Replays the `actions` of a trace that may have several devices.
+    ///
+    /// Each `Action::Init` switches the replay to its device, which the
+    /// actions following it belong to. The first time a device is seen,
+    /// `create_device` is called with its `Action::Init` to create it,
+    /// and returns the ID of the created device. If `PlayerConfig::backend`
+    /// is set, the devices are switched to that backend first.
+    fn play_devices(
+        &self,
+        actions: impl IntoIterator<Item = trace::Action>,
+        dir: &Path,
+        state: &mut PlaybackState,
+        mut create_device: impl FnMut(trace::Action) -> Result<wgc::id::DeviceId, ActionError>,
+    ) -> Result<(), PlayerError> {
+        let mut device_ids = HashMap::new();
+        for mut action in actions {
+            if let Some(backend) = state.config.backend {
+                substitute_backend(&mut action, backend);
+            }
+            if let trace::Action::Init {
+                device, backend, ..
+            } = action
+            {
+                if state.switch_device(device, backend) {
+                    check_backend(backend).map_err(|e| PlayerError {
+                        action_index: state.action_index,
+                        action: "Init",
+                        cause: Box::new(e),
+                    })?;
+                    let created = create_device(action).map_err(|cause| PlayerError {
+                        action_index: state.action_index,
+                        action: "Init",
+                        cause,
+                    })?;
+                    device_ids.insert(device, created);
+                }
+                continue;
+            }
+            let device = match state.current_device() {
+                Some(device) => match device_ids.get(&device) {
+                    Some(&created) => created,
+                    None => {
+                        return Err(PlayerError {
+                            action_index: state.action_index,
+                            action: action_name(&action),
+                            cause: format!("device {:?} wasn't created by this replay", device)
+                                .into(),
+                        })
+                    }
+                },
+                None => {
+                    return Err(PlayerError {
+                        action_index: state.action_index,
+                        action: action_name(&action),
+                        cause: "no Action::Init before the action".into(),
+                    })
+                }
+            };
+            let name = action_name(&action);
+            gfx_select!(device => self.process(device, action, dir, state)).map_err(|e| {
+                PlayerError {
+                    action_index: state.action_index,
+                    action: name,
+                    cause: Box::new(e),
+                }
+            })??;
+        }
+        Ok(())
+    }
+}
+
+impl GlobalPlay for wgc::hub::Global<IdentityPassThroughFactory> {
+    fn encode_commands<B: wgc::hub::GfxBackend>(
+        &self,
+        encoder: wgc::id::CommandEncoderId,
+        commands: Vec<trace::Command>,
+    ) -> Result<wgc::id::CommandBufferId, ActionError> {
+        for command in commands {
+            match command {
+                trace::Command::CopyBufferToBuffer {
+                    src,
+                    src_offset,
+                    dst,
+                    dst_offset,
+                    size,
+                } => self.command_encoder_copy_buffer_to_buffer::<B>(
+                    encoder, src, src_offset, dst, dst_offset, size,
+                )?,
+                trace::Command::CopyBufferToTexture { src, dst, size } => self
+                    .command_encoder_copy_buffer_to_texture::<B>(encoder, &src, &dst, &size)
+                    .map_err(copy_error)?,
+                trace::Command::CopyTextureToBuffer { src, dst, size } => self
+                    .command_encoder_copy_texture_to_buffer::<B>(encoder, &src, &dst, &size)
+                    .map_err(copy_error)?,
+                trace::Command::CopyTextureToTexture { src, dst, size } => self
+                    .command_encoder_copy_texture_to_texture::<B>(encoder, &src, &dst, &size)
+                    .map_err(copy_error)?,
+                trace::Command::ClearBuffer { dst, offset, size } => {
+                    self.command_encoder_clear_buffer::<B>(encoder, dst, offset, size)?
+                }
+                trace::Command::ClearTexture {
+                    dst,
+                    subresource_range,
+                } => self.command_encoder_clear_texture::<B>(encoder, dst, &subresource_range)?,
+                trace::Command::RunComputePass {
+                    base,
+                    timestamp_writes,
+                } => {
+                    self.command_encoder_run_compute_pass_impl::<B>(
+                        encoder,
+                        base.as_ref(),
+                        timestamp_writes.as_ref(),
+                    )?;
+                }
+                trace::Command::RunRenderPass {
+                    base,
+                    target_colors,
+                    target_depth_stencil,
+                    timestamp_writes,
+                } => {
+                    self.command_encoder_run_render_pass_impl::<B>(
+                        encoder,
+                        base.as_ref(),
+                        &target_colors,
+                        target_depth_stencil.as_ref(),
+                        timestamp_writes.as_ref(),
+                    )?;
+                }
+                trace::Command::PushDebugGroup(label) => {
+                    self.command_encoder_push_debug_group::<B>(encoder, &label)
+                }
+                trace::Command::PopDebugGroup => self.command_encoder_pop_debug_group::<B>(encoder),
+                trace::Command::InsertDebugMarker(label) => {
+                    self.command_encoder_insert_debug_marker::<B>(encoder, &label)
+                }
+                trace::Command::WriteTimestamp {
+                    query_set,
+                    query_index,
+                    pipeline_stage,
+                } => self.command_encoder_write_timestamp::<B>(
+                    encoder,
+                    query_set,
+                    query_index,
+                    wgc::PipelineStage::from_bits_truncate(pipeline_stage),
+                )?,
+                trace::Command::ResolveQuerySet {
+                    query_set,
+                    first_query,
+                    query_count,
+                    destination,
+                    destination_offset,
+                    destination_stride,
+                    wait,
+                } => self.command_encoder_resolve_query_set::<B>(
+                    encoder,
+                    query_set,
+                    first_query,
+                    query_count,
+                    destination,
+                    destination_offset,
+                    destination_stride,
+                    wait,
+                )?,
+                trace::Command::ResetQuerySet {
+                    query_set,
+                    first_query,
+                    query_count,
+                } => self.command_encoder_reset_query_set::<B>(
+                    encoder,
+                    query_set,
+                    first_query,
+                    query_count,
+                )?,
+            }
+        }
+        let comb =
+            self.command_encoder_finish::<B>(encoder, &wgt::CommandBufferDescriptor { todo: 0 })?;
+        Ok(comb)
+    }
+
+    fn replay_action<B: wgc::hub::GfxBackend>(
+        &self,
+        device: wgc::id::DeviceId,
+        action: trace::Action,
+        dir: &Path,
+        state: &mut PlaybackState,
+    ) -> Result<(), ActionError> {
+        process_action::<B>(self, device, action, dir, state)
+    }
+
+    fn process_batch<B: wgc::hub::GfxBackend>(
+        &self,
+        device: wgc::id::DeviceId,
+        actions: Vec<trace::Action>,
+        dir: &Path,
+        state: &mut PlaybackState,
+    ) -> Result<(), Vec<PlayerError>> {
+        if state.config.parallel_encoding && actions.iter().all(is_submission) {
+            return batch_result(submit_concurrently::<B>(self, device, actions, state));
+        }
+        if !state.config.parallel_creation || !actions.iter().all(is_concurrent_creation) {
+            return process_sequentially::<B, _>(self, device, actions, dir, state);
+        }
+
+        // pipelines may only refer to the shader modules created before them
+        let mut modules = Vec::new();
+        let mut pipelines = Vec::new();
+        let mut errors = Vec::new();
+        for action in actions {
+            match begin_action(&action, state) {
+                Ok(Some(index)) => match action {
+                    trace::Action::CreateShaderModule { .. } => modules.push((index, action)),
+                    _ => pipelines.push((index, action)),
+                },
+                Ok(None) => {}
+                Err(e) => errors.push(e),
+            }
+        }
+        self.device_maintain_ids::<B>(device);
+        for group in vec![modules, pipelines] {
+            let results = group
+                .into_par_iter()
+                .map(|(index, action)| {
+                    let name = action_name(&action);
+                    let start = Instant::now();
+                    let result = create_concurrently::<B>(self, device, action, dir);
+                    (index, name, start.elapsed(), result)
+                })
+                .collect::<Vec<_>>();
+            for (action_index, name, duration, result) in results {
+                if let Some(ref mut stats) = state.stats {
+                    stats.record(action_index, name, duration);
+                }
+                if let Err(cause) = result {
+                    errors.push(PlayerError {
+                        action_index,
+                        action: name,
+                        cause,
+                    });
+                }
+            }
+        }
+        batch_result(errors)
+    }
+}
+
+/// Replays the `actions` of a batch one by one, see `GlobalPlay::process_batch`.
+fn process_sequentially<B: wgc::hub::GfxBackend, G: GlobalPlay + ?Sized>(
+    global: &G,
+    device: wgc::id::DeviceId,
+    actions: Vec<trace::Action>,
+    dir: &Path,
+    state: &mut PlaybackState,
+) -> Result<(), Vec<PlayerError>> {
+    let errors = actions
+        .into_iter()
+        .filter_map(|action| global.process::<B>(device, action, dir, state).err())
+        .collect();
+    batch_result(errors)
+}
+
+fn batch_result(errors: Vec<PlayerError>) -> Result<(), Vec<PlayerError>> {
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+/// Creates the command encoder `id` recorded by the trace.
+fn create_command_encoder<B: wgc::hub::GfxBackend>(
+    global: &wgc::hub::Global<IdentityPassThroughFactory>,
+    device: wgc::id::DeviceId,
+    id: wgc::id::CommandEncoderId,
+    desc: &wgt::CommandEncoderDescriptor<Option<String>>,
+) {
+    let label = Label::new(desc.label.as_deref());
+    global.device_create_command_encoder::<B>(device, &desc.map_label(|_| label.as_ptr()), id);
+}
+
+/// Encodes `commands` into a new command buffer of the player's own,
+/// and submits it.
+fn submit_commands<B: wgc::hub::GfxBackend>(
+    global: &wgc::hub::Global<IdentityPassThroughFactory>,
+    device: wgc::id::DeviceId,
+    commands: Vec<trace::Command>,
+    state: &mut PlaybackState,
+) -> Result<(), ActionError> {
+    let encoder = global.device_create_command_encoder::<B>(
+        device,
+        &wgt::CommandEncoderDescriptor { label: ptr::null() },
+        state.alloc_comb_id(device.backend()),
+    );
+    let result = submit_encoder::<B>(global, device, encoder, commands, state);
+    state.free_comb_id(encoder);
+    result
+}
+
+/// Encodes `commands` into the command buffer of `encoder`, and submits it.
+/// The encoder is destroyed if the encoding fails.
+fn submit_encoder<B: wgc::hub::GfxBackend>(
+    global: &wgc::hub::Global<IdentityPassThroughFactory>,
+    device: wgc::id::DeviceId,
+    encoder: wgc::id::CommandEncoderId,
+    commands: Vec<trace::Command>,
+    state: &mut PlaybackState,
+) -> Result<(), ActionError> {
+    match global.encode_commands::<B>(encoder, commands) {
+        Ok(comb) => submit_command_buffer::<B>(global, device, comb, state),
+        Err(e) => {
+            global.command_encoder_destroy::<B>(encoder);
+            Err(e)
+        }
+    }
+}
+
+/// Submits the command buffer `comb` encoded by the player, or only
+/// destroys it if `PlayerConfig::validate_only` is set.
+fn submit_command_buffer<B: wgc::hub::GfxBackend>(
+    global: &wgc::hub::Global<IdentityPassThroughFactory>,
+    device: wgc::id::DeviceId,
+    comb: wgc::id::CommandBufferId,
+    state: &mut PlaybackState,
+) -> Result<(), ActionError> {
+    if state.config.validate_only {
+        global.command_buffer_destroy::<B>(comb);
+    } else {
+        // the command buffer is unregistered by a successful submission
+        if let Err(e) = global.queue_submit::<B>(device, &[comb]) {
+            global.command_buffer_destroy::<B>(comb);
+            return Err(e.into());
+        }
+    }
+    Ok(())
+}
+
+/// Checks that submission `index` doesn't go back in time.
+fn check_submission_index(index: usize, state: &mut PlaybackState) -> Result<(), ActionError> {
+    // command buffers of a single submission share the index
+    if let Some(last) = state.last_submission_index {
+        if index < last {
+            return Err(
+                format!("submission {} is recorded after submission {}", index, last).into(),
+            );
+        }
+    }
+    state.last_submission_index = Some(index);
+    Ok(())
+}
+
+/// Replays a batch of submissions, encoding their command buffers on
+/// a thread pool, see `GlobalPlay::process_batch`. Returns the errors of
+/// the submissions that failed.
+fn submit_concurrently<B: wgc::hub::GfxBackend>(
+    global: &wgc::hub::Global<IdentityPassThroughFactory>,
+    device: wgc::id::DeviceId,
+    actions: Vec<trace::Action>,
+    state: &mut PlaybackState,
+) -> Vec<PlayerError> {
+    let mut encoders = Vec::new();
+    let mut errors = Vec::new();
+    for action in actions {
+        let action_index = match begin_action(&action, state) {
+            Ok(Some(action_index)) => action_index,
+            Ok(None) => continue,
+            Err(e) => {
+                errors.push(e);
+                continue;
+            }
+        };
+        match action {
+            trace::Action::CreateCommandEncoder { id, desc } => {
+                create_command_encoder::<B>(global, device, id, &desc);
+            }
+            trace::Action::Submit(index, _queue, encoder, commands) => {
+                match check_submission_index(index, state) {
+                    Ok(()) => encoders.push((action_index, encoder, commands)),
+                    Err(cause) => {
+                        global.command_encoder_destroy::<B>(encoder);
+                        errors.push(PlayerError {
+                            action_index,
+                            action: "Submit",
+                            cause,
+                        });
+                    }
+                }
+            }
+            action => errors.push(PlayerError {
+                action_index,
+                action: action_name(&action),
+                cause: Box::new(NotConcurrent {
+                    action: action_name(&action),
+                }),
+            }),
+        }
+    }
+
+    let results = encoders
+        .into_par_iter()
+        .map(|(action_index, encoder, commands)| {
+            let start = Instant::now();
+            let result = global.encode_commands::<B>(encoder, commands);
+            (action_index, encoder, start.elapsed(), result)
+        })
+        .collect::<Vec<_>>();
+
+    // like in the sequential replay, the submissions after a failed one
+    // are still submitted
+    for (action_index, encoder, duration, result) in results {
+        let start = Instant::now();
+        let result = match result {
+            Ok(comb) => submit_command_buffer::<B>(global, device, comb, state),
+            Err(cause) => {
+                global.command_encoder_destroy::<B>(encoder);
+                Err(cause)
+            }
+        };
+        if let Some(ref mut stats) = state.stats {
+            stats.record(action_index, "Submit", duration + start.elapsed());
+        }
+        if let Err(cause) = result {
+            errors.push(PlayerError {
+                action_index,
+                action: "Submit",
+                cause,
+            });
+        }
+    }
+    errors
+}
+
+/// Action given to one of the concurrent paths of `GlobalPlay::process_batch`,
+/// which it can't replay.
+#[derive(Clone, Debug)]
+pub struct NotConcurrent {
+    /// Name of the action variant.
+    pub action: &'static str,
+}
+
+impl fmt::Display for NotConcurrent {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} can't be replayed concurrently", self.action)
+    }
+}
+
+impl Error for NotConcurrent {}
+
+/// Creates a shader module or a pipeline, which can be done concurrently
+/// with other actions passing `is_concurrent_creation`.
+fn create_concurrently<B: wgc::hub::GfxBackend>(
+    global: &wgc::hub::Global<IdentityPassThroughFactory>,
+    device: wgc::id::DeviceId,
+    action: trace::Action,
+    dir: &Path,
+) -> Result<(), ActionError> {
+    use wgc::device::trace::Action as A;
+    match action {
+        A::CreateShaderModule { id, source, .. } => match source {
+            trace::ShaderModuleSource::SpirV(data) => {
+                let byte_vec = read_data(&dir.join(data))?;
+                let spv = byte_vec
+                    .chunks(4)
+                    .map(|c| u32::from_le_bytes([c[0], c[1], c[2], c[3]]))
+                    .collect::<Vec<_>>();
+                global.device_create_shader_module::<B>(
+                    device,
+                    wgc::pipeline::ShaderModuleSource::SpirV(&spv),
+                    id,
+                );
+            }
+            trace::ShaderModuleSource::Wgsl(data) => {
+                let code = String::from_utf8(read_data(&dir.join(data))?)?;
+                global.device_create_shader_module::<B>(
+                    device,
+                    wgc::pipeline::ShaderModuleSource::Wgsl(&code),
+                    id,
+                );
+            }
+        },
+        A::CreateComputePipeline { id, desc } => {
+            let compute_stage = desc.compute_stage.to_core()?;
+            global.device_maintain_ids::<B>(device);
+            global.device_create_compute_pipeline::<B>(
+                device,
+                &wgc::pipeline::ComputePipelineDescriptor {
+                    layout: desc.layout,
+                    compute_stage,
+                },
                 id,
-                label,
-                layout_id,
-                entries,
+            )?;
+        }
+        A::CreateRenderPipeline { id, desc } => {
+            let vertex_stage = desc.vertex_stage.to_core()?;
+            let fragment_stage = match desc.fragment_stage {
+                Some(ref fs) => Some(fs.to_core()?),
+                None => None,
+            };
+            let vertex_buffers = desc
+                .vertex_state
+                .vertex_buffers
+                .iter()
+                .map(|vb| wgt::VertexBufferDescriptor {
+                    stride: vb.stride,
+                    step_mode: vb.step_mode,
+                    attributes: &vb.attributes,
+                })
+                .collect::<Vec<_>>();
+            global.device_maintain_ids::<B>(device);
+            global.device_create_render_pipeline::<B>(
+                device,
+                &wgc::pipeline::RenderPipelineDescriptor {
+                    layout: desc.layout,
+                    vertex_stage,
+                    fragment_stage,
+                    primitive_topology: desc.primitive_topology,
+                    rasterization_state: desc.rasterization_state,
+                    color_states: &desc.color_states,
+                    depth_stencil_state: desc.depth_stencil_state,
+                    vertex_state: wgt::VertexStateDescriptor {
+                        index_format: desc.vertex_state.index_format,
+                        vertex_buffers: &vertex_buffers,
+                    },
+                    sample_count: desc.sample_count,
+                    sample_mask: desc.sample_mask,
+                    alpha_to_coverage_enabled: desc.alpha_to_coverage_enabled,
+                },
+                id,
+            )?;
+        }
+        action => {
+            return Err(Box::new(NotConcurrent {
+                action: action_name(&action),
+            }))
+        }
+    }
+    Ok(())
+}
+
+fn process_action<B: wgc::hub::GfxBackend>(
+    global: &wgc::hub::Global<IdentityPassThroughFactory>,
+    device: wgc::id::DeviceId,
+    action: trace::Action,
+    dir: &Path,
+    state: &mut PlaybackState,
+) -> Result<(), ActionError> {
+    use wgc::device::trace::Action as A;
+    match action {
+        A::Init { .. } => {
+            return Err("unexpected Action::Init: devices are switched by the caller".into())
+        }
+        // The surfaces of the recorded application are never presented to.
+        A::CreateSurface { id, backend } => {
+            log::debug!("Ignoring {:?} surface {:?}", backend, id);
+        }
+        // Swap chains that reach the player have no window to present to.
+        A::CreateSwapChain { id, desc } => {
+            if !state.config.use_headless_swapchain {
+                log::warn!("No window for swap chain {:?}, rendering off-screen", id);
+            }
+            create_headless_swap_chain::<B>(global, device, id, &desc, state);
+        }
+        A::PresentSwapChain(id) => present_headless_swap_chain::<B>(global, device, id, state)?,
+        A::CreateBuffer { id, desc } => {
+            let label = Label::new(desc.label.as_deref());
+            global.device_maintain_ids::<B>(device);
+            global.device_create_buffer::<B>(device, &desc.map_label(|_| label.as_ptr()), id);
+        }
+        A::DestroyBuffer(id) => {
+            global.buffer_destroy::<B>(id);
+        }
+        A::CreateTexture { id, desc } => {
+            state.texture_descs.insert(id, (desc.format, desc.size));
+            let label = Label::new(desc.label.as_deref());
+            global.device_maintain_ids::<B>(device);
+            global.device_create_texture::<B>(device, &desc.map_label(|_| label.as_ptr()), id);
+        }
+        A::DestroyTexture(id) => {
+            state.texture_descs.remove(&id);
+            global.texture_destroy::<B>(id);
+        }
+        A::CreateTextureView {
+            id,
+            parent_id,
+            desc,
+        } => {
+            let label = desc
+                .as_ref()
+                .map_or(Label(None), |d| Label::new(d.label.as_deref()));
+            global.device_maintain_ids::<B>(device);
+            global.texture_create_view::<B>(
+                parent_id,
+                desc.map(|d| d.map_label(|_| label.as_ptr())).as_ref(),
+                id,
+            );
+        }
+        A::DestroyTextureView(id) => {
+            global.texture_view_destroy::<B>(id);
+        }
+        A::CreateSampler { id, desc } => {
+            let label = Label::new(desc.label.as_deref());
+            global.device_maintain_ids::<B>(device);
+            global.device_create_sampler::<B>(device, &desc.map_label(|_| label.as_ptr()), id);
+        }
+        A::DestroySampler(id) => {
+            global.sampler_destroy::<B>(id);
+        }
+        A::GetSwapChainTexture { id, parent_id }
+            if state.headless_swap_chains.contains_key(&parent_id) =>
+        {
+            if let Some(id) = id {
+                let sc = state.headless_swap_chains.get_mut(&parent_id).unwrap();
+                global.device_maintain_ids::<B>(device);
+                global.texture_create_view::<B>(sc.texture, None, id);
+                sc.acquired_view = Some(id);
+            }
+        }
+        A::GetSwapChainTexture { id, parent_id } => {
+            if let Some(id) = id {
+                global
+                    .swap_chain_get_current_texture_view::<B>(parent_id, id)?
+                    .view_id
+                    .ok_or("swap chain has no texture to present")?;
+            }
+        }
+        A::CreateBindGroupLayout {
+            id,
+            ref label,
+            ref entries,
+        } => {
+            global.device_create_bind_group_layout::<B>(
+                device,
+                &wgt::BindGroupLayoutDescriptor {
+                    label: label.as_deref(),
+                    entries,
+                },
+                id,
+            )?;
+        }
+        A::DestroyBindGroupLayout(id) => {
+            global.bind_group_layout_destroy::<B>(id);
+        }
+        A::CreatePipelineLayout {
+            id,
+            bind_group_layouts,
+            push_constant_ranges,
+        } => {
+            global.device_maintain_ids::<B>(device);
+            global.device_create_pipeline_layout::<B>(
+                device,
+                &wgt::PipelineLayoutDescriptor {
+                    bind_group_layouts: &bind_group_layouts,
+                    push_constant_ranges: &push_constant_ranges,
+                },
+                id,
+            )?;
+        }
+        A::DestroyPipelineLayout(id) => {
+            global.pipeline_layout_destroy::<B>(id);
+        }
+        A::CreateBindGroup {
+            id,
+            label,
+            layout_id,
+            entries,
+        } => {
+            use wgc::binding_model as bm;
+            let entry_vec = entries
+                .iter()
+                .map(|(binding, res)| wgc::binding_model::BindGroupEntry {
+                    binding: *binding,
+                    resource: match *res {
+                        trace::BindingResource::Buffer { id, offset, size } => {
+                            bm::BindingResource::Buffer(bm::BufferBinding {
+                                buffer_id: id,
+                                offset,
+                                size,
+                            })
+                        }
+                        trace::BindingResource::Sampler(id) => bm::BindingResource::Sampler(id),
+                        trace::BindingResource::TextureView(id) => {
+                            bm::BindingResource::TextureView(id)
+                        }
+                        trace::BindingResource::TextureViewArray(ref binding_array) => {
+                            bm::BindingResource::TextureViewArray(binding_array)
+                        }
+                    },
+                })
+                .collect::<Vec<_>>();
+            global.device_maintain_ids::<B>(device);
+            global.device_create_bind_group::<B>(
+                device,
+                &wgc::binding_model::BindGroupDescriptor {
+                    label: label.as_deref(),
+                    layout: layout_id,
+                    entries: &entry_vec,
+                },
+                id,
+            )?;
+        }
+        A::DestroyBindGroup(id) => {
+            global.bind_group_destroy::<B>(id);
+        }
+        action @ A::CreateShaderModule { .. }
+        | action @ A::CreateComputePipeline { .. }
+        | action @ A::CreateRenderPipeline { .. } => {
+            create_concurrently::<B>(global, device, action, dir)?
+        }
+        A::DestroyShaderModule(id) => {
+            global.shader_module_destroy::<B>(id);
+        }
+        A::DestroyComputePipeline(id) => {
+            global.compute_pipeline_destroy::<B>(id);
+        }
+        A::DestroyRenderPipeline(id) => {
+            global.render_pipeline_destroy::<B>(id);
+        }
+        A::CreateRenderBundle { id, desc, base } => {
+            let label = Label::new(desc.label.as_deref());
+            let bundle = wgc::command::RenderBundleEncoder::new(
+                &wgt::RenderBundleEncoderDescriptor {
+                    label: None,
+                    color_formats: &desc.color_formats,
+                    depth_stencil_format: desc.depth_stencil_format,
+                    sample_count: desc.sample_count,
+                },
+                device,
+                Some(base),
+            )?;
+            global.device_maintain_ids::<B>(device);
+            global.render_bundle_encoder_finish::<B>(
+                bundle,
+                &wgt::RenderBundleDescriptor {
+                    label: label.as_ptr(),
+                },
+                id,
+            )?;
+        }
+        A::DestroyRenderBundle(id) => {
+            global.render_bundle_destroy::<B>(id);
+        }
+        A::CreateQuerySet {
+            id,
+            desc,
+        } => {
+            let type_ = match &desc.type_ {
+                trace::QueryType::Occlusion =>
+                    wgt::QueryType::Occlusion,
+                trace::QueryType::PipelineStatistics(pipeline_statistics) =>
+                    wgt::QueryType::PipelineStatistics(&pipeline_statistics),
+                trace::QueryType::Timestamp =>
+                    wgt::QueryType::Timestamp,
+            };
+
+            global.device_maintain_ids::<B>(device);
+            global.device_create_query_set::<B>(
+                device,
+                &wgt::QuerySetDescriptor {
+                    type_,
+                    count: desc.count,
+                },
+                id,
+            )?;
+        }
+        A::DestroyQuerySet(id) => {
+            global.query_set_destroy::<B>(id);
+        }
+        A::WriteBuffer {
+            id,
+            data,
+            range,
+            queued,
+            ..
+        } => {
+            let bin = read_data(&dir.join(&data))?;
+            let bin = blob_range(&data, &bin, &range)?;
+            if state.config.validate_only {
+                log::debug!("Skipping write to buffer {:?}", id);
+            } else if queued {
+                global.queue_write_buffer::<B>(device, id, range.start, bin);
+            } else {
+                global.device_wait_for_buffer::<B>(device, id)?;
+                global.device_set_buffer_sub_data::<B>(device, id, range.start, bin);
+            }
+        }
+        A::WriteBufferMany { id, writes, .. } => {
+            for (range, data) in writes {
+                let bin = read_data(&dir.join(&data))?;
+                let bin = blob_range(&data, &bin, &range)?;
+                if state.config.validate_only {
+                    log::debug!("Skipping write to buffer {:?}", id);
+                } else {
+                    global.queue_write_buffer::<B>(device, id, range.start, bin);
+                }
+            }
+        }
+        A::ClearBuffer { id, offset, size } => {
+            if state.config.validate_only {
+                log::debug!("Skipping clear of buffer {:?}", id);
+            } else {
+                global.queue_clear_buffer::<B>(device, id, offset, size);
+            }
+        }
+        A::WriteTexture {
+            to,
+            data,
+            layout,
+            size,
+            ..
+        } => {
+            let bin = read_data(&dir.join(data))?;
+            if state.config.validate_only {
+                log::debug!("Skipping write to texture {:?}", to.texture);
+            } else {
+                global.queue_write_texture::<B>(device, &to, &bin, &layout, &size);
+            }
+        }
+        A::MapBuffer { id, mode, range } => match mode {
+            // written contents are replayed by `WriteBuffer` upon unmapping
+            wgt::MapMode::Write => (),
+            wgt::MapMode::Read => {
+                global.buffer_map_async::<B>(
+                    id,
+                    range,
+                    wgc::resource::BufferMapOperation {
+                        host: mode.into(),
+                        callback: map_callback,
+                        user_data: ptr::null_mut(),
+                    },
+                );
+                state.mapped_buffers.insert(id);
+            }
+        },
+        A::UnmapBuffer(id) => {
+            if state.mapped_buffers.remove(&id) {
+                global.buffer_unmap::<B>(id);
+            }
+        }
+        A::WriteTimestamp {
+            query_set,
+            query_index,
+            pipeline_stage,
+        } => {
+            let command = trace::Command::WriteTimestamp {
+                query_set,
+                query_index,
+                pipeline_stage,
+            };
+            submit_commands::<B>(global, device, vec![command], state)?;
+        }
+        A::ResolveQuerySet {
+            query_set,
+            first_query,
+            query_count,
+            destination,
+            destination_offset,
+            destination_stride,
+            wait,
+        } => {
+            let command = trace::Command::ResolveQuerySet {
+                query_set,
+                first_query,
+                query_count,
+                destination,
+                destination_offset,
+                destination_stride,
+                wait,
+            };
+            submit_commands::<B>(global, device, vec![command], state)?;
+        }
+        A::CreateCommandEncoder { id, desc } => {
+            create_command_encoder::<B>(global, device, id, &desc);
+        }
+        A::DropCommandEncoder(id) => {
+            global.command_encoder_destroy::<B>(id);
+        }
+        A::Submit(index, _queue, encoder, commands) => {
+            check_submission_index(index, state)?;
+            submit_encoder::<B>(global, device, encoder, commands, state)?;
+            let capture_dir = state.config.capture_dir.clone();
+            if let (Some(texture), Some(dir)) = (state.config.capture_output, capture_dir) {
+                if !state.config.validate_only {
+                    let path = dir.join(format!("submit{}.png", index));
+                    capture_output_texture::<B>(global, device, texture, &path, state)?;
+                }
+            }
+        }
+        A::FrameBoundary { frame_index } => {
+            log::debug!("End of frame {}", frame_index);
+        }
+        // the reads of mapped buffers that follow may rely on the work being done
+        A::Poll { force_wait } => global.device_poll::<B>(device, force_wait)?,
+        // the resources of the snapshot are checked by `DryRun`
+        A::ResourceSnapshot { .. } => {}
+    }
+    Ok(())
+}
+
+/// Kind of a resource that actions of a trace refer to.
+#[derive(Clone, Copy, Debug, Hash, Eq, Ord, PartialEq, PartialOrd)]
+pub enum ResourceKind {
+    Buffer,
+    Texture,
+    TextureView,
+    Sampler,
+    SwapChain,
+    BindGroupLayout,
+    PipelineLayout,
+    BindGroup,
+    ShaderModule,
+    ComputePipeline,
+    RenderPipeline,
+    RenderBundle,
+    QuerySet,
+    CommandEncoder,
+}
+
+/// Index and epoch of an ID, with the backend stripped.
+pub type RawId = (u32, u32);
+
+fn raw_id<I: wgc::id::TypedId>(id: I) -> RawId {
+    let (index, epoch, _backend) = id.unzip();
+    (index, epoch)
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum DryRunProblem {
+    /// The resource is used or destroyed, but it's not alive.
+    NotAlive,
+    /// The resource is created, but its ID is already alive.
+    AlreadyAlive,
+    /// The resource is destroyed, but it was destroyed already.
+    DestroyedTwice,
+}
+
+/// Inconsistency of the trace found by `DryRunPlayer`.
+#[derive(Clone, Debug)]
+pub struct DryRunError {
+    /// Index of the action, counting from the first one passed to
+    /// `DryRunPlayer::process`.
+    pub action_index: usize,
+    /// Line of the trace file where the action starts, if it was passed
+    /// to `DryRunPlayer::process_at_line`.
+    pub line: Option<usize>,
+    /// Name of the action variant.
+    pub action: &'static str,
+    pub kind: ResourceKind,
+    pub id: RawId,
+    pub problem: DryRunProblem,
+}
+
+impl fmt::Display for DryRunProblem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            DryRunProblem::NotAlive => "is not alive",
+            DryRunProblem::AlreadyAlive => "is already alive",
+            DryRunProblem::DestroyedTwice => "is already destroyed",
+        })
+    }
+}
+
+impl fmt::Display for DryRunError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "action #{}", self.action_index)?;
+        if let Some(line) = self.line {
+            write!(f, " at line {}", line)?;
+        }
+        write!(
+            f,
+            " ({}): {:?} {:?} {}",
+            self.action, self.kind, self.id, self.problem
+        )
+    }
+}
+
+/// Resource that an action refers to, but which isn't alive,
+/// found by `GlobalPlay::process` before replaying the action.
+#[derive(Clone, Debug)]
+pub struct DanglingId {
+    pub kind: ResourceKind,
+    pub id: RawId,
+    /// Either `NotAlive`, or `DestroyedTwice` if the action destroys
+    /// a resource that was destroyed before.
+    pub problem: DryRunProblem,
+}
+
+impl fmt::Display for DanglingId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?} {:?} {}", self.kind, self.id, self.problem)
+    }
+}
+
+impl Error for DanglingId {}
+
+/// Walks the actions of a trace and checks that they only refer to live
+/// resources, without touching the GPU.
+#[derive(Debug, Default)]
+pub struct DryRunPlayer {
+    live: HashMap<ResourceKind, HashSet<RawId>>,
+    /// Epoch of the last resource destroyed at each index, to tell
+    /// a destruction of the same resource twice apart from one of
+    /// a resource that never existed. Indices are reused by the recording
+    /// application, so this is bounded like the resources alive at once.
+    destroyed: HashMap<ResourceKind, HashMap<u32, u32>>,
+    /// Views currently acquired from each of the swap chains.
+    swap_chain_views: HashMap<RawId, RawId>,
+    /// Index of the last action referring to each resource, counting the
+    /// one creating it, with the backend of its ID. Only recorded for
+    /// `destroy_after_last_use`, since it grows with every resource created.
+    last_use: HashMap<(ResourceKind, RawId), (usize, wgt::Backend)>,
+    record_last_use: bool,
+    action_index: usize,
+    line: Option<usize>,
+    action: &'static str,
+    errors: Vec<DryRunError>,
+}
+
+impl DryRunPlayer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Checks a whole list of actions, returning everything that was found.
+    pub fn run(actions: &[trace::Action]) -> Vec<DryRunError> {
+        let mut player = Self::new();
+        for action in actions {
+            player.process(action);
+        }
+        player.finish()
+    }
+
+    pub fn finish(self) -> Vec<DryRunError> {
+        self.errors
+    }
+
+    /// Returns the resources that are alive after the actions processed
+    /// so far. Swap chains are left out, since traces don't destroy them.
+    pub fn leaks(&self) -> LeakReport {
+        let resources = self
+            .live
+            .iter()
+            .filter(|&(&kind, ids)| kind != ResourceKind::SwapChain && !ids.is_empty())
+            .map(|(&kind, ids)| {
+                let mut ids = ids.iter().cloned().collect::<Vec<_>>();
+                ids.sort();
+                (kind, ids)
+            })
+            .collect();
+        LeakReport { resources }
+    }
+
+    /// Processes the `action`, returning the first resource it refers to
+    /// that isn't alive, if any.
+    fn process_checked(&mut self, action: &trace::Action) -> Option<DanglingId> {
+        let first_new = self.errors.len();
+        self.process(action);
+        self.errors
+            .drain(first_new..)
+            .find(|e| e.problem != DryRunProblem::AlreadyAlive)
+            .map(|e| DanglingId {
+                kind: e.kind,
+                id: e.id,
+                problem: e.problem,
+            })
+    }
+
+    fn report(&mut self, kind: ResourceKind, id: RawId, problem: DryRunProblem) {
+        self.errors.push(DryRunError {
+            action_index: self.action_index,
+            line: self.line,
+            action: self.action,
+            kind,
+            id,
+            problem,
+        });
+    }
+
+    fn create<I: wgc::id::TypedId>(&mut self, kind: ResourceKind, id: I) {
+        self.use_resource(kind, id);
+        let id = raw_id(id);
+        if !self.live.entry(kind).or_default().insert(id) {
+            self.report(kind, id, DryRunProblem::AlreadyAlive);
+        }
+        let destroyed = self.destroyed.entry(kind).or_default();
+        if destroyed.get(&id.0) == Some(&id.1) {
+            destroyed.remove(&id.0);
+        }
+    }
+
+    fn destroy<I: wgc::id::TypedId>(&mut self, kind: ResourceKind, id: I) {
+        let id = raw_id(id);
+        let destroyed = self.destroyed.entry(kind).or_default();
+        if self.live.entry(kind).or_default().remove(&id) {
+            destroyed.insert(id.0, id.1);
+            return;
+        }
+        let problem = if destroyed.get(&id.0) == Some(&id.1) {
+            DryRunProblem::DestroyedTwice
+        } else {
+            DryRunProblem::NotAlive
+        };
+        self.report(kind, id, problem);
+    }
+
+    fn use_resource<I: wgc::id::TypedId>(&mut self, kind: ResourceKind, id: I) {
+        if self.record_last_use {
+            self.last_use
+                .insert((kind, raw_id(id)), (self.action_index, id.backend()));
+        }
+    }
+
+    fn check<I: wgc::id::TypedId>(&mut self, kind: ResourceKind, id: I) {
+        self.use_resource(kind, id);
+        let id = raw_id(id);
+        if !self.live.get(&kind).map_or(false, |set| set.contains(&id)) {
+            self.report(kind, id, DryRunProblem::NotAlive);
+        }
+    }
+
+    fn check_render_commands(&mut self, commands: &[wgc::command::RenderCommand]) {
+        use wgc::command::RenderCommand as C;
+        for command in commands {
+            match *command {
+                C::SetBindGroup { bind_group_id, .. } => {
+                    self.check(ResourceKind::BindGroup, bind_group_id)
+                }
+                C::SetPipeline(id) => self.check(ResourceKind::RenderPipeline, id),
+                C::SetIndexBuffer { buffer_id, .. }
+                | C::SetVertexBuffer { buffer_id, .. }
+                | C::MultiDrawIndirect { buffer_id, .. } => {
+                    self.check(ResourceKind::Buffer, buffer_id)
+                }
+                C::MultiDrawIndirectCount {
+                    buffer_id,
+                    count_buffer_id,
+                    ..
+                } => {
+                    self.check(ResourceKind::Buffer, buffer_id);
+                    self.check(ResourceKind::Buffer, count_buffer_id);
+                }
+                C::ExecuteBundle(id) => self.check(ResourceKind::RenderBundle, id),
+                _ => {}
+            }
+        }
+    }
+
+    fn check_compute_commands(&mut self, commands: &[wgc::command::ComputeCommand]) {
+        use wgc::command::ComputeCommand as C;
+        for command in commands {
+            match *command {
+                C::SetBindGroup { bind_group_id, .. } => {
+                    self.check(ResourceKind::BindGroup, bind_group_id)
+                }
+                C::SetPipeline(id) => self.check(ResourceKind::ComputePipeline, id),
+                C::DispatchIndirect { buffer_id, .. } => {
+                    self.check(ResourceKind::Buffer, buffer_id)
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn check_command(&mut self, command: &trace::Command) {
+        use wgc::device::trace::Command as C;
+        match *command {
+            C::CopyBufferToBuffer { src, dst, .. } => {
+                self.check(ResourceKind::Buffer, src);
+                self.check(ResourceKind::Buffer, dst);
+            }
+            C::CopyBufferToTexture {
+                ref src, ref dst, ..
             } => {
-                use wgc::binding_model as bm;
-                let entry_vec = entries
-                    .iter()
-                    .map(|(binding, res)| wgc::binding_model::BindGroupEntry {
-                        binding: *binding,
-                        resource: match *res {
-                            trace::BindingResource::Buffer { id, offset, size } => {
-                                bm::BindingResource::Buffer(bm::BufferBinding {
-                                    buffer_id: id,
-                                    offset,
-                                    size,
-                                })
-                            }
-                            trace::BindingResource::Sampler(id) => bm::BindingResource::Sampler(id),
-                            trace::BindingResource::TextureView(id) => {
-                                bm::BindingResource::TextureView(id)
-                            }
-                            trace::BindingResource::TextureViewArray(ref binding_array) => {
-                                bm::BindingResource::TextureViewArray(binding_array)
-                            }
-                        },
-                    })
-                    .collect::<Vec<_>>();
-                self.device_maintain_ids::<B>(device);
-                self.device_create_bind_group::<B>(
-                    device,
-                    &wgc::binding_model::BindGroupDescriptor {
-                        label: Some(&label),
-                        layout: layout_id,
-                        entries: &entry_vec,
-                    },
-                    id,
-                )
-                .unwrap();
+                self.check(ResourceKind::Buffer, src.buffer);
+                self.check(ResourceKind::Texture, dst.texture);
             }
-            A::DestroyBindGroup(id) => {
-                self.bind_group_destroy::<B>(id);
+            C::CopyTextureToBuffer {
+                ref src, ref dst, ..
+            } => {
+                self.check(ResourceKind::Texture, src.texture);
+                self.check(ResourceKind::Buffer, dst.buffer);
             }
-            A::CreateShaderModule { id, data } => {
-                let byte_vec = fs::read(dir.join(data)).unwrap();
-                let spv = byte_vec
-                    .chunks(4)
-                    .map(|c| u32::from_le_bytes([c[0], c[1], c[2], c[3]]))
-                    .collect::<Vec<_>>();
-                self.device_create_shader_module::<B>(
-                    device,
-                    wgc::pipeline::ShaderModuleSource::SpirV(&spv),
-                    id,
-                );
+            C::CopyTextureToTexture {
+                ref src, ref dst, ..
+            } => {
+                self.check(ResourceKind::Texture, src.texture);
+                self.check(ResourceKind::Texture, dst.texture);
             }
-            A::DestroyShaderModule(id) => {
-                self.shader_module_destroy::<B>(id);
+            C::ClearBuffer { dst, .. } => self.check(ResourceKind::Buffer, dst),
+            C::ClearTexture { dst, .. } => self.check(ResourceKind::Texture, dst),
+            C::RunComputePass {
+                ref base,
+                ref timestamp_writes,
+            } => {
+                if let Some(ref writes) = *timestamp_writes {
+                    self.check(ResourceKind::QuerySet, writes.query_set);
+                }
+                self.check_compute_commands(&base.commands);
             }
-            A::CreateComputePipeline { id, desc } => {
-                let compute_stage = desc.compute_stage.to_core();
-                self.device_maintain_ids::<B>(device);
-                self.device_create_compute_pipeline::<B>(
-                    device,
-                    &wgc::pipeline::ComputePipelineDescriptor {
-                        layout: desc.layout,
-                        compute_stage,
-                    },
-                    id,
-                )
-                .unwrap();
+            C::RunRenderPass {
+                ref base,
+                ref target_colors,
+                ref target_depth_stencil,
+                ref timestamp_writes,
+            } => {
+                if let Some(ref writes) = *timestamp_writes {
+                    self.check(ResourceKind::QuerySet, writes.query_set);
+                }
+                for at in target_colors {
+                    self.check(ResourceKind::TextureView, at.attachment);
+                    if let Some(resolve) = at.resolve_target {
+                        self.check(ResourceKind::TextureView, resolve);
+                    }
+                }
+                if let Some(ref at) = *target_depth_stencil {
+                    self.check(ResourceKind::TextureView, at.attachment);
+                }
+                self.check_render_commands(&base.commands);
             }
-            A::DestroyComputePipeline(id) => {
-                self.compute_pipeline_destroy::<B>(id);
-            }
-            A::CreateRenderPipeline { id, desc } => {
-                let vertex_stage = desc.vertex_stage.to_core();
-                let fragment_stage = desc.fragment_stage.as_ref().map(|fs| fs.to_core());
-                let vertex_buffers = desc
-                    .vertex_state
-                    .vertex_buffers
-                    .iter()
-                    .map(|vb| wgt::VertexBufferDescriptor {
-                        stride: vb.stride,
-                        step_mode: vb.step_mode,
-                        attributes: &vb.attributes,
-                    })
-                    .collect::<Vec<_>>();
-                self.device_maintain_ids::<B>(device);
-                self.device_create_render_pipeline::<B>(
-                    device,
-                    &wgc::pipeline::RenderPipelineDescriptor {
-                        layout: desc.layout,
-                        vertex_stage,
-                        fragment_stage,
-                        primitive_topology: desc.primitive_topology,
-                        rasterization_state: desc.rasterization_state,
-                        color_states: &desc.color_states,
-                        depth_stencil_state: desc.depth_stencil_state,
-                        vertex_state: wgt::VertexStateDescriptor {
-                            index_format: desc.vertex_state.index_format,
-                            vertex_buffers: &vertex_buffers,
-                        },
-                        sample_count: desc.sample_count,
-                        sample_mask: desc.sample_mask,
-                        alpha_to_coverage_enabled: desc.alpha_to_coverage_enabled,
-                    },
-                    id,
-                )
-                .unwrap();
+            C::PushDebugGroup(_) | C::PopDebugGroup | C::InsertDebugMarker(_) => {}
+            C::WriteTimestamp { query_set, .. } => self.check(ResourceKind::QuerySet, query_set),
+            C::ResolveQuerySet {
+                query_set,
+                destination,
+                ..
+            } => {
+                self.check(ResourceKind::QuerySet, query_set);
+                self.check(ResourceKind::Buffer, destination);
             }
-            A::DestroyRenderPipeline(id) => {
-                self.render_pipeline_destroy::<B>(id);
+            C::ResetQuerySet { query_set, .. } => self.check(ResourceKind::QuerySet, query_set),
+        }
+    }
+
+    /// Checks the `action` read from the `line` of the trace file,
+    /// like `TraceReader::line` returns, to report it with the problems.
+    pub fn process_at_line(&mut self, action: &trace::Action, line: Option<usize>) {
+        self.line = line;
+        self.process(action);
+        self.line = None;
+    }
+
+    pub fn process(&mut self, action: &trace::Action) {
+        use wgc::device::trace::Action as A;
+        use ResourceKind as K;
+        self.action = action_name(action);
+        match *action {
+            A::Init { .. } => {}
+            A::CreateBuffer { id, .. } => self.create(K::Buffer, id),
+            A::DestroyBuffer(id) => self.destroy(K::Buffer, id),
+            A::CreateTexture { id, .. } => self.create(K::Texture, id),
+            A::DestroyTexture(id) => self.destroy(K::Texture, id),
+            A::CreateTextureView { id, parent_id, .. } => {
+                self.check(K::Texture, parent_id);
+                self.create(K::TextureView, id);
             }
-            A::CreateRenderBundle { id, desc, base } => {
-                let label = Label::new(&desc.label);
-                let bundle = wgc::command::RenderBundleEncoder::new(
-                    &wgt::RenderBundleEncoderDescriptor {
-                        label: None,
-                        color_formats: &desc.color_formats,
-                        depth_stencil_format: desc.depth_stencil_format,
-                        sample_count: desc.sample_count,
-                    },
-                    device,
-                    Some(base),
-                )
-                .unwrap();
-                self.render_bundle_encoder_finish::<B>(
-                    bundle,
-                    &wgt::RenderBundleDescriptor {
-                        label: label.as_ptr(),
-                    },
-                    id,
-                )
-                .unwrap();
+            A::DestroyTextureView(id) => self.destroy(K::TextureView, id),
+            A::CreateSampler { id, .. } => self.create(K::Sampler, id),
+            A::DestroySampler(id) => self.destroy(K::Sampler, id),
+            A::CreateSurface { .. } => {}
+            A::CreateSwapChain { id, .. } => {
+                // re-creating a swap chain is allowed
+                self.live
+                    .entry(K::SwapChain)
+                    .or_default()
+                    .insert(raw_id(id));
             }
-            A::DestroyRenderBundle(id) => {
-                self.render_bundle_destroy::<B>(id);
+            A::GetSwapChainTexture { id, parent_id } => {
+                self.check(K::SwapChain, parent_id);
+                if let Some(id) = id {
+                    self.create(K::TextureView, id);
+                    self.swap_chain_views.insert(raw_id(parent_id), raw_id(id));
+                }
+            }
+            A::PresentSwapChain(id) => {
+                self.check(K::SwapChain, id);
+                if let Some(view) = self.swap_chain_views.remove(&raw_id(id)) {
+                    self.live.entry(K::TextureView).or_default().remove(&view);
+                }
             }
-            A::CreateQuerySet {
+            A::CreateBindGroupLayout { id, .. } => self.create(K::BindGroupLayout, id),
+            A::DestroyBindGroupLayout(id) => self.destroy(K::BindGroupLayout, id),
+            A::CreatePipelineLayout {
                 id,
-                desc,
+                ref bind_group_layouts,
+                ..
             } => {
-                let type_ = match &desc.type_ {
-                    trace::QueryType::Occlusion =>
-                        wgt::QueryType::Occlusion,
-                    trace::QueryType::PipelineStatistics(pipeline_statistics) =>
-                        wgt::QueryType::PipelineStatistics(&pipeline_statistics),
-                    trace::QueryType::Timestamp =>
-                        wgt::QueryType::Timestamp,
-                };
-
-                self.device_create_query_set::<B>(
-                    device,
-                    &wgt::QuerySetDescriptor {
-                        type_,
-                        count: desc.count,
-                    },
-                    id,
-                );
-            }
-            A::DestroyQuerySet(id) => {
-                self.query_set_destroy::<B>(id);
+                for &bgl in bind_group_layouts {
+                    self.check(K::BindGroupLayout, bgl);
+                }
+                self.create(K::PipelineLayout, id);
             }
-            A::WriteBuffer {
+            A::DestroyPipelineLayout(id) => self.destroy(K::PipelineLayout, id),
+            A::CreateBindGroup {
                 id,
-                data,
-                range,
-                queued,
+                layout_id,
+                ref entries,
+                ..
             } => {
-                let bin = std::fs::read(dir.join(data)).unwrap();
-                let size = (range.end - range.start) as usize;
-                if queued {
-                    self.queue_write_buffer::<B>(device, id, range.start, &bin);
-                } else {
-                    self.device_wait_for_buffer::<B>(device, id).unwrap();
-                    self.device_set_buffer_sub_data::<B>(device, id, range.start, &bin[..size]);
+                self.check(K::BindGroupLayout, layout_id);
+                for resource in entries.values() {
+                    match *resource {
+                        trace::BindingResource::Buffer { id, .. } => self.check(K::Buffer, id),
+                        trace::BindingResource::Sampler(id) => self.check(K::Sampler, id),
+                        trace::BindingResource::TextureView(id) => self.check(K::TextureView, id),
+                        trace::BindingResource::TextureViewArray(ref ids) => {
+                            for &id in ids.iter() {
+                                self.check(K::TextureView, id);
+                            }
+                        }
+                    }
+                }
+                self.create(K::BindGroup, id);
+            }
+            A::DestroyBindGroup(id) => self.destroy(K::BindGroup, id),
+            A::CreateShaderModule { id, .. } => self.create(K::ShaderModule, id),
+            A::DestroyShaderModule(id) => self.destroy(K::ShaderModule, id),
+            A::CreateComputePipeline { id, ref desc } => {
+                self.check(K::PipelineLayout, desc.layout);
+                self.check(K::ShaderModule, desc.compute_stage.module);
+                self.create(K::ComputePipeline, id);
+            }
+            A::DestroyComputePipeline(id) => self.destroy(K::ComputePipeline, id),
+            A::CreateRenderPipeline { id, ref desc } => {
+                self.check(K::PipelineLayout, desc.layout);
+                self.check(K::ShaderModule, desc.vertex_stage.module);
+                if let Some(ref fs) = desc.fragment_stage {
+                    self.check(K::ShaderModule, fs.module);
                 }
+                self.create(K::RenderPipeline, id);
             }
-            A::WriteTexture {
-                to,
-                data,
-                layout,
-                size,
+            A::DestroyRenderPipeline(id) => self.destroy(K::RenderPipeline, id),
+            A::CreateRenderBundle { id, ref base, .. } => {
+                self.check_render_commands(&base.commands);
+                self.create(K::RenderBundle, id);
+            }
+            A::DestroyRenderBundle(id) => self.destroy(K::RenderBundle, id),
+            A::CreateQuerySet { id, .. } => self.create(K::QuerySet, id),
+            A::DestroyQuerySet(id) => self.destroy(K::QuerySet, id),
+            A::CreateCommandEncoder { id, .. } => self.create(K::CommandEncoder, id),
+            A::DropCommandEncoder(id) => self.destroy(K::CommandEncoder, id),
+            A::WriteBuffer { id, .. }
+            | A::WriteBufferMany { id, .. }
+            | A::ClearBuffer { id, .. }
+            | A::MapBuffer { id, .. }
+            | A::UnmapBuffer(id) => self.check(K::Buffer, id),
+            A::WriteTexture { ref to, .. } => self.check(K::Texture, to.texture),
+            A::WriteTimestamp { query_set, .. } => self.check(K::QuerySet, query_set),
+            A::ResolveQuerySet {
+                query_set,
+                destination,
+                ..
             } => {
-                let bin = std::fs::read(dir.join(data)).unwrap();
-                self.queue_write_texture::<B>(device, &to, &bin, &layout, &size);
+                self.check(K::QuerySet, query_set);
+                self.check(K::Buffer, destination);
             }
-            A::Submit(_index, commands) => {
-                let encoder = self.device_create_command_encoder::<B>(
-                    device,
-                    &wgt::CommandEncoderDescriptor { label: ptr::null() },
-                    comb_manager.alloc(device.backend()),
+            // wgpu-core only has the default queue of each device,
+            // whose ID is the one of the device
+            A::Submit(_, _queue, encoder, ref commands) => {
+                self.destroy(K::CommandEncoder, encoder);
+                for command in commands {
+                    self.check_command(command);
+                }
+            }
+            A::FrameBoundary { .. } | A::Poll { .. } => {}
+            A::ResourceSnapshot {
+                ref live_buffers,
+                ref live_textures,
+                ref live_samplers,
+                ref live_bind_groups,
+                ref live_shader_modules,
+                ref live_compute_pipelines,
+                ref live_render_pipelines,
+                ref live_render_bundles,
+                ref live_query_sets,
+            } => {
+                for &id in live_buffers {
+                    self.check(K::Buffer, id);
+                }
+                for &id in live_textures {
+                    self.check(K::Texture, id);
+                }
+                for &id in live_samplers {
+                    self.check(K::Sampler, id);
+                }
+                for &id in live_bind_groups {
+                    self.check(K::BindGroup, id);
+                }
+                for &id in live_shader_modules {
+                    self.check(K::ShaderModule, id);
+                }
+                for &id in live_compute_pipelines {
+                    self.check(K::ComputePipeline, id);
+                }
+                for &id in live_render_pipelines {
+                    self.check(K::RenderPipeline, id);
+                }
+                for &id in live_render_bundles {
+                    self.check(K::RenderBundle, id);
+                }
+                for &id in live_query_sets {
+                    self.check(K::QuerySet, id);
+                }
+            }
+        }
+        self.action_index += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use wgc::id::TypedId as _;
+
+    #[cfg(any(target_os = "ios", target_os = "macos"))]
+    type Backend = wgc::backend::Metal;
+    #[cfg(not(any(target_os = "ios", target_os = "macos")))]
+    type Backend = wgc::backend::Vulkan;
+
+    /// Creates an empty directory for the test files of `name`, named after
+    /// the process too, so that concurrent runs don't share their files.
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// Stand-in for `wgc::hub::Global`, which records the names of the
+    /// replayed actions, and the devices they are replayed on, instead of
+    /// passing them to the core. Everything else goes through the default
+    /// `GlobalPlay::process` and `GlobalPlay::process_batch`.
+    #[derive(Default)]
+    struct MockGlobal {
+        calls: RefCell<Vec<String>>,
+        devices: RefCell<Vec<wgc::id::DeviceId>>,
+    }
+
+    impl GlobalPlay for MockGlobal {
+        fn encode_commands<B: wgc::hub::GfxBackend>(
+            &self,
+            encoder: wgc::id::CommandEncoderId,
+            commands: Vec<trace::Command>,
+        ) -> Result<wgc::id::CommandBufferId, ActionError> {
+            self.calls
+                .borrow_mut()
+                .push(format!("encode_commands({})", commands.len()));
+            Ok(encoder)
+        }
+
+        fn replay_action<B: wgc::hub::GfxBackend>(
+            &self,
+            device: wgc::id::DeviceId,
+            action: trace::Action,
+            _dir: &Path,
+            _state: &mut PlaybackState,
+        ) -> Result<(), ActionError> {
+            self.devices.borrow_mut().push(device);
+            if let trace::Action::Submit(_, _, encoder, commands) = action {
+                self.encode_commands::<B>(encoder, commands).unwrap();
+                self.calls.borrow_mut().push("Submit".to_string());
+            } else {
+                self.calls
+                    .borrow_mut()
+                    .push(action_name(&action).to_string());
+            }
+            Ok(())
+        }
+    }
+
+    fn device() -> wgc::id::DeviceId {
+        wgc::id::TypedId::zip(1, 0, wgt::Backend::Empty)
+    }
+
+    fn buffer(index: u32) -> wgc::id::BufferId {
+        wgc::id::TypedId::zip(index, 1, wgt::Backend::Empty)
+    }
+
+    fn create_buffer(index: u32) -> trace::Action {
+        trace::Action::CreateBuffer {
+            id: buffer(index),
+            desc: wgt::BufferDescriptor {
+                label: None,
+                size: 256,
+                usage: wgt::BufferUsage::COPY_DST,
+                mapped_at_creation: false,
+            },
+        }
+    }
+
+    fn encoder(index: u32) -> wgc::id::CommandEncoderId {
+        wgc::id::TypedId::zip(index, 1, wgt::Backend::Empty)
+    }
+
+    fn create_encoder(index: u32) -> trace::Action {
+        trace::Action::CreateCommandEncoder {
+            id: encoder(index),
+            desc: wgt::CommandEncoderDescriptor { label: None },
+        }
+    }
+
+    fn clear_buffer(index: u32) -> trace::Command {
+        trace::Command::ClearBuffer {
+            dst: buffer(index),
+            offset: 0,
+            size: None,
+        }
+    }
+
+    #[test]
+    fn test_process() {
+        let actions = vec![
+            create_buffer(0),
+            trace::Action::ClearBuffer {
+                id: buffer(0),
+                offset: 0,
+                size: None,
+            },
+            create_encoder(0),
+            trace::Action::Submit(1, None, encoder(0), vec![clear_buffer(0), clear_buffer(0)]),
+            trace::Action::DestroyBuffer(buffer(0)),
+        ];
+        let global = MockGlobal::default();
+        let mut state = PlaybackState::new(PlayerConfig::default(), &actions);
+        for action in actions {
+            global
+                .process::<Backend>(device(), action, Path::new(""), &mut state)
+                .unwrap();
+        }
+        assert_eq!(
+            *global.calls.borrow(),
+            [
+                "CreateBuffer",
+                "ClearBuffer",
+                "CreateCommandEncoder",
+                "encode_commands(2)",
+                "Submit",
+                "DestroyBuffer"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_process_dangling() {
+        let actions = vec![
+            create_buffer(0),
+            create_encoder(0),
+            trace::Action::DestroyBuffer(buffer(0)),
+            trace::Action::Submit(1, None, encoder(0), vec![clear_buffer(0)]),
+        ];
+        let global = MockGlobal::default();
+        let mut state = PlaybackState::new(PlayerConfig::default(), &actions);
+        let results = actions
+            .into_iter()
+            .map(|action| global.process::<Backend>(device(), action, Path::new(""), &mut state))
+            .collect::<Vec<_>>();
+        let error = results[3].as_ref().unwrap_err();
+        assert_eq!((error.action_index, error.action), (3, "Submit"));
+        assert!(error.dangling_id().is_some());
+        assert_eq!(
+            *global.calls.borrow(),
+            ["CreateBuffer", "CreateCommandEncoder", "DestroyBuffer"]
+        );
+    }
+
+    #[test]
+    fn test_double_destroy() {
+        let actions = vec![
+            create_buffer(0),
+            trace::Action::DestroyBuffer(buffer(0)),
+            trace::Action::DestroyBuffer(buffer(0)),
+            trace::Action::DestroyBuffer(buffer(1)),
+        ];
+        let global = MockGlobal::default();
+        let mut state = PlaybackState::new(PlayerConfig::default(), &actions);
+        let results = actions
+            .into_iter()
+            .map(|action| global.process::<Backend>(device(), action, Path::new(""), &mut state))
+            .collect::<Vec<_>>();
+        let problem = |index: usize| {
+            let error = results[index].as_ref().unwrap_err();
+            error.dangling_id().unwrap().problem
+        };
+        assert_eq!(problem(2), DryRunProblem::DestroyedTwice);
+        assert_eq!(problem(3), DryRunProblem::NotAlive);
+        assert_eq!(*global.calls.borrow(), ["CreateBuffer", "DestroyBuffer"]);
+    }
+
+    #[test]
+    fn test_resource_snapshot() {
+        let snapshot = |buffers: &[u32]| trace::Action::ResourceSnapshot {
+            live_buffers: buffers.iter().map(|&index| buffer(index)).collect(),
+            live_textures: Vec::new(),
+            live_samplers: Vec::new(),
+            live_bind_groups: Vec::new(),
+            live_shader_modules: Vec::new(),
+            live_compute_pipelines: Vec::new(),
+            live_render_pipelines: Vec::new(),
+            live_render_bundles: Vec::new(),
+            live_query_sets: Vec::new(),
+        };
+        let actions = vec![
+            create_buffer(0),
+            create_buffer(1),
+            trace::Action::DestroyBuffer(buffer(1)),
+            snapshot(&[0]),
+            snapshot(&[0, 1]),
+        ];
+        let global = MockGlobal::default();
+        let mut state = PlaybackState::new(PlayerConfig::default(), &actions);
+        let results = actions
+            .into_iter()
+            .map(|action| global.process::<Backend>(device(), action, Path::new(""), &mut state))
+            .collect::<Vec<_>>();
+        assert!(results[3].is_ok());
+        let error = results[4].as_ref().unwrap_err();
+        assert_eq!((error.action_index, error.action), (4, "ResourceSnapshot"));
+        assert!(error.dangling_id().is_some());
+    }
+
+    #[test]
+    fn test_trace_reader() {
+        let header = format!(
+            "(version: {}, wgpu_core_git_hash: \"0123abc\", backend: Empty, timed: false)",
+            trace::FORMAT_VERSION
+        );
+        let entries = [
+            header.as_str(),
+            r#"CreateCommandEncoder(
+                id: Id(0, 1, Empty),
+                desc: (label: Some("brackets ], ( and \" in a label")),
+            )"#,
+            "DestroyBuffer(Id(1, 1, Empty))",
+            "DropCommandEncoder(Id(0, 1, Empty))",
+        ];
+        let text = format!("[\n{},\n]", entries.join(",\n"));
+        let mut reader = TraceReader::new(text.as_bytes()).unwrap();
+        assert_eq!(reader.header().wgpu_core_git_hash, "0123abc");
+        let mut dry_run = DryRunPlayer::new();
+        let mut names = Vec::new();
+        while let Some(action) = reader.next() {
+            let action = action.unwrap();
+            names.push((action_name(&action), reader.line()));
+            dry_run.process_at_line(&action, reader.line());
+        }
+        assert_eq!(
+            names,
+            [
+                ("CreateCommandEncoder", Some(3)),
+                ("DestroyBuffer", Some(7)),
+                ("DropCommandEncoder", Some(8))
+            ]
+        );
+        // the buffer was never created
+        let errors = dry_run.finish();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].line, Some(7));
+        assert!(errors[0].to_string().contains("at line 7"), "{}", errors[0]);
+
+        // the closing bracket is missing if the application didn't exit cleanly
+        let truncated = format!("[\n{},\n", entries[..2].join(",\n"));
+        let reader = TraceReader::new(truncated.as_bytes()).unwrap();
+        assert_eq!(reader.count(), 1);
+
+        // actions unknown to this version are skipped, along with garbled
+        // ones, and the other actions are still replayed
+        let create = ron::ser::to_string(&create_buffer(0)).unwrap();
+        let destroy = ron::ser::to_string(&trace::Action::DestroyBuffer(buffer(0))).unwrap();
+        let mixed = format!(
+            "[\n{},\n]",
+            [
+                entries[0],
+                create.as_str(),
+                "CreateHologram(id: Id(0, 1, Empty), desc: (fancy: true))",
+                "DestroyBuffer(Id(one, 1, Empty))",
+                destroy.as_str(),
+            ]
+            .join(",\n")
+        );
+        let global = MockGlobal::default();
+        let mut state = PlaybackState::new(PlayerConfig::default(), &[]);
+        for action in TraceReader::new(mixed.as_bytes()).unwrap().skip_invalid() {
+            global
+                .process::<Backend>(device(), action, Path::new(""), &mut state)
+                .unwrap();
+        }
+        assert_eq!(*global.calls.borrow(), ["CreateBuffer", "DestroyBuffer"]);
+
+        // brackets and commas in raw strings and comments don't end an entry
+        let commented = format!(
+            "[\n{},\n{}\n]",
+            header,
+            r##"// a comment with ], and (
+            CreateCommandEncoder(
+                id: Id(0, 1, Empty), // ]
+                desc: (label: Some(r#"raw "], ( label"#)),
+            ),
+            DropCommandEncoder(Id(0, 1, Empty)), // trailing ]"##
+        );
+        let names = TraceReader::new(commented.as_bytes())
+            .unwrap()
+            .map(|action| action_name(&action.unwrap()))
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["CreateCommandEncoder", "DropCommandEncoder"]);
+
+        // the entries of a timed trace are read along with their times
+        let timed = format!(
+            "[\n{},\n(action: DestroyBuffer(Id(1, 1, Empty)), cpu_time_us: 5),\n]",
+            header.replace("timed: false", "timed: true")
+        );
+        let mut reader = TraceReader::new(timed.as_bytes()).unwrap();
+        let action = reader.next().unwrap().unwrap();
+        assert_eq!(action_name(&action), "DestroyBuffer");
+        assert_eq!(reader.cpu_time_us(), Some(5));
+
+        let header = trace::TraceHeader {
+            version: trace::FORMAT_VERSION,
+            wgpu_core_git_hash: "0123abc".to_string(),
+            backend: wgt::Backend::Empty,
+            timed: true,
+        };
+        let timed = trace::TimedAction {
+            action: trace::Action::DestroyBuffer(buffer(1)),
+            cpu_time_us: 5,
+        };
+        let mut binary = Vec::new();
+        for entry in vec![
+            bincode::serialize(&header).unwrap(),
+            bincode::serialize(&timed).unwrap(),
+        ] {
+            binary.extend_from_slice(&(entry.len() as u64).to_le_bytes());
+            binary.extend_from_slice(&entry);
+        }
+        let mut reader = TraceReader::new_binary(binary.as_slice()).unwrap();
+        let action = reader.next().unwrap().unwrap();
+        assert_eq!(action_name(&action), "DestroyBuffer");
+        assert_eq!(reader.cpu_time_us(), Some(5));
+        assert!(reader.next().is_none());
+    }
+
+    #[test]
+    fn test_comb_id_reuse() {
+        let actions = vec![create_encoder(0), create_encoder(4)];
+        let mut state = PlaybackState::new(PlayerConfig::default(), &actions);
+        for _ in 0..10_000 {
+            let id = state.alloc_comb_id(wgt::Backend::Empty);
+            // the IDs of the player don't collide with the recorded ones
+            assert!(id.unzip().0 > 4);
+            state.free_comb_id(id);
+        }
+        assert_eq!(state.command_buffer_index_count(), 1);
+
+        let first = state.alloc_comb_id(wgt::Backend::Empty);
+        let second = state.alloc_comb_id(wgt::Backend::Empty);
+        assert_ne!(first, second);
+        assert_eq!(state.command_buffer_index_count(), 2);
+    }
+
+    #[test]
+    fn test_buffer_id_reuse() {
+        let actions = vec![create_buffer(0), create_buffer(3)];
+        let mut state = PlaybackState::new(PlayerConfig::default(), &actions);
+        let first = state.alloc_buffer_id(wgt::Backend::Empty);
+        // the IDs of the player don't collide with the recorded ones
+        assert_eq!(first.unzip().0, 4);
+        state.free_buffer_id(first);
+        for _ in 0..10_000 {
+            let id = state.alloc_buffer_id(wgt::Backend::Empty);
+            assert_eq!(id.unzip().0, 4);
+            state.free_buffer_id(id);
+        }
+        let second = state.alloc_buffer_id(wgt::Backend::Empty);
+        let third = state.alloc_buffer_id(wgt::Backend::Empty);
+        assert_eq!((second.unzip().0, third.unzip().0), (4, 5));
+    }
+
+    #[test]
+    fn test_destroyed_ids_high_water_mark() {
+        // the recording application allocates its IDs like the core does
+        let mut recorder = wgc::hub::IdentityManager::default();
+        let global = MockGlobal::default();
+        let mut state = PlaybackState::new(PlayerConfig::default(), &[]);
+        for _ in 0..10_000 {
+            let id: wgc::id::BufferId = recorder.alloc(wgt::Backend::Empty);
+            let actions = vec![
+                trace::Action::CreateBuffer {
+                    id,
+                    desc: wgt::BufferDescriptor {
+                        label: None,
+                        size: 16,
+                        usage: wgt::BufferUsage::COPY_DST,
+                        mapped_at_creation: false,
+                    },
+                },
+                trace::Action::DestroyBuffer(id),
+            ];
+            for action in actions {
+                global
+                    .process::<Backend>(device(), action, Path::new(""), &mut state)
+                    .unwrap();
+            }
+            recorder.free(id);
+            assert_eq!(state.index_high_water_mark(), 1);
+        }
+        // only the last destruction at the index is remembered
+        assert_eq!(state.live.destroyed[&ResourceKind::Buffer].len(), 1);
+        assert!(state.live.last_use.is_empty());
+    }
+
+    #[test]
+    fn test_invalid_copy() {
+        use wgc::command::{CopyError, TransferError};
+        let copy = CopyError::InvalidMipLevel {
+            texture: wgc::id::TextureId::zip(0, 1, wgt::Backend::Empty),
+            level: 3,
+            level_count: 3,
+        };
+        let error = |cause| PlayerError {
+            action_index: 0,
+            action: "Submit",
+            cause,
+        };
+        assert_eq!(
+            error(copy_error(TransferError::Copy(copy))).invalid_copy(),
+            Some(&copy)
+        );
+        assert_eq!(
+            error(copy_error(TransferError::MissingCopySrcUsageFlag)).invalid_copy(),
+            None
+        );
+    }
+
+    /// Action of the given `kind` for `test_batches`.
+    fn batch_action(kind: u8, index: u32) -> trace::Action {
+        match kind {
+            0 => trace::Action::CreateShaderModule {
+                id: wgc::id::TypedId::zip(index, 1, wgt::Backend::Empty),
+                source: trace::ShaderModuleSource::Wgsl("shader.wgsl".to_string()),
+                compressed: false,
+            },
+            1 => create_buffer(index),
+            2 => trace::Action::DestroyBuffer(buffer(index)),
+            3 => create_encoder(index),
+            _ => trace::Action::Submit(index as usize + 1, None, encoder(index), Vec::new()),
+        }
+    }
+
+    proptest::proptest! {
+        #[test]
+        fn test_batches(
+            kinds in proptest::collection::vec(0..5u8, 0..64),
+            parallel_creation: bool,
+            parallel_encoding: bool,
+        ) {
+            let config = PlayerConfig {
+                parallel_creation,
+                parallel_encoding,
+                ..PlayerConfig::default()
+            };
+            let actions = kinds
+                .iter()
+                .enumerate()
+                .map(|(index, &kind)| batch_action(kind, index as u32))
+                .collect::<Vec<_>>();
+            let mut batches = Vec::<Vec<&trace::Action>>::new();
+            for action in &actions {
+                match batches.last_mut() {
+                    Some(batch) if fits_batch(&config, batch[0], action) => batch.push(action),
+                    _ => batches.push(vec![action]),
+                }
+            }
+            // the batches keep the order of the actions
+            let batched = batches.iter().flatten().map(|action| action_name(action));
+            proptest::prop_assert!(batched.eq(actions.iter().map(action_name)));
+            // anything else than creations or submissions is a barrier
+            for batch in batches.iter().filter(|batch| batch.len() > 1) {
+                let creations = batch.iter().all(|action| is_concurrent_creation(action));
+                let submissions = batch.iter().all(|action| is_submission(action));
+                proptest::prop_assert!(
+                    (parallel_creation && creations) || (parallel_encoding && submissions),
+                    "{:?}",
+                    batch
                 );
-                let comb = self.encode_commands::<B>(encoder, commands);
-                self.queue_submit::<B>(device, &[comb]).unwrap();
             }
         }
     }
+
+    #[test]
+    fn test_step_hook() {
+        let actions = vec![
+            create_buffer(0),
+            create_buffer(1),
+            trace::Action::DestroyBuffer(buffer(0)),
+        ];
+        let global = MockGlobal::default();
+        let mut state = PlaybackState::new(PlayerConfig::default(), &actions);
+        state.set_step_hook(|index, _| match index {
+            1 => StepControl::Skip,
+            _ => StepControl::Continue,
+        });
+        for action in actions {
+            global
+                .process::<Backend>(device(), action, Path::new(""), &mut state)
+                .unwrap();
+        }
+        assert_eq!(*global.calls.borrow(), ["CreateBuffer", "DestroyBuffer"]);
+    }
+
+    #[test]
+    fn test_blob_range() {
+        let data = [1, 2, 3, 4];
+        assert_eq!(blob_range("data.bin", &data, &(8..11)).unwrap(), [1, 2, 3]);
+        assert_eq!(blob_range("data.bin", &data, &(0..4)).unwrap(), data);
+        let error = blob_range("data.bin", &data, &(0..5)).unwrap_err();
+        let error = error.downcast_ref::<BlobTooSmall>().unwrap();
+        assert_eq!(
+            (error.file.as_str(), error.expected, error.got),
+            ("data.bin", 5, 4)
+        );
+        let inverted = Range { start: 4, end: 0 };
+        assert!(blob_range("data.bin", &data, &inverted).is_err());
+    }
+
+    #[test]
+    fn test_leak_report() {
+        let actions = vec![
+            create_buffer(0),
+            create_buffer(1),
+            create_buffer(2),
+            trace::Action::DestroyBuffer(buffer(1)),
+        ];
+        let global = MockGlobal::default();
+        let config = PlayerConfig {
+            report_leaks: true,
+            ..PlayerConfig::default()
+        };
+        let mut state = PlaybackState::new(config, &actions);
+        for action in actions {
+            global
+                .process::<Backend>(device(), action, Path::new(""), &mut state)
+                .unwrap();
+        }
+        let report = state.leak_report().unwrap();
+        assert_eq!(report.count(), 2);
+        assert_eq!(
+            report.resources.into_iter().collect::<Vec<_>>(),
+            [(ResourceKind::Buffer, vec![(0, 1), (2, 1)])]
+        );
+
+        let state = PlaybackState::new(PlayerConfig::default(), &[]);
+        assert_eq!(state.leak_report(), None);
+    }
+
+    #[test]
+    fn test_play_range() {
+        let actions = vec![
+            create_buffer(0),
+            create_encoder(0),
+            trace::Action::Submit(1, None, encoder(0), vec![clear_buffer(0)]),
+            create_encoder(1),
+            trace::Action::Submit(2, None, encoder(1), vec![clear_buffer(0)]),
+            create_encoder(2),
+            trace::Action::Submit(3, None, encoder(2), Vec::new()),
+            create_buffer(1),
+            trace::Action::DestroyBuffer(buffer(1)),
+            trace::Action::DestroyBuffer(buffer(0)),
+        ];
+        let global = MockGlobal::default();
+        global
+            .play_range::<Backend>(
+                device(),
+                actions,
+                Path::new(""),
+                3..5,
+                PlayerConfig::default(),
+                None,
+            )
+            .unwrap();
+        // the encoder submitted before the range is dropped instead, and
+        // the resources created after the range are never created nor destroyed
+        assert_eq!(
+            *global.calls.borrow(),
+            [
+                "CreateBuffer",
+                "CreateCommandEncoder",
+                "DropCommandEncoder",
+                "CreateCommandEncoder",
+                "encode_commands(1)",
+                "Submit",
+                "DestroyBuffer"
+            ]
+        );
+
+        let global = MockGlobal::default();
+        let error = global
+            .play_range::<Backend>(
+                device(),
+                vec![create_buffer(0)],
+                Path::new(""),
+                0..2,
+                PlayerConfig::default(),
+                None,
+            )
+            .unwrap_err();
+        assert_eq!(error.action, "CreateBuffer");
+        assert!(global.calls.borrow().is_empty());
+    }
+
+    #[test]
+    fn test_after_submit_observer() {
+        #[derive(Default)]
+        struct SubmitObserver {
+            submissions: RefCell<Vec<usize>>,
+        }
+        impl PlaybackObserver for SubmitObserver {
+            fn on_after_submit(&self, submission_index: usize) {
+                self.submissions.borrow_mut().push(submission_index);
+            }
+        }
+
+        let actions = vec![
+            create_buffer(0),
+            create_encoder(0),
+            trace::Action::Submit(1, None, encoder(0), vec![clear_buffer(0)]),
+            create_encoder(1),
+            trace::Action::Submit(2, None, encoder(1), vec![clear_buffer(0)]),
+            create_encoder(2),
+            trace::Action::Submit(3, None, encoder(2), Vec::new()),
+        ];
+        let global = MockGlobal::default();
+        let observer = SubmitObserver::default();
+        global
+            .play_range::<Backend>(
+                device(),
+                actions,
+                Path::new(""),
+                3..7,
+                PlayerConfig::default(),
+                Some(&observer),
+            )
+            .unwrap();
+        // the submission before the range is dropped instead of submitted
+        assert_eq!(*observer.submissions.borrow(), [2, 3]);
+    }
+
+    #[test]
+    fn test_poll() {
+        let actions = || {
+            vec![
+                create_buffer(0),
+                create_encoder(0),
+                trace::Action::Submit(1, None, encoder(0), vec![clear_buffer(0)]),
+                trace::Action::Poll { force_wait: true },
+                trace::Action::MapBuffer {
+                    id: buffer(0),
+                    mode: wgt::MapMode::Read,
+                    range: 0..256,
+                },
+            ]
+        };
+        let global = MockGlobal::default();
+        global
+            .play_range::<Backend>(
+                device(),
+                actions(),
+                Path::new(""),
+                0..5,
+                PlayerConfig::default(),
+                None,
+            )
+            .unwrap();
+        assert_eq!(
+            *global.calls.borrow(),
+            [
+                "CreateBuffer",
+                "CreateCommandEncoder",
+                "encode_commands(1)",
+                "Submit",
+                "Poll",
+                "MapBuffer"
+            ]
+        );
+
+        // without the submission, there is nothing to wait for
+        let global = MockGlobal::default();
+        global
+            .play_range::<Backend>(
+                device(),
+                actions(),
+                Path::new(""),
+                4..5,
+                PlayerConfig::default(),
+                None,
+            )
+            .unwrap();
+        assert_eq!(
+            *global.calls.borrow(),
+            [
+                "CreateBuffer",
+                "CreateCommandEncoder",
+                "DropCommandEncoder",
+                "MapBuffer"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_frame_boundary_observer() {
+        #[derive(Default)]
+        struct FrameObserver {
+            frames: RefCell<Vec<u64>>,
+        }
+        impl PlaybackObserver for FrameObserver {
+            fn on_frame_boundary(&self, frame_index: u64) {
+                self.frames.borrow_mut().push(frame_index);
+            }
+        }
+
+        let actions = vec![
+            create_buffer(0),
+            trace::Action::FrameBoundary { frame_index: 0 },
+            trace::Action::WriteBuffer {
+                id: buffer(0),
+                data: "data1.bin".to_string(),
+                range: 0..4,
+                queued: true,
+                compressed: false,
+            },
+            trace::Action::FrameBoundary { frame_index: 1 },
+            trace::Action::FrameBoundary { frame_index: 2 },
+        ];
+        let global = MockGlobal::default();
+        let observer = FrameObserver::default();
+        global
+            .play_range::<Backend>(
+                device(),
+                actions,
+                Path::new(""),
+                2..4,
+                PlayerConfig::default(),
+                Some(&observer),
+            )
+            .unwrap();
+        // only the boundaries within the range are reported
+        assert_eq!(*observer.frames.borrow(), [1]);
+        assert_eq!(
+            *global.calls.borrow(),
+            [
+                "CreateBuffer",
+                "FrameBoundary",
+                "WriteBuffer",
+                "FrameBoundary"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_play_devices() {
+        use wgc::hub::GfxBackend as _;
+
+        let init = |device: wgc::id::DeviceId| trace::Action::Init {
+            device,
+            desc: wgt::DeviceDescriptor::default(),
+            backend: Backend::VARIANT,
+            adapter_info: wgc::instance::AdapterInfo {
+                name: "adapter".to_string(),
+                vendor: 0,
+                device: 0,
+                device_type: wgc::instance::DeviceType::Other,
+                backend: Backend::VARIANT,
+            },
+            adapter_features: wgt::Features::empty(),
+            adapter_limits: wgt::Limits::default(),
+        };
+        let clear = |index: u32| trace::Action::ClearBuffer {
+            id: buffer(index),
+            offset: 0,
+            size: None,
+        };
+        let first = wgc::id::TypedId::zip(1, 0, Backend::VARIANT);
+        let second = wgc::id::TypedId::zip(2, 0, Backend::VARIANT);
+        let actions = vec![
+            init(first),
+            create_buffer(0),
+            init(second),
+            create_buffer(1),
+            clear(1),
+            init(first),
+            clear(0),
+            trace::Action::DestroyBuffer(buffer(0)),
+            init(second),
+            trace::Action::DestroyBuffer(buffer(1)),
+        ];
+        let global = MockGlobal::default();
+        let mut state = PlaybackState::new(PlayerConfig::default(), &actions);
+        let mut created = Vec::new();
+        global
+            .play_devices(actions, Path::new(""), &mut state, |action| match action {
+                trace::Action::Init { device, .. } => {
+                    created.push(device);
+                    Ok(device)
+                }
+                _ => unreachable!(),
+            })
+            .unwrap();
+        // each device is only created once
+        assert_eq!(created, [first, second]);
+        assert_eq!(
+            *global.calls.borrow(),
+            [
+                "CreateBuffer",
+                "CreateBuffer",
+                "ClearBuffer",
+                "ClearBuffer",
+                "DestroyBuffer",
+                "DestroyBuffer"
+            ]
+        );
+        assert_eq!(
+            *global.devices.borrow(),
+            [first, second, second, first, first, second]
+        );
+    }
+
+    #[test]
+    fn test_substitute_backend() {
+        use wgc::hub::GfxBackend as _;
+
+        // a backend that isn't compiled in, so that only the substituted
+        // one can be dispatched to
+        let recorded = match Backend::VARIANT {
+            wgt::Backend::Metal => wgt::Backend::Dx11,
+            _ => wgt::Backend::Metal,
+        };
+        let ids = [(0, 1), (3, 2), (7, 0)];
+        for &(index, epoch) in ids.iter() {
+            let id: wgc::id::BufferId = wgc::id::TypedId::zip(index, epoch, recorded);
+            assert_eq!(
+                rezip(id, Backend::VARIANT).unzip(),
+                (index, epoch, Backend::VARIANT)
+            );
+        }
+
+        let device = wgc::id::TypedId::zip(1, 0, recorded);
+        let actions = vec![
+            trace::Action::Init {
+                device,
+                desc: wgt::DeviceDescriptor::default(),
+                backend: recorded,
+                adapter_info: wgc::instance::AdapterInfo {
+                    name: "adapter".to_string(),
+                    vendor: 0,
+                    device: 0,
+                    device_type: wgc::instance::DeviceType::Other,
+                    backend: recorded,
+                },
+                adapter_features: wgt::Features::empty(),
+                adapter_limits: wgt::Limits::default(),
+            },
+            create_buffer(0),
+            trace::Action::DestroyBuffer(buffer(0)),
+        ];
+        let config = PlayerConfig {
+            backend: Some(Backend::VARIANT),
+            ..PlayerConfig::default()
+        };
+        let global = MockGlobal::default();
+        let mut state = PlaybackState::new(config, &actions);
+        let mut created = Vec::new();
+        global
+            .play_devices(actions, Path::new(""), &mut state, |action| match action {
+                trace::Action::Init {
+                    device, backend, ..
+                } => {
+                    created.push(backend);
+                    Ok(device)
+                }
+                _ => unreachable!(),
+            })
+            .unwrap();
+        assert_eq!(created, [Backend::VARIANT]);
+        assert_eq!(
+            *global.devices.borrow(),
+            [rezip(device, Backend::VARIANT); 2]
+        );
+        assert_eq!(
+            state.devices().collect::<Vec<_>>(),
+            [(rezip(device, Backend::VARIANT), Backend::VARIANT)]
+        );
+    }
+
+    #[test]
+    fn test_unsupported_backend() {
+        use wgc::hub::GfxBackend as _;
+
+        assert_eq!(check_backend(Backend::VARIANT), Ok(()));
+        let device = wgc::id::TypedId::zip(1, 0, wgt::Backend::Gl);
+        let actions = vec![
+            trace::Action::Init {
+                device,
+                desc: wgt::DeviceDescriptor::default(),
+                backend: wgt::Backend::Gl,
+                adapter_info: wgc::instance::AdapterInfo {
+                    name: "adapter".to_string(),
+                    vendor: 0,
+                    device: 0,
+                    device_type: wgc::instance::DeviceType::Other,
+                    backend: wgt::Backend::Gl,
+                },
+                adapter_features: wgt::Features::empty(),
+                adapter_limits: wgt::Limits::default(),
+            },
+            create_buffer(0),
+        ];
+        let global = MockGlobal::default();
+        let mut state = PlaybackState::new(PlayerConfig::default(), &actions);
+        let error = global
+            .play_devices(actions, Path::new(""), &mut state, |_| {
+                panic!("a device was created on an unsupported backend")
+            })
+            .unwrap_err();
+        assert_eq!(error.action, "Init");
+        assert_eq!(
+            error.cause.downcast_ref::<UnsupportedBackend>(),
+            Some(&UnsupportedBackend {
+                backend: wgt::Backend::Gl
+            })
+        );
+        assert!(global.calls.borrow().is_empty());
+    }
+
+    #[test]
+    fn test_trace_summary() {
+        let dir = test_dir("wgpu-player-summary");
+        fs::write(dir.join("data1.bin"), &[0; 16]).unwrap();
+        fs::write(dir.join("data2.bin"), &[0; 64]).unwrap();
+        fs::write(dir.join("data3.wgsl"), &[0; 32]).unwrap();
+
+        let shader = |index| trace::Action::CreateShaderModule {
+            id: wgc::id::TypedId::zip(index, 1, wgt::Backend::Empty),
+            source: trace::ShaderModuleSource::Wgsl("data3.wgsl".to_string()),
+            compressed: false,
+        };
+        let bundle = wgc::id::TypedId::zip(0, 1, wgt::Backend::Empty);
+        let write = |data: &str| trace::Action::WriteBuffer {
+            id: buffer(0),
+            data: data.to_string(),
+            range: 0..16,
+            queued: true,
+            compressed: false,
+        };
+        let actions = vec![
+            create_buffer(0),
+            write("data1.bin"),
+            write("data2.bin"),
+            write("data1.bin"),
+            shader(0),
+            shader(1),
+            create_encoder(0),
+            trace::Action::Submit(
+                1,
+                None,
+                encoder(0),
+                vec![
+                    trace::Command::RunComputePass {
+                        base: wgc::command::BasePass {
+                            commands: vec![
+                                wgc::command::ComputeCommand::Dispatch([1, 1, 1]),
+                                wgc::command::ComputeCommand::DispatchIndirect {
+                                    buffer_id: buffer(0),
+                                    offset: 0,
+                                },
+                                wgc::command::ComputeCommand::Dispatch([4, 4, 1]),
+                            ],
+                            dynamic_offsets: Vec::new(),
+                            string_data: Vec::new(),
+                            push_constant_data: Vec::new(),
+                        },
+                        timestamp_writes: None,
+                    },
+                    trace::Command::RunRenderPass {
+                        base: wgc::command::BasePass {
+                            commands: vec![
+                                wgc::command::RenderCommand::ExecuteBundle(bundle),
+                                wgc::command::RenderCommand::Draw {
+                                    vertex_count: 3,
+                                    instance_count: 1,
+                                    first_vertex: 0,
+                                    first_instance: 0,
+                                },
+                                wgc::command::RenderCommand::ExecuteBundle(bundle),
+                            ],
+                            dynamic_offsets: Vec::new(),
+                            string_data: Vec::new(),
+                            push_constant_data: Vec::new(),
+                        },
+                        target_colors: Vec::new(),
+                        target_depth_stencil: None,
+                        timestamp_writes: None,
+                    },
+                ],
+            ),
+            trace::Action::PresentSwapChain(wgc::id::TypedId::zip(0, 1, wgt::Backend::Empty)),
+            trace::Action::PresentSwapChain(wgc::id::TypedId::zip(0, 1, wgt::Backend::Empty)),
+        ];
+        let summary = TraceSummary::collect(&actions, &dir).unwrap();
+        assert_eq!(summary.action_count, 10);
+        assert_eq!(summary.per_action["WriteBuffer"], 3);
+        assert_eq!(summary.submit_count(), 1);
+        assert_eq!(summary.frame_count, 2);
+        // each write counts, but a shader is only counted once
+        assert_eq!(summary.buffer_data_bytes, 16 + 64 + 16);
+        assert_eq!(summary.shader_bytes, 32);
+        assert_eq!(summary.unique_shaders, 1);
+        assert_eq!(summary.dispatches, (2, 1));
+        assert_eq!(summary.bundle_executions, 2);
+        assert_eq!(
+            summary.largest_blobs,
+            [
+                ("data2.bin".to_string(), 64),
+                ("data3.wgsl".to_string(), 32),
+                ("data1.bin".to_string(), 16)
+            ]
+        );
+
+        // missing binary files are reported with their names
+        fs::remove_file(dir.join("data2.bin")).unwrap();
+        let error = TraceSummary::collect(&actions, &dir).unwrap_err();
+        assert!(error.to_string().contains("data2.bin"), "{}", error);
+    }
+
+    #[test]
+    fn test_destroy_filters() {
+        let texture = wgc::id::TypedId::zip(0, 1, wgt::Backend::Empty);
+        let actions = || {
+            vec![
+                create_buffer(0),
+                create_buffer(1),
+                trace::Action::CreateTexture {
+                    id: texture,
+                    desc: wgt::TextureDescriptor {
+                        label: None,
+                        size: wgt::Extent3d {
+                            width: 4,
+                            height: 4,
+                            depth: 1,
+                        },
+                        mip_level_count: 1,
+                        sample_count: 1,
+                        dimension: wgt::TextureDimension::D2,
+                        format: wgt::TextureFormat::Rgba8Unorm,
+                        usage: wgt::TextureUsage::COPY_DST,
+                    },
+                },
+                create_encoder(0),
+                trace::Action::Submit(1, None, encoder(0), vec![clear_buffer(0)]),
+                trace::Action::DestroyBuffer(buffer(0)),
+                trace::Action::DestroyTexture(texture),
+            ]
+        };
+        let count_destroys =
+            |actions: &[trace::Action]| actions.iter().filter(|a| is_destroy(a)).count();
+
+        let stripped = strip_destroys(actions());
+        assert_eq!(stripped.len(), 5);
+        assert_eq!(count_destroys(&stripped), 0);
+
+        // the leaked buffer is destroyed too, and the texture right away
+        let early = destroy_after_last_use(actions());
+        assert_eq!(
+            early.iter().map(action_name).collect::<Vec<_>>(),
+            [
+                "CreateBuffer",
+                "CreateBuffer",
+                "DestroyBuffer",
+                "CreateTexture",
+                "DestroyTexture",
+                "CreateCommandEncoder",
+                "Submit",
+                "DestroyBuffer"
+            ]
+        );
+        assert!(DryRunPlayer::run(&early).is_empty());
+
+        // the core holds a single resource at each index, so the destruction
+        // of a resource is kept if its index is reused
+        let reused = wgc::id::TypedId::zip(0, 2, wgt::Backend::Empty);
+        let recycling = || {
+            vec![
+                create_buffer(0),
+                trace::Action::DestroyBuffer(buffer(0)),
+                trace::Action::CreateBuffer {
+                    id: reused,
+                    desc: wgt::BufferDescriptor {
+                        label: None,
+                        size: 256,
+                        usage: wgt::BufferUsage::COPY_DST,
+                        mapped_at_creation: false,
+                    },
+                },
+                trace::Action::ClearBuffer {
+                    id: reused,
+                    offset: 0,
+                    size: None,
+                },
+                trace::Action::DestroyBuffer(reused),
+            ]
+        };
+        let check_indices = |actions: &[trace::Action]| {
+            let mut occupied = HashSet::new();
+            for action in actions {
+                if let Some((kind, (index, _))) = destroyed_resource(action) {
+                    occupied.remove(&(kind, index));
+                }
+                if let Some((kind, (index, _))) = created_resource(action) {
+                    assert!(occupied.insert((kind, index)), "{:?}", action);
+                }
+            }
+        };
+        let stripped_recycling = strip_destroys(recycling());
+        assert_eq!(
+            stripped_recycling
+                .iter()
+                .map(action_name)
+                .collect::<Vec<_>>(),
+            [
+                "CreateBuffer",
+                "DestroyBuffer",
+                "CreateBuffer",
+                "ClearBuffer"
+            ]
+        );
+        check_indices(&stripped_recycling);
+        let early_recycling = destroy_after_last_use(recycling());
+        check_indices(&early_recycling);
+        assert!(DryRunPlayer::run(&early_recycling).is_empty());
+
+        let dir = test_dir("wgpu-player-filtered");
+        save_trace(&dir, wgt::Backend::Empty, stripped, Path::new("")).unwrap();
+        let loaded = load_trace(&dir).unwrap();
+        assert_eq!(loaded.actions.len(), 5);
+        assert_eq!(count_destroys(&loaded.actions), 0);
+    }
+
+    #[test]
+    fn test_unterminated_trace() {
+        let dir = test_dir("wgpu-player-unterminated");
+        let mut trace =
+            trace::Trace::new(&dir, wgt::Backend::Empty, trace::TraceFormat::Ron).unwrap();
+        trace.add(create_encoder(0));
+        trace.add(trace::Action::DropCommandEncoder(encoder(0)));
+        trace.flush(true).unwrap();
+        // a crash leaves the trace without its closing bracket
+        std::mem::forget(trace);
+        let text = fs::read_to_string(dir.join(trace::FILE_NAME)).unwrap();
+        assert!(!text.trim_end().ends_with(']'), "{}", text);
+
+        let loaded = load_trace(&dir).unwrap();
+        let names = loaded.actions.iter().map(action_name).collect::<Vec<_>>();
+        assert_eq!(names, ["CreateCommandEncoder", "DropCommandEncoder"]);
+        assert_eq!(TraceReader::open(&dir).unwrap().count(), 2);
+    }
+
+    #[test]
+    fn test_compact_trace() {
+        let actions = || {
+            vec![
+                trace::Action::CreateBuffer {
+                    id: buffer(0),
+                    desc: wgt::BufferDescriptor {
+                        label: Some("commas, and (parentheses)\n".to_string()),
+                        size: 256,
+                        usage: wgt::BufferUsage::COPY_DST,
+                        mapped_at_creation: false,
+                    },
+                },
+                create_encoder(0),
+                trace::Action::Submit(
+                    1,
+                    None,
+                    encoder(0),
+                    vec![trace::Command::RunComputePass {
+                        base: wgc::command::BasePass {
+                            commands: vec![wgc::command::ComputeCommand::Dispatch([4, 4, 1])],
+                            dynamic_offsets: Vec::new(),
+                            string_data: Vec::new(),
+                            push_constant_data: Vec::new(),
+                        },
+                        timestamp_writes: None,
+                    }],
+                ),
+                trace::Action::DestroyBuffer(buffer(0)),
+            ]
+        };
+        let record = |name: &str, config: Option<ron::ser::PrettyConfig>| {
+            let dir = test_dir(name);
+            let mut trace =
+                trace::Trace::new(&dir, wgt::Backend::Empty, trace::TraceFormat::Ron).unwrap();
+            trace.set_ron_config(config);
+            for action in actions() {
+                trace.add(action);
+            }
+            dir
+        };
+        let pretty = record(
+            "wgpu-player-pretty",
+            Some(ron::ser::PrettyConfig::default()),
+        );
+        let compact = record("wgpu-player-compact", None);
+        let size = |dir: &Path| fs::metadata(dir.join(trace::FILE_NAME)).unwrap().len();
+        assert!(size(&compact) < size(&pretty));
+
+        let names = actions().iter().map(action_name).collect::<Vec<_>>();
+        let loaded = load_trace(&compact).unwrap();
+        assert_eq!(
+            loaded.actions.iter().map(action_name).collect::<Vec<_>>(),
+            names
+        );
+        match loaded.actions[0] {
+            trace::Action::CreateBuffer { ref desc, .. } => assert_eq!(
+                desc.label.as_ref().map(String::as_str),
+                Some("commas, and (parentheses)\n")
+            ),
+            ref other => panic!("Unexpected action {:?}", other),
+        }
+        let read = TraceReader::open(&compact)
+            .unwrap()
+            .map(|action| action_name(&action.unwrap()))
+            .collect::<Vec<_>>();
+        assert_eq!(read, names);
+    }
+
+    #[test]
+    fn test_metadata() {
+        let dir = test_dir("wgpu-player-metadata");
+        let trace = trace::Trace::new(&dir, wgt::Backend::Empty, trace::TraceFormat::Ron).unwrap();
+        assert!(load_metadata(&dir).unwrap().is_none());
+
+        let adapter_info = wgc::instance::AdapterInfo {
+            name: "Test adapter".to_string(),
+            vendor: 0x10DE,
+            device: 0x1234,
+            device_type: wgc::instance::DeviceType::DiscreteGpu,
+            backend: wgt::Backend::Vulkan,
+        };
+        let desc = wgt::DeviceDescriptor {
+            features: wgt::Features::MAPPABLE_PRIMARY_BUFFERS,
+            ..wgt::DeviceDescriptor::default()
+        };
+        trace.write_metadata(&desc, &adapter_info).unwrap();
+        let mut metadata = load_metadata(&dir).unwrap().unwrap();
+        assert_eq!(metadata.adapter_info, adapter_info);
+        assert_eq!(metadata.device_features, desc.features);
+        assert!(metadata.capture_time > 0);
+        assert!(environment_differences(&metadata).is_empty());
+
+        metadata.os = "plan9".to_string();
+        assert_eq!(
+            environment_differences(&metadata),
+            [format!(
+                "captured on plan9, replayed on {}",
+                std::env::consts::OS
+            )]
+        );
+
+        metadata.os = std::env::consts::OS.to_string();
+        metadata.arch = "sparc".to_string();
+        assert_eq!(
+            environment_differences(&metadata),
+            [format!(
+                "captured on architecture sparc, replayed on {}",
+                std::env::consts::ARCH
+            )]
+        );
+    }
 }
@@ -0,0 +1,7 @@
+[[location 0]] var<out> o_color : vec4<f32>;
+
+fn main() -> void {
+    o_color = vec4<f32>(0.0, 1.0, 0.0, 1.0);
+    return;
+}
+entry_point fragment as "main" = main;
@@ -0,0 +1,8 @@
+[[location 0]] var<in> a_pos : vec4<f32>;
+[[builtin position]] var<out> o_position : vec4<f32>;
+
+fn main() -> void {
+    o_position = a_pos;
+    return;
+}
+entry_point vertex as "main" = main;
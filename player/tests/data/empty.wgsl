@@ -0,0 +1,4 @@
+fn main() -> void {
+    return;
+}
+entry_point compute as "main" = main;
@@ -0,0 +1,32 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+/*! Checks that the prelude is enough to replay a trace.
+!*/
+
+use player::prelude::*;
+
+#[allow(dead_code)]
+fn replay(
+    global: &wgc::hub::Global<IdentityPassThroughFactory>,
+    device: wgc::id::DeviceId,
+    actions: Vec<wgc::device::trace::Action>,
+    dir: &std::path::Path,
+) {
+    let mut state = player::PlaybackState::new(Default::default(), &actions);
+    for action in actions {
+        gfx_select!(device => global.process(device, action, dir, &mut state))
+            .unwrap()
+            .unwrap();
+    }
+}
+
+#[test]
+fn test_prelude() {
+    let _global = wgc::hub::Global::new(
+        "prelude",
+        IdentityPassThroughFactory,
+        wgt::BackendBit::empty(),
+    );
+}
@@ -9,16 +9,19 @@
  *  Test requirements:
  *    - all IDs have the backend `Empty`
  *    - all expected buffers have `MAP_READ` usage
- *    - last action is `Submit`
- *    - no swapchain use
+ *    - last action is `Submit`, if there are any expectations
+ *    - swapchains are replayed with off-screen textures
 !*/
 
-use player::{gfx_select, GlobalPlay, IdentityPassThroughFactory};
+use player::{
+    gfx_select, DryRunPlayer, GlobalPlay, IdentityPassThroughFactory, PlaybackState, PlayerConfig,
+};
 use std::{
-    fs::{read_to_string, File},
+    fs::{self, read_to_string, File},
     path::{Path, PathBuf},
     ptr, slice,
 };
+use wgc::device::trace::{Action, Command};
 
 #[derive(serde::Deserialize)]
 struct RawId {
@@ -37,6 +40,8 @@ struct Expectation {
 #[derive(serde::Deserialize)]
 struct Test {
     features: wgt::Features,
+    #[serde(default)]
+    limits: wgt::Limits,
     expectations: Vec<Expectation>,
     actions: Vec<wgc::device::trace::Action>,
 }
@@ -67,24 +72,36 @@ impl Test {
         dir: &Path,
         global: &wgc::hub::Global<IdentityPassThroughFactory>,
         adapter: wgc::id::AdapterId,
+        config: &PlayerConfig,
     ) {
         let backend = adapter.backend();
         let device = gfx_select!(adapter => global.adapter_request_device(
             adapter,
             &wgt::DeviceDescriptor {
                 features: self.features | wgt::Features::MAPPABLE_PRIMARY_BUFFERS,
-                limits: wgt::Limits::default(),
+                limits: self.limits.clone(),
                 shader_validation: true,
             },
             None,
             wgc::id::TypedId::zip(1, 0, backend)
         ))
+        .unwrap()
         .unwrap();
 
-        let mut command_buffer_id_manager = wgc::hub::IdentityManager::default();
+        let mut state = PlaybackState::new(config.clone(), &self.actions);
         println!("\t\t\tRunning...");
-        for action in self.actions {
-            gfx_select!(device => global.process(device, action, dir, &mut command_buffer_id_manager));
+        let mut actions = self.actions.into_iter().peekable();
+        while let Some(action) = actions.next() {
+            let mut batch = vec![action];
+            while actions
+                .peek()
+                .map_or(false, |next| player::fits_batch(&config, &batch[0], next))
+            {
+                batch.push(actions.next().unwrap());
+            }
+            gfx_select!(device => global.process_batch(device, batch, dir, &mut state))
+                .unwrap()
+                .unwrap();
         }
         println!("\t\t\tMapping...");
         for expect in &self.expectations {
@@ -97,17 +114,21 @@ impl Test {
                     callback: map_callback,
                     user_data: ptr::null_mut(),
                 }
-            ));
+            ))
+            .unwrap();
         }
 
         println!("\t\t\tWaiting...");
-        gfx_select!(device => global.device_poll(device, true)).unwrap();
+        gfx_select!(device => global.device_poll(device, true))
+            .unwrap()
+            .unwrap();
 
         for expect in self.expectations {
             println!("\t\t\tChecking {}", expect.name);
             let buffer = wgc::id::TypedId::zip(expect.buffer.index, expect.buffer.epoch, backend);
             let ptr =
-                gfx_select!(device => global.buffer_get_mapped_range(buffer, expect.offset, None));
+                gfx_select!(device => global.buffer_get_mapped_range(buffer, expect.offset, None))
+                    .unwrap();
             let contents = unsafe { slice::from_raw_parts(ptr, expect.data.len()) };
             assert_eq!(&expect.data[..], contents);
         }
@@ -129,12 +150,15 @@ const BACKENDS: &[wgt::Backend] = &[
 ];
 
 impl Corpus {
-    fn run_from(path: PathBuf) {
+    /// Runs the tests of the corpus at `path` on each backend that has an
+    /// adapter, and returns the number of these backends.
+    fn run_from(path: PathBuf, config: PlayerConfig) -> usize {
         println!("Corpus {:?}", path);
         let dir = path.parent().unwrap();
         let corpus: Corpus = ron::de::from_reader(File::open(&path).unwrap()).unwrap();
 
         let global = wgc::hub::Global::new("test", IdentityPassThroughFactory, corpus.backends);
+        let mut backend_count = 0;
         for &backend in BACKENDS {
             if !corpus.backends.contains(backend.into()) {
                 continue;
@@ -154,7 +178,9 @@ impl Corpus {
             };
 
             println!("\tBackend {:?}", backend);
-            let supported_features = gfx_select!(adapter => global.adapter_features(adapter));
+            backend_count += 1;
+            let supported_features =
+                gfx_select!(adapter => global.adapter_features(adapter)).unwrap();
             for test_path in &corpus.tests {
                 println!("\t\tTest '{:?}'", test_path);
                 let test = Test::load(dir.join(test_path), adapter.backend());
@@ -165,13 +191,412 @@ impl Corpus {
                     );
                     continue;
                 }
-                test.run(dir, &global, adapter);
+                test.run(dir, &global, adapter, &config);
             }
         }
+        if backend_count == 0 {
+            println!("\tSkipped, there is no adapter to replay with");
+        }
+        backend_count
+    }
+}
+
+/// Creates an empty directory for the test files of `name`, named after
+/// the process too, so that concurrent runs don't share their files.
+fn test_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Runs the `test` with a device of each backend that has an adapter,
+/// tracing into a directory of `trace_dir` named after the backend, if any.
+fn for_each_device(
+    trace_dir: Option<&Path>,
+    mut test: impl FnMut(&wgc::hub::Global<IdentityPassThroughFactory>, wgc::id::DeviceId),
+) {
+    let global = wgc::hub::Global::new("test", IdentityPassThroughFactory, wgt::BackendBit::all());
+    let mut backend_count = 0;
+    for &backend in BACKENDS {
+        let adapter = match global.pick_adapter(
+            &wgc::instance::RequestAdapterOptions {
+                power_preference: wgt::PowerPreference::Default,
+                compatible_surface: None,
+            },
+            wgc::instance::AdapterInputs::IdSet(&[wgc::id::TypedId::zip(0, 0, backend)], |id| {
+                id.backend()
+            }),
+        ) {
+            Some(adapter) => adapter,
+            None => continue,
+        };
+        println!("\tBackend {:?}", backend);
+        backend_count += 1;
+        let trace_path = trace_dir.map(|dir| dir.join(format!("{:?}", backend)));
+        if let Some(ref path) = trace_path {
+            fs::create_dir_all(path).unwrap();
+        }
+        let device = gfx_select!(adapter => global.adapter_request_device(
+            adapter,
+            &wgt::DeviceDescriptor::default(),
+            trace_path.as_deref(),
+            wgc::id::TypedId::zip(1, 0, backend)
+        ))
+        .unwrap()
+        .unwrap();
+        test(&global, device);
+        gfx_select!(device => global.device_poll(device, true))
+            .unwrap()
+            .unwrap();
+    }
+    if backend_count == 0 {
+        println!("\tSkipped, there is no adapter to test with");
     }
 }
 
 #[test]
 fn test_api() {
-    Corpus::run_from(PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/data/all.ron"))
+    Corpus::run_from(
+        PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/data/all.ron"),
+        PlayerConfig::default(),
+    )
+}
+
+#[test]
+fn test_api_parallel() {
+    Corpus::run_from(
+        PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/data/all.ron"),
+        PlayerConfig {
+            parallel_creation: true,
+            ..PlayerConfig::default()
+        },
+    )
+}
+
+#[test]
+fn test_api_parallel_encoding() {
+    Corpus::run_from(
+        PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/data/all.ron"),
+        PlayerConfig {
+            parallel_encoding: true,
+            ..PlayerConfig::default()
+        },
+    )
+}
+
+#[test]
+#[cfg(feature = "png")]
+fn test_capture_frames() {
+    let capture_dir = test_dir("wgpu-player-capture");
+    let backend_count = Corpus::run_from(
+        PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/data/all.ron"),
+        PlayerConfig {
+            capture_dir: Some(capture_dir.clone()),
+            ..PlayerConfig::default()
+        },
+    );
+    // frames are only captured if there is an adapter to replay with
+    if backend_count == 0 {
+        return;
+    }
+    let entries = fs::read_dir(&capture_dir)
+        .unwrap()
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+    assert!(!entries.is_empty(), "no frame captured");
+    for entry in entries {
+        let path = entry.path();
+        let decoder = png::Decoder::new(File::open(&path).unwrap());
+        let (info, mut reader) = decoder.read_info().unwrap();
+        assert_eq!((info.width, info.height), (64, 64), "{:?}", path);
+        let mut pixels = vec![0; info.buffer_size()];
+        reader.next_frame(&mut pixels).unwrap();
+        assert_eq!(pixels[..4], [0x00, 0xFF, 0x00, 0xFF], "{:?}", path);
+    }
+}
+
+#[test]
+#[cfg(feature = "png")]
+fn test_compare_frames() {
+    let reference_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/data/reference");
+    // the replay would fail the present of a frame that doesn't match
+    Corpus::run_from(
+        PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/data/all.ron"),
+        PlayerConfig {
+            reference_dir: Some(reference_dir.clone()),
+            ..PlayerConfig::default()
+        },
+    );
+
+    // a frame whose first pixel is red instead of green
+    let reference = player::Frame::load(&reference_dir.join("frame0.png")).unwrap();
+    let mut frame = reference.clone();
+    frame.pixels[..4].copy_from_slice(&[0xFF, 0x00, 0x00, 0xFF]);
+    let result = player::compare_frames(0, &frame, &reference, 8).unwrap();
+    assert!(!result.passed());
+    assert_eq!(result.max_delta, 0xFF);
+    assert_eq!(result.worst_pixel, (0, 0));
+    assert!((result.mean_delta - 255.0 / (64.0 * 64.0)).abs() < 1e-9);
+    assert_eq!(
+        result.to_string(),
+        "frame 0 differs by up to 255 at (0, 0) (0.062 on average), the tolerance is 8"
+    );
+
+    // a frame of another size can't be compared at all
+    let small = player::Frame {
+        width: 32,
+        height: 32,
+        pixels: reference.pixels[..32 * 32 * 4].to_vec(),
+    };
+    let error = player::compare_frames(1, &small, &reference, 8).unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "frame 1 is 32x32, but the reference is 64x64"
+    );
+}
+
+#[test]
+#[cfg(feature = "png")]
+fn test_frame_difference() {
+    let reference_path =
+        PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/data/reference/frame0.png");
+    let reference = player::Frame::load(&reference_path).unwrap();
+    assert_eq!((reference.width, reference.height), (64, 64));
+    assert_eq!(reference.pixels[..4], [0x00, 0xFF, 0x00, 0xFF]);
+
+    let mut frame = reference.clone();
+    let result = player::compare_frames(0, &frame, &reference, 0).unwrap();
+    assert!(result.passed());
+    assert_eq!(result.max_delta, 0);
+
+    let offset = (3 * 64 + 5) * 4;
+    frame.pixels[offset] = 10;
+    frame.pixels[offset + 64 * 4 + 1] = 0xF9;
+    let result = player::compare_frames(0, &frame, &reference, 8).unwrap();
+    assert!(!result.passed());
+    assert_eq!(result.max_delta, 10);
+    assert_eq!(result.worst_pixel, (5, 3));
+    assert!((result.mean_delta - 16.0 / (64.0 * 64.0)).abs() < 1e-9);
+    assert!(player::compare_frames(0, &frame, &reference, 10)
+        .unwrap()
+        .passed());
+}
+
+#[test]
+fn test_dry_run() {
+    let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/data");
+    let corpus: Corpus = ron::de::from_reader(File::open(dir.join("all.ron")).unwrap()).unwrap();
+    for test_path in &corpus.tests {
+        let string = read_to_string(dir.join(test_path)).unwrap();
+        let test: Test = ron::de::from_str(&string).unwrap();
+        let errors = DryRunPlayer::run(&test.actions);
+        assert!(errors.is_empty(), "{:?}: {:?}", test_path, errors);
+    }
+}
+
+#[test]
+fn test_render_bundle_execution() {
+    use wgc::command::RenderCommand;
+
+    // `test_api` skips the backends without an adapter, so the recorded
+    // execution of the bundle is also checked without one
+    let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/data");
+    let test = Test::load(dir.join("render-bundle.ron"), wgt::Backend::Vulkan);
+    let mut bundles = Vec::new();
+    let mut executed = Vec::new();
+    for action in &test.actions {
+        match *action {
+            Action::CreateRenderBundle { id, ref base, .. } => {
+                let mut draws = 0;
+                for command in &base.commands {
+                    if let RenderCommand::Draw { .. } = *command {
+                        draws += 1;
+                    }
+                }
+                bundles.push((id, draws));
+            }
+            Action::Submit(_, _, _, ref commands) => {
+                for command in commands {
+                    if let Command::RunRenderPass { ref base, .. } = *command {
+                        executed.extend(base.commands.iter().filter_map(
+                            |command| match *command {
+                                RenderCommand::ExecuteBundle(id) => Some(id),
+                                _ => None,
+                            },
+                        ));
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    assert_eq!(bundles.len(), 1);
+    assert_eq!(bundles[0].1, 1, "the bundle should draw once");
+    assert_eq!(executed, [bundles[0].0]);
+    let errors = DryRunPlayer::run(&test.actions);
+    assert!(errors.is_empty(), "{:?}", errors);
+}
+
+#[test]
+fn test_destroyed_ids_high_water_mark() {
+    for_each_device(None, |global, device| {
+        let backend = device.backend();
+        let mut state = PlaybackState::new(Default::default(), &[]);
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+        // the recording application allocates its IDs like the core does
+        let mut recorder = wgc::hub::IdentityManager::default();
+        for index in 0..10_000 {
+            let id: wgc::id::BufferId = recorder.alloc(backend);
+            let encoder = wgc::id::TypedId::zip(0, index + 1, backend);
+            let actions = vec![
+                Action::CreateBuffer {
+                    id,
+                    desc: wgt::BufferDescriptor {
+                        label: None,
+                        size: 16,
+                        usage: wgt::BufferUsage::COPY_DST,
+                        mapped_at_creation: false,
+                    },
+                },
+                Action::DestroyBuffer(id),
+                Action::CreateCommandEncoder {
+                    id: encoder,
+                    desc: wgt::CommandEncoderDescriptor { label: None },
+                },
+                Action::Submit(index as usize + 1, None, encoder, Vec::new()),
+            ];
+            for action in actions {
+                gfx_select!(device => global.process(device, action, dir, &mut state))
+                    .unwrap()
+                    .unwrap();
+            }
+            // the core frees the buffer when maintaining the device on submit,
+            // which is when the index goes back to the recording application
+            recorder.free(id);
+            assert_eq!(state.index_high_water_mark(), 1);
+        }
+        // the encoders of the trace are created with their recorded IDs
+        assert_eq!(state.command_buffer_index_count(), 0);
+    });
+}
+
+#[test]
+fn test_dangling_id() {
+    for_each_device(None, |global, device| {
+        let mut state = PlaybackState::new(Default::default(), &[]);
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let action = Action::DestroyBuffer(wgc::id::TypedId::zip(0, 1, device.backend()));
+        let error = gfx_select!(device => global.process(device, action, dir, &mut state))
+            .unwrap()
+            .unwrap_err();
+        assert_eq!(error.action, "DestroyBuffer");
+        assert!(error.dangling_id().is_some(), "{}", error);
+    });
+}
+
+#[test]
+fn test_trace_frame_boundary() {
+    let trace_dir = test_dir("wgpu-player-frame-boundary");
+    for_each_device(Some(&trace_dir), |global, device| {
+        gfx_select!(device => global.device_add_trace_frame_boundary(device, 0)).unwrap();
+        gfx_select!(device => global.device_add_trace_frame_boundary(device, 1)).unwrap();
+        gfx_select!(device => global.device_flush_trace(device, false))
+            .unwrap()
+            .unwrap();
+        let path = trace_dir.join(format!("{:?}", device.backend()));
+        let frames = player::load_trace(&path)
+            .unwrap()
+            .actions
+            .iter()
+            .filter_map(|action| match *action {
+                Action::FrameBoundary { frame_index } => Some(frame_index),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(frames, [0, 1]);
+    });
+}
+
+#[test]
+fn test_trace_ron_config() {
+    let trace_dir = test_dir("wgpu-player-ron-config");
+    for_each_device(Some(&trace_dir), |global, device| {
+        gfx_select!(device => global.device_set_trace_ron_config(device, None)).unwrap();
+        gfx_select!(device => global.device_add_trace_frame_boundary(device, 7)).unwrap();
+        gfx_select!(device => global.device_flush_trace(device, false))
+            .unwrap()
+            .unwrap();
+        let path = trace_dir.join(format!("{:?}", device.backend()));
+        // traces recorded in bincode have no RON to format
+        if path.join(wgc::device::trace::BINARY_FILE_NAME).exists() {
+            println!("\t\tSkipped, the trace is recorded in bincode");
+            return;
+        }
+        let text = read_to_string(path.join(wgc::device::trace::FILE_NAME)).unwrap();
+        assert!(text
+            .lines()
+            .any(|line| line.contains("FrameBoundary(frame_index:7)")));
+    });
+}
+
+#[test]
+fn test_frame_range() {
+    let swap_chain = wgc::id::TypedId::zip(0, 1, wgt::Backend::Empty);
+    let mut actions = Vec::new();
+    for frame in 0..10 {
+        actions.push(Action::GetSwapChainTexture {
+            id: Some(wgc::id::TypedId::zip(frame, 1, wgt::Backend::Empty)),
+            parent_id: swap_chain,
+        });
+        actions.push(Action::Submit(
+            frame as usize + 1,
+            None,
+            wgc::id::TypedId::zip(frame, 1, wgt::Backend::Empty),
+            Vec::new(),
+        ));
+        actions.push(Action::PresentSwapChain(swap_chain));
+    }
+
+    let range = player::frame_range(&actions, 3..6);
+    assert_eq!(range, 9..18);
+    let submissions = actions[range]
+        .iter()
+        .filter_map(|action| match *action {
+            Action::Submit(index, ..) => Some(index),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(submissions, [4, 5, 6]);
+    assert_eq!(player::frame_range(&actions, 9..12), 27..30);
+    assert_eq!(player::frame_range(&actions, 12..15), 30..30);
+}
+
+#[test]
+fn test_gl_backend() {
+    let unsupported = player::UnsupportedBackend {
+        backend: wgt::Backend::Gl,
+    };
+    assert_eq!(player::check_backend(wgt::Backend::Gl), Err(unsupported));
+    let global =
+        wgc::hub::Global::new("test", IdentityPassThroughFactory, wgt::BackendBit::empty());
+    let device: wgc::id::DeviceId = wgc::id::TypedId::zip(0, 0, wgt::Backend::Gl);
+    let result = gfx_select!(device => global.device_poll(device, false));
+    assert_eq!(result.unwrap_err(), unsupported);
+}
+
+#[test]
+fn test_browser_webgpu_backend() {
+    let unsupported = player::UnsupportedBackend {
+        backend: wgt::Backend::BrowserWebGpu,
+    };
+    assert_eq!(
+        player::check_backend(wgt::Backend::BrowserWebGpu),
+        Err(unsupported)
+    );
+    let global =
+        wgc::hub::Global::new("test", IdentityPassThroughFactory, wgt::BackendBit::empty());
+    let device: wgc::id::DeviceId = wgc::id::TypedId::zip(0, 0, wgt::Backend::BrowserWebGpu);
+    let result = gfx_select!(device => global.device_poll(device, false));
+    assert_eq!(result.unwrap_err(), unsupported);
 }
@@ -6,7 +6,8 @@ use crate::{
     binding_model::{BindError, PushConstantUploadError},
     command::{
         bind::{Binder, LayoutChange},
-        BasePass, BasePassRef, CommandBuffer,
+        write_pass_timestamp, BasePass, BasePassRef, CommandBuffer, PassTimestampWrites,
+        QueryError,
     },
     device::all_buffer_stages,
     hub::{GfxBackend, Global, GlobalIdentityHandlerFactory, Token},
@@ -23,7 +24,7 @@ use wgt::{BufferAddress, BufferUsage};
 use std::{fmt, iter, str};
 
 #[doc(hidden)]
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 #[cfg_attr(
     any(feature = "serial-pass", feature = "trace"),
     derive(serde::Serialize)
@@ -64,6 +65,7 @@ pub enum ComputeCommand {
 pub struct ComputePass {
     base: BasePass<ComputeCommand>,
     parent_id: id::CommandEncoderId,
+    timestamp_writes: Option<PassTimestampWrites>,
 }
 
 impl ComputePass {
@@ -71,12 +73,18 @@ impl ComputePass {
         ComputePass {
             base: BasePass::new(),
             parent_id,
+            timestamp_writes: None,
         }
     }
 
     pub fn parent_id(&self) -> id::CommandEncoderId {
         self.parent_id
     }
+
+    /// Sets the timestamps to write at the beginning and at the end of the pass.
+    pub fn set_timestamp_writes(&mut self, timestamp_writes: Option<PassTimestampWrites>) {
+        self.timestamp_writes = timestamp_writes;
+    }
 }
 
 impl fmt::Debug for ComputePass {
@@ -124,6 +132,8 @@ pub enum ComputePassError {
     Bind(#[from] BindError),
     #[error(transparent)]
     PushConstants(#[from] PushConstantUploadError),
+    #[error(transparent)]
+    Query(#[from] QueryError),
 }
 
 // Common routines between render/compute
@@ -134,7 +144,11 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
         encoder_id: id::CommandEncoderId,
         pass: &ComputePass,
     ) -> Result<(), ComputePassError> {
-        self.command_encoder_run_compute_pass_impl::<B>(encoder_id, pass.base.as_ref())
+        self.command_encoder_run_compute_pass_impl::<B>(
+            encoder_id,
+            pass.base.as_ref(),
+            pass.timestamp_writes.as_ref(),
+        )
     }
 
     #[doc(hidden)]
@@ -142,6 +156,7 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
         &self,
         encoder_id: id::CommandEncoderId,
         mut base: BasePassRef<ComputeCommand>,
+        timestamp_writes: Option<&PassTimestampWrites>,
     ) -> Result<(), ComputePassError> {
         span!(_guard, INFO, "CommandEncoder::run_compute_pass");
         let hub = B::hub(self);
@@ -156,11 +171,13 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
             Some(ref mut list) => {
                 list.push(crate::device::trace::Command::RunComputePass {
                     base: BasePass::from_ref(base),
+                    timestamp_writes: timestamp_writes.cloned(),
                 });
             }
             None => {}
         }
 
+        let (query_set_guard, mut token) = hub.query_sets.read(&mut token);
         let (_, mut token) = hub.render_bundles.read(&mut token);
         let (pipeline_layout_guard, mut token) = hub.pipeline_layouts.read(&mut token);
         let (bind_group_guard, mut token) = hub.bind_groups.read(&mut token);
@@ -174,6 +191,19 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
             debug_scope_depth: 0,
         };
 
+        if let Some(writes) = timestamp_writes {
+            let query_set = &query_set_guard[writes.query_set];
+            writes.validate(query_set)?;
+            unsafe {
+                write_pass_timestamp(
+                    raw,
+                    query_set,
+                    writes.beginning_of_pass_write_index,
+                    hal::pso::PipelineStage::TOP_OF_PIPE,
+                );
+            }
+        }
+
         for command in base.commands {
             match *command {
                 ComputeCommand::SetBindGroup {
@@ -387,6 +417,16 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
             }
         }
 
+        if let Some(writes) = timestamp_writes {
+            unsafe {
+                write_pass_timestamp(
+                    raw,
+                    &query_set_guard[writes.query_set],
+                    writes.end_of_pass_write_index,
+                    hal::pso::PipelineStage::BOTTOM_OF_PIPE,
+                );
+            }
+        }
         Ok(())
     }
 }
@@ -0,0 +1,153 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use crate::{command::TextureCopyView, id::TextureId, resource::Texture};
+
+use thiserror::Error;
+use wgt::{Extent3d, Origin3d};
+
+/// Error encountered when a copy region doesn't fit in a texture.
+#[derive(Copy, Clone, Debug, Error, Eq, PartialEq)]
+pub enum CopyError {
+    #[error("mip level {level} is out of bounds of the {level_count} levels of {texture}")]
+    InvalidMipLevel {
+        texture: TextureId,
+        level: u32,
+        level_count: u32,
+    },
+    #[error("copy of {size:?} at {origin:?} is out of bounds of mip level {level} of {texture}, which is {extent:?}")]
+    OutOfBounds {
+        texture: TextureId,
+        level: u32,
+        origin: Origin3d,
+        size: Extent3d,
+        extent: Extent3d,
+    },
+}
+
+/// Checks that a copy of `size` fits in the mip levels of both the source
+/// and the destination textures.
+pub(crate) fn validate_texture_copy_bounds<B: hal::Backend>(
+    src: &TextureCopyView,
+    dst: &TextureCopyView,
+    size: &Extent3d,
+    src_tex: &Texture<B>,
+    dst_tex: &Texture<B>,
+) -> Result<(), CopyError> {
+    validate_texture_copy_view_bounds(src, size, src_tex)?;
+    validate_texture_copy_view_bounds(dst, size, dst_tex)
+}
+
+/// Checks that a copy of `size` fits in the mip level of `view`, for the
+/// copies that have a texture on one side only.
+pub(crate) fn validate_texture_copy_view_bounds<B: hal::Backend>(
+    view: &TextureCopyView,
+    size: &Extent3d,
+    texture: &Texture<B>,
+) -> Result<(), CopyError> {
+    validate_copy_extent(view, size, texture.kind, texture.full_range.levels.end)
+}
+
+fn validate_copy_extent(
+    view: &TextureCopyView,
+    size: &Extent3d,
+    kind: hal::image::Kind,
+    level_count: hal::image::Level,
+) -> Result<(), CopyError> {
+    if view.mip_level >= level_count as u32 {
+        return Err(CopyError::InvalidMipLevel {
+            texture: view.texture,
+            level: view.mip_level,
+            level_count: level_count as u32,
+        });
+    }
+
+    let level_extent = kind.level_extent(view.mip_level as hal::image::Level);
+    let extent = Extent3d {
+        width: level_extent.width,
+        height: level_extent.height,
+        // the layers of 2D textures are copied as the depth
+        depth: match kind {
+            hal::image::Kind::D2(_, _, array_layers, _) => array_layers as u32,
+            _ => level_extent.depth,
+        },
+    };
+    let fits = |origin: u32, size: u32, extent: u32| {
+        origin
+            .checked_add(size)
+            .map_or(false, |copy_max| copy_max <= extent)
+    };
+    if fits(view.origin.x, size.width, extent.width)
+        && fits(view.origin.y, size.height, extent.height)
+        && fits(view.origin.z, size.depth, extent.depth)
+    {
+        Ok(())
+    } else {
+        Err(CopyError::OutOfBounds {
+            texture: view.texture,
+            level: view.mip_level,
+            origin: view.origin,
+            size: *size,
+            extent,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn view(mip_level: u32, x: u32) -> TextureCopyView {
+        TextureCopyView {
+            texture: crate::id::TypedId::zip(0, 1, wgt::Backend::Empty),
+            mip_level,
+            origin: Origin3d { x, y: 0, z: 0 },
+        }
+    }
+
+    fn size(width: u32, height: u32) -> Extent3d {
+        Extent3d {
+            width,
+            height,
+            depth: 1,
+        }
+    }
+
+    #[test]
+    fn test_copy_extent() {
+        let kind = hal::image::Kind::D2(64, 32, 1, 1);
+        assert_eq!(
+            validate_copy_extent(&view(0, 0), &size(64, 32), kind, 3),
+            Ok(())
+        );
+        assert_eq!(
+            validate_copy_extent(&view(2, 8), &size(8, 8), kind, 3),
+            Ok(())
+        );
+        assert_eq!(
+            validate_copy_extent(&view(1, 8), &size(32, 16), kind, 3),
+            Err(CopyError::OutOfBounds {
+                texture: view(1, 8).texture,
+                level: 1,
+                origin: view(1, 8).origin,
+                size: size(32, 16),
+                extent: size(32, 16),
+            })
+        );
+        assert_eq!(
+            validate_copy_extent(&view(3, 0), &size(1, 1), kind, 3),
+            Err(CopyError::InvalidMipLevel {
+                texture: view(3, 0).texture,
+                level: 3,
+                level_count: 3,
+            })
+        );
+    }
+
+    #[test]
+    fn test_copy_extent_overflow() {
+        let kind = hal::image::Kind::D2(64, 32, 1, 1);
+        assert!(validate_copy_extent(&view(0, u32::MAX), &size(2, 1), kind, 1).is_err());
+    }
+}
@@ -6,6 +6,7 @@ mod allocator;
 mod bind;
 mod bundle;
 mod compute;
+mod copy;
 mod render;
 mod transfer;
 mod query;
@@ -13,10 +14,13 @@ mod query;
 pub(crate) use self::allocator::CommandAllocator;
 pub use self::bundle::*;
 pub use self::compute::*;
+pub use self::copy::*;
 pub use self::render::*;
 pub use self::transfer::*;
 pub use self::query::*;
 
+#[cfg(feature = "trace")]
+use crate::device::trace::Command as TraceCommand;
 use crate::{
     device::{all_buffer_stages, all_image_stages},
     hub::{GfxBackend, Global, GlobalIdentityHandlerFactory, Storage, Token},
@@ -98,7 +102,7 @@ pub struct BasePassRef<'a, C> {
 }
 
 #[doc(hidden)]
-#[derive(Debug)]
+#[derive(Debug, PartialEq)]
 #[cfg_attr(
     any(feature = "serial-pass", feature = "trace"),
     derive(serde::Serialize)
@@ -192,8 +196,14 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
 
         let (mut cmb_guard, _) = hub.command_buffers.write(&mut token);
         let cmb = &mut cmb_guard[encoder_id];
-        let cmb_raw = cmb.raw.last_mut().unwrap();
 
+        #[cfg(feature = "trace")]
+        match cmb.commands {
+            Some(ref mut list) => list.push(TraceCommand::PushDebugGroup(label.to_string())),
+            None => (),
+        }
+
+        let cmb_raw = cmb.raw.last_mut().unwrap();
         unsafe {
             cmb_raw.begin_debug_marker(label, 0);
         }
@@ -211,8 +221,14 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
 
         let (mut cmb_guard, _) = hub.command_buffers.write(&mut token);
         let cmb = &mut cmb_guard[encoder_id];
-        let cmb_raw = cmb.raw.last_mut().unwrap();
 
+        #[cfg(feature = "trace")]
+        match cmb.commands {
+            Some(ref mut list) => list.push(TraceCommand::InsertDebugMarker(label.to_string())),
+            None => (),
+        }
+
+        let cmb_raw = cmb.raw.last_mut().unwrap();
         unsafe {
             cmb_raw.insert_debug_marker(label, 0);
         }
@@ -226,8 +242,14 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
 
         let (mut cmb_guard, _) = hub.command_buffers.write(&mut token);
         let cmb = &mut cmb_guard[encoder_id];
-        let cmb_raw = cmb.raw.last_mut().unwrap();
 
+        #[cfg(feature = "trace")]
+        match cmb.commands {
+            Some(ref mut list) => list.push(TraceCommand::PopDebugGroup),
+            None => (),
+        }
+
+        let cmb_raw = cmb.raw.last_mut().unwrap();
         unsafe {
             cmb_raw.end_debug_marker();
         }
@@ -5,25 +5,377 @@
 use hal;
 use hal::command::CommandBuffer;
 
+#[cfg(feature = "trace")]
+use crate::device::trace::Command as TraceCommand;
+
 use crate::{
-    device::all_buffer_stages,
+    device::{all_buffer_stages, HostMap, WaitIdleError},
     hub::{GfxBackend, Global, GlobalIdentityHandlerFactory, Token},
     id::{BufferId, CommandEncoderId, QuerySetId},
-    resource::{BufferUse},
+    resource::{BufferMapAsyncStatus, BufferMapOperation, BufferUse, QuerySet, QuerySetType},
 };
+use thiserror::Error;
 use wgt::{
     BufferAddress, BufferUsage
 };
 
+use std::{collections::HashMap, slice};
+
 pub type QueryId = hal::query::Id;
 
+/// Alignment of the destination offset of a query set resolve.
+pub const QUERY_RESOLVE_BUFFER_ALIGNMENT: BufferAddress = 256;
+/// Size of a single value of a resolved query, which is also
+/// the alignment of a custom stride. Queries are resolved into 64-bit
+/// values, since timestamps don't fit in 32 bits.
+const QUERY_RESULT_SIZE: BufferAddress = 8;
+
+/// Order in which the values of a pipeline statistics query are resolved.
+const PIPELINE_STATISTICS_ORDER: [wgt::PipelineStatisticName; 5] = [
+    wgt::PipelineStatisticName::VertexShaderInvocations,
+    wgt::PipelineStatisticName::ClipperInvocations,
+    wgt::PipelineStatisticName::ClipperPrimitivesOut,
+    wgt::PipelineStatisticName::FragmentShaderInvocations,
+    wgt::PipelineStatisticName::ComputeShaderInvocations,
+];
+
+/// Statistics gathered by a pipeline statistics query with the given `names`,
+/// in the order they are resolved. Each statistic is only gathered once.
+pub(crate) fn resolved_pipeline_statistics(
+    names: &[wgt::PipelineStatisticName],
+) -> Vec<wgt::PipelineStatisticName> {
+    PIPELINE_STATISTICS_ORDER
+        .iter()
+        .filter(|name| names.contains(name))
+        .cloned()
+        .collect()
+}
+
+/// Size of a resolved query of the given type: one u64 for each value
+/// of the query information, and one u64 for the availability.
+pub(crate) fn query_resolve_stride(type_: &wgt::QueryType) -> BufferAddress {
+    let value_count = match *type_ {
+        wgt::QueryType::Occlusion | wgt::QueryType::Timestamp => 1,
+        wgt::QueryType::PipelineStatistics(names) => {
+            resolved_pipeline_statistics(names).len() as BufferAddress
+        }
+    };
+    (value_count + 1) * QUERY_RESULT_SIZE
+}
+
+/// Picks the stride of a resolve, which defaults to the `min_stride`
+/// of the query type.
+fn resolve_stride(
+    min_stride: BufferAddress,
+    stride: Option<BufferAddress>,
+) -> Result<BufferAddress, QueryError> {
+    match stride {
+        None => Ok(min_stride),
+        Some(stride) if stride < min_stride => {
+            Err(QueryError::StrideTooSmall { stride, min_stride })
+        }
+        Some(stride) if stride % QUERY_RESULT_SIZE != 0 => Err(QueryError::UnalignedStride(stride)),
+        Some(stride) => Ok(stride),
+    }
+}
+
+/// End of `query_count` queries resolved at `offset` with `stride`,
+/// or `None` if it overflows.
+fn resolve_end(
+    offset: BufferAddress,
+    stride: BufferAddress,
+    query_count: u32,
+) -> Option<BufferAddress> {
+    stride
+        .checked_mul(query_count as BufferAddress)
+        .and_then(|size| offset.checked_add(size))
+}
+
+/// Checks that a buffer with the given `usage` and `size` can be the
+/// destination of `query_count` queries resolved at `offset` with `stride`.
+fn check_resolve_destination(
+    usage: BufferUsage,
+    size: BufferAddress,
+    offset: BufferAddress,
+    stride: BufferAddress,
+    query_count: u32,
+) -> Result<(), QueryError> {
+    if !usage.contains(BufferUsage::COPY_DST) {
+        return Err(QueryError::MissingCopyDst { actual: usage });
+    }
+    if offset % QUERY_RESOLVE_BUFFER_ALIGNMENT != 0 {
+        return Err(QueryError::UnalignedDestinationOffset(offset));
+    }
+    match resolve_end(offset, stride, query_count) {
+        Some(end) if end <= size => Ok(()),
+        _ => Err(QueryError::DestinationBufferTooSmall {
+            query_count,
+            offset,
+            stride,
+            buffer_size: size,
+        }),
+    }
+}
+
+/// Checks that the `query_count` queries starting at `first_query`
+/// are within a set of `set_size` queries.
+fn check_query_range(first_query: u32, query_count: u32, set_size: u32) -> Result<(), QueryError> {
+    match first_query.checked_add(query_count) {
+        Some(end) if end <= set_size => Ok(()),
+        _ => Err(QueryError::QueryRangeOutOfBounds {
+            first_query,
+            query_count,
+            set_size,
+        }),
+    }
+}
+
+/// Checks that the queries of a set of type `type_` are of the `expected` kind.
+fn check_query_kind(type_: &QuerySetType, expected: QueryKind) -> Result<(), QueryError> {
+    let actual = type_.kind();
+    if actual != expected {
+        return Err(QueryError::WrongQueryType { expected, actual });
+    }
+    Ok(())
+}
+
+/// Type of the queries of a set, without the statistics they gather.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum QueryKind {
+    Occlusion,
+    PipelineStatistics,
+    Timestamp,
+}
+
+/// Error encountered while resetting or resolving a query set.
+#[derive(Clone, Debug, Error, PartialEq)]
+pub enum QueryError {
+    #[error("destination buffer usage {actual:?} is missing the `COPY_DST` flag")]
+    MissingCopyDst { actual: BufferUsage },
+    #[error(
+        "destination offset {0} is not a multiple of {}",
+        QUERY_RESOLVE_BUFFER_ALIGNMENT
+    )]
+    UnalignedDestinationOffset(BufferAddress),
+    #[error(
+        "destination stride {stride} is smaller than the size {min_stride} of a resolved query"
+    )]
+    StrideTooSmall {
+        stride: BufferAddress,
+        min_stride: BufferAddress,
+    },
+    #[error("destination stride {0} is not a multiple of {}", QUERY_RESULT_SIZE)]
+    UnalignedStride(BufferAddress),
+    #[error("resolving {query_count} queries at offset {offset} with a stride of {stride} overruns the destination buffer of size {buffer_size}")]
+    DestinationBufferTooSmall {
+        query_count: u32,
+        offset: BufferAddress,
+        stride: BufferAddress,
+        buffer_size: BufferAddress,
+    },
+    #[error("{query_count} queries starting at {first_query} are out of bounds of the query set of size {set_size}")]
+    QueryRangeOutOfBounds {
+        first_query: u32,
+        query_count: u32,
+        set_size: u32,
+    },
+    #[error("query set has {actual:?} queries instead of {expected:?} ones")]
+    WrongQueryType {
+        expected: QueryKind,
+        actual: QueryKind,
+    },
+}
+
+/// Timestamps written at the beginning and at the end of a compute or a render pass.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(
+    any(feature = "serial-pass", feature = "trace"),
+    derive(serde::Serialize)
+)]
+#[cfg_attr(
+    any(feature = "serial-pass", feature = "replay"),
+    derive(serde::Deserialize)
+)]
+pub struct PassTimestampWrites {
+    pub query_set: QuerySetId,
+    /// Query written before the commands of the pass, if any.
+    pub beginning_of_pass_write_index: Option<u32>,
+    /// Query written once the commands of the pass are done, if any.
+    pub end_of_pass_write_index: Option<u32>,
+}
+
+impl PassTimestampWrites {
+    /// Checks that the written queries are in `query_set`, which has to be
+    /// the timestamp query set of the writes.
+    pub(crate) fn validate<B: hal::Backend>(
+        &self,
+        query_set: &QuerySet<B>,
+    ) -> Result<(), QueryError> {
+        check_query_kind(&query_set.type_, QueryKind::Timestamp)?;
+        for &index in self
+            .beginning_of_pass_write_index
+            .iter()
+            .chain(self.end_of_pass_write_index.iter())
+        {
+            check_query_range(index, 1, query_set.count)?;
+        }
+        Ok(())
+    }
+}
+
+/// Writes a timestamp into the query `index` of `query_set`, if there is one.
+pub(crate) unsafe fn write_pass_timestamp<B: hal::Backend>(
+    raw: &mut B::CommandBuffer,
+    query_set: &QuerySet<B>,
+    index: Option<u32>,
+    stage: hal::pso::PipelineStage,
+) {
+    if let Some(index) = index {
+        let hal_query = hal::query::Query::<B> {
+            pool: &query_set.raw,
+            id: index,
+        };
+        raw.write_timestamp(stage, hal_query);
+    }
+}
+
+/// Values of a pipeline statistics query, in the order they are resolved.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PipelineStatistics(pub Vec<(wgt::PipelineStatisticName, u64)>);
+
+impl PipelineStatistics {
+    pub fn get(&self, name: wgt::PipelineStatisticName) -> Option<u64> {
+        self.0
+            .iter()
+            .find(|&&(other, _)| other == name)
+            .map(|&(_, value)| value)
+    }
+
+    /// Values of the statistics, looked up by name.
+    pub fn to_map(&self) -> HashMap<wgt::PipelineStatisticName, u64> {
+        self.0.iter().cloned().collect()
+    }
+}
+
+/// Results of the queries of a set, as read by `Global::query_set_read_results`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum QueryResults {
+    /// Value of each query of an occlusion or a timestamp set.
+    Values(Vec<u64>),
+    /// Values of each query of a pipeline statistics set.
+    PipelineStatistics(Vec<PipelineStatistics>),
+}
+
+/// Error encountered while reading the results of a query set.
+#[derive(Debug, Error)]
+pub enum QueryReadError {
+    #[error(transparent)]
+    Resolve(#[from] QueryError),
+    #[error("mapping the buffer failed with {0:?}")]
+    MapFailed(BufferMapAsyncStatus),
+    #[error(transparent)]
+    Wait(#[from] WaitIdleError),
+    /// The index counts from the first query that was read.
+    #[error("result of query {0} is not available")]
+    Unavailable(u32),
+    #[error("query set doesn't contain timestamps")]
+    NotTimestamps,
+    #[error("queries of a pipeline statistics set have multiple values")]
+    MultipleValues,
+    #[error(transparent)]
+    TimestampPeriod(#[from] TimestampPeriodError),
+}
+
+/// Error encountered while getting the timestamp period of a device.
+#[derive(Clone, Debug, Error, PartialEq)]
+pub enum TimestampPeriodError {
+    #[error("timestamp queries are not supported by the device")]
+    Unsupported,
+    #[error("device reports an invalid timestamp period of {0}")]
+    InvalidPeriod(f32),
+}
+
+/// Converts timestamp `ticks` to nanoseconds, given the `period` of a tick.
+fn ticks_to_nanoseconds(ticks: &[u64], period: f32) -> Vec<u64> {
+    ticks
+        .iter()
+        .map(|&tick| (tick as f64 * period as f64).round() as u64)
+        .collect()
+}
+
+/// Decodes the `value_count` values of each of the `count` queries resolved
+/// in `bytes` with the given `stride`, or `None` for the queries whose
+/// availability word is zero.
+fn decode_available_values(
+    bytes: &[u8],
+    count: u32,
+    stride: BufferAddress,
+    value_count: usize,
+) -> Vec<Option<Vec<u64>>> {
+    const VALUE_SIZE: usize = QUERY_RESULT_SIZE as usize;
+    let read_value = |offset: usize| {
+        let mut raw = [0; VALUE_SIZE];
+        raw.copy_from_slice(&bytes[offset..offset + VALUE_SIZE]);
+        u64::from_ne_bytes(raw)
+    };
+    (0..count)
+        .map(|query| {
+            let start = (query as BufferAddress * stride) as usize;
+            // the availability is resolved after the values
+            if read_value(start + value_count * VALUE_SIZE) == 0 {
+                return None;
+            }
+            Some(
+                (0..value_count)
+                    .map(|i| read_value(start + i * VALUE_SIZE))
+                    .collect(),
+            )
+        })
+        .collect()
+}
+
+/// Decodes the `count` queries resolved in `bytes` with the given `stride`,
+/// gathering the `statistics` if it's a pipeline statistics set.
+fn decode_query_results(
+    bytes: &[u8],
+    count: u32,
+    stride: BufferAddress,
+    statistics: &[wgt::PipelineStatisticName],
+) -> Result<QueryResults, QueryReadError> {
+    let value_count = statistics.len().max(1);
+    let mut queries = Vec::with_capacity(count as usize);
+    for (query, values) in decode_available_values(bytes, count, stride, value_count)
+        .into_iter()
+        .enumerate()
+    {
+        match values {
+            Some(values) => queries.push(values),
+            None => return Err(QueryReadError::Unavailable(query as u32)),
+        }
+    }
+    Ok(if statistics.is_empty() {
+        QueryResults::Values(queries.into_iter().map(|values| values[0]).collect())
+    } else {
+        QueryResults::PipelineStatistics(
+            queries
+                .into_iter()
+                .map(|values| PipelineStatistics(statistics.iter().cloned().zip(values).collect()))
+                .collect(),
+        )
+    })
+}
+
+extern "C" fn read_results_callback(status: BufferMapAsyncStatus, user_data: *mut u8) {
+    unsafe { *(user_data as *mut BufferMapAsyncStatus) = status };
+}
+
 impl<G: GlobalIdentityHandlerFactory> Global<G> {
     pub fn command_encoder_begin_pipeline_statistics_query<B: GfxBackend>(
         &self,
         command_encoder_id: CommandEncoderId,
         query_set: QuerySetId,
         query_index: u32,
-    ) {
+    ) -> Result<(), QueryError> {
         let hub = B::hub(self);
         let mut token = Token::root();
 
@@ -31,6 +383,7 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
         let cmb = &mut cmb_guard[command_encoder_id];
         let (query_set_guard, _) = hub.query_sets.read(&mut token);
         let query_set = &query_set_guard[query_set];
+        check_query_kind(&query_set.type_, QueryKind::PipelineStatistics)?;
 
         let cmb_raw = cmb.raw.last_mut().unwrap();
 
@@ -43,6 +396,7 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
             cmb_raw.reset_query_pool(&query_set.raw, query_index..(query_index + 1));
             cmb_raw.begin_query(hal_query, hal::query::ControlFlags::empty());
         }
+        Ok(())
     }
 
     pub fn command_encoder_end_pipeline_statistics_query<B: GfxBackend>(
@@ -50,7 +404,7 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
         command_encoder_id: CommandEncoderId,
         query_set: QuerySetId,
         query_index: u32,
-    ) {
+    ) -> Result<(), QueryError> {
         let hub = B::hub(self);
         let mut token = Token::root();
 
@@ -58,6 +412,7 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
         let cmb = &mut cmb_guard[command_encoder_id];
         let (query_set_guard, _) = hub.query_sets.read(&mut token);
         let query_set = &query_set_guard[query_set];
+        check_query_kind(&query_set.type_, QueryKind::PipelineStatistics)?;
 
         let cmb_raw = cmb.raw.last_mut().unwrap();
 
@@ -69,6 +424,7 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
         unsafe {
             cmb_raw.end_query(hal_query);
         }
+        Ok(())
     }
 
     pub fn command_encoder_write_timestamp<B: GfxBackend>(
@@ -77,15 +433,29 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
         query_set: QuerySetId,
         query_index: u32,
         pipeline_stage: hal::pso::PipelineStage,
-    ) {
+    ) -> Result<(), QueryError> {
         let hub = B::hub(self);
         let mut token = Token::root();
 
         let (mut cmb_guard, mut token) = hub.command_buffers.write(&mut token);
         let cmb = &mut cmb_guard[command_encoder_id];
         let (query_set_guard, _) = hub.query_sets.read(&mut token);
+        #[cfg(feature = "trace")]
+        let query_set_id = query_set;
         let query_set = &query_set_guard[query_set];
 
+        #[cfg(feature = "trace")]
+        match cmb.commands {
+            Some(ref mut list) => list.push(TraceCommand::WriteTimestamp {
+                query_set: query_set_id,
+                query_index,
+                pipeline_stage: pipeline_stage.bits(),
+            }),
+            None => (),
+        }
+
+        check_query_kind(&query_set.type_, QueryKind::Timestamp)?;
+
         let cmb_raw = cmb.raw.last_mut().unwrap();
 
         let hal_query = hal::query::Query::<B> {
@@ -96,8 +466,52 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
         unsafe {
             cmb_raw.write_timestamp(pipeline_stage, hal_query);
         }
+        Ok(())
+    }
+
+    /// Resets the `query_count` queries of `query_set` starting at `first_query`,
+    /// so that they can be written to again, for example at the start of a frame.
+    pub fn command_encoder_reset_query_set<B: GfxBackend>(
+        &self,
+        command_encoder_id: CommandEncoderId,
+        query_set: QuerySetId,
+        first_query: QueryId,
+        query_count: u32,
+    ) -> Result<(), QueryError> {
+        let hub = B::hub(self);
+        let mut token = Token::root();
+
+        let (mut cmb_guard, mut token) = hub.command_buffers.write(&mut token);
+        let cmb = &mut cmb_guard[command_encoder_id];
+        let (query_set_guard, _) = hub.query_sets.read(&mut token);
+        #[cfg(feature = "trace")]
+        let query_set_id = query_set;
+        let query_set = &query_set_guard[query_set];
+
+        #[cfg(feature = "trace")]
+        match cmb.commands {
+            Some(ref mut list) => list.push(TraceCommand::ResetQuerySet {
+                query_set: query_set_id,
+                first_query,
+                query_count,
+            }),
+            None => (),
+        }
+
+        check_query_range(first_query, query_count, query_set.count)?;
+
+        let cmb_raw = cmb.raw.last_mut().unwrap();
+        unsafe {
+            cmb_raw.reset_query_pool(&query_set.raw, first_query..(first_query + query_count));
+        }
+        Ok(())
     }
 
+    /// Resolves the `query_count` queries of `query_set` starting at `first_query`
+    /// into `destination`, each followed by its availability.
+    ///
+    /// If `wait` isn't set, the queries that aren't available yet are resolved
+    /// with an availability of zero instead of stalling the device.
     pub fn command_encoder_resolve_query_set<B: GfxBackend>(
         &self,
         command_encoder_id: CommandEncoderId,
@@ -106,31 +520,58 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
         query_count: u32,
         destination: BufferId,
         destination_offset: BufferAddress,
-    ) {
+        destination_stride: Option<BufferAddress>,
+        wait: bool,
+    ) -> Result<(), QueryError> {
         let hub = B::hub(self);
         let mut token = Token::root();
 
         let (mut cmb_guard, mut token) = hub.command_buffers.write(&mut token);
         let cmb = &mut cmb_guard[command_encoder_id];
         let (query_set_guard, mut token) = hub.query_sets.read(&mut token);
+        #[cfg(feature = "trace")]
+        let query_set_id = query_set;
         let query_set = &query_set_guard[query_set];
 
         let (buffer_guard, _) = hub.buffers.read(&mut token);
 
+        #[cfg(feature = "trace")]
+        match cmb.commands {
+            Some(ref mut list) => list.push(TraceCommand::ResolveQuerySet {
+                query_set: query_set_id,
+                first_query,
+                query_count,
+                destination,
+                destination_offset,
+                destination_stride,
+                wait,
+            }),
+            None => (),
+        }
+
+        check_query_range(first_query, query_count, query_set.count)?;
+        let stride = resolve_stride(query_set.resolve_stride, destination_stride)?;
+        let buffer = &buffer_guard[destination];
+        check_resolve_destination(
+            buffer.usage,
+            buffer.size,
+            destination_offset,
+            stride,
+            query_count,
+        )?;
+
         let (dst_buffer, dst_pending) = cmb.trackers.buffers.use_replace(
             &*buffer_guard,
             destination,
             (),
             BufferUse::COPY_DST,
         );
-        assert!(
-            dst_buffer.usage.contains(BufferUsage::COPY_DST),
-            "Destination buffer usage {:?} must contain usage flag COPY_DST",
-            dst_buffer.usage
-        );
         let dst_barrier = dst_pending.map(|pending| pending.into_hal(dst_buffer));
-
-        // Logic here to calculate stride, based on the query type, which is embedded within the query set.
+        let mut flags =
+            hal::query::ResultFlags::WITH_AVAILABILITY | hal::query::ResultFlags::BITS_64;
+        if wait {
+            flags |= hal::query::ResultFlags::WAIT;
+        }
 
         let cmb_raw = cmb.raw.last_mut().unwrap();
         unsafe {
@@ -144,10 +585,382 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
                 first_query..(first_query + query_count),
                 &dst_buffer.raw,
                 destination_offset,
-                // Stride is currently 8, one u32 for the query information and one u32 for the availability.
-                8,
-                hal::query::ResultFlags::WAIT | hal::query::ResultFlags::WITH_AVAILABILITY,
+                stride,
+                flags,
             );
         }
+        Ok(())
     }
+
+    /// Copies the `count` queries of `query_set` that were resolved into `buffer`
+    /// at `offset`, returning them along with their stride and the statistics
+    /// of the set.
+    fn read_resolved_queries<B: GfxBackend>(
+        &self,
+        query_set: QuerySetId,
+        buffer: BufferId,
+        offset: BufferAddress,
+        count: u32,
+        stride: Option<BufferAddress>,
+    ) -> Result<(Vec<u8>, BufferAddress, Vec<wgt::PipelineStatisticName>), QueryReadError> {
+        let hub = B::hub(self);
+
+        // the token is released before the buffer is mapped
+        let (device_id, stride, end, statistics) = {
+            let mut token = Token::root();
+            let (query_set_guard, mut token) = hub.query_sets.read(&mut token);
+            let query_set = &query_set_guard[query_set];
+            let (buffer_guard, _) = hub.buffers.read(&mut token);
+            let buffer_size = buffer_guard[buffer].size;
+
+            if offset % QUERY_RESOLVE_BUFFER_ALIGNMENT != 0 {
+                return Err(QueryError::UnalignedDestinationOffset(offset).into());
+            }
+            let stride = resolve_stride(query_set.resolve_stride, stride)?;
+            let end = match resolve_end(offset, stride, count) {
+                Some(end) if end <= buffer_size => end,
+                _ => {
+                    return Err(QueryError::DestinationBufferTooSmall {
+                        query_count: count,
+                        offset,
+                        stride,
+                        buffer_size,
+                    }
+                    .into())
+                }
+            };
+            let statistics = match query_set.type_ {
+                QuerySetType::PipelineStatistics(ref names) => names.clone(),
+                QuerySetType::Occlusion | QuerySetType::Timestamp => Vec::new(),
+            };
+            (
+                buffer_guard[buffer].device_id.value,
+                stride,
+                end,
+                statistics,
+            )
+        };
+
+        // the mapped range is addressed from the start of the mapping,
+        // so the buffer is mapped from its start
+        let mut status = BufferMapAsyncStatus::Unknown;
+        self.buffer_map_async::<B>(
+            buffer,
+            0..end,
+            BufferMapOperation {
+                host: HostMap::Read,
+                callback: read_results_callback,
+                user_data: &mut status as *mut BufferMapAsyncStatus as *mut u8,
+            },
+        );
+        self.device_poll::<B>(device_id, true)?;
+        match status {
+            BufferMapAsyncStatus::Success => {}
+            _ => return Err(QueryReadError::MapFailed(status)),
+        }
+
+        let ptr = self.buffer_get_mapped_range::<B>(buffer, offset, None);
+        let bytes = unsafe { slice::from_raw_parts(ptr, (end - offset) as usize) }.to_vec();
+        self.buffer_unmap::<B>(buffer);
+        Ok((bytes, stride, statistics))
+    }
+
+    /// Reads the results of `count` queries of `query_set` that were resolved
+    /// into `buffer` at `offset`, with the given `stride`, and decodes them
+    /// according to the type of the set.
+    ///
+    /// The buffer needs the `MAP_READ` usage. It's mapped and unmapped here,
+    /// waiting for the device to be done with the submitted work.
+    pub fn query_set_read_results<B: GfxBackend>(
+        &self,
+        query_set: QuerySetId,
+        buffer: BufferId,
+        offset: BufferAddress,
+        count: u32,
+        stride: Option<BufferAddress>,
+    ) -> Result<QueryResults, QueryReadError> {
+        let (bytes, stride, statistics) =
+            self.read_resolved_queries::<B>(query_set, buffer, offset, count, stride)?;
+        decode_query_results(&bytes, count, stride, &statistics)
+    }
+
+    /// Reads the results of an occlusion or a timestamp `query_set`, like
+    /// `query_set_read_results` does, but returns `None` for the queries
+    /// whose result isn't available instead of failing.
+    ///
+    /// This tells apart a query with a value of zero from a query that
+    /// wasn't ready when it was resolved without waiting.
+    pub fn query_set_read_available_values<B: GfxBackend>(
+        &self,
+        query_set: QuerySetId,
+        buffer: BufferId,
+        offset: BufferAddress,
+        count: u32,
+        stride: Option<BufferAddress>,
+    ) -> Result<Vec<Option<u64>>, QueryReadError> {
+        let (bytes, stride, statistics) =
+            self.read_resolved_queries::<B>(query_set, buffer, offset, count, stride)?;
+        if !statistics.is_empty() {
+            return Err(QueryReadError::MultipleValues);
+        }
+        Ok(decode_available_values(&bytes, count, stride, 1)
+            .into_iter()
+            .map(|values| values.map(|values| values[0]))
+            .collect())
+    }
+
+    /// Reads the results of `count` queries of the timestamp `query_set`,
+    /// like `query_set_read_results` does, and converts them to nanoseconds
+    /// with the timestamp period of the device.
+    pub fn query_set_read_timestamps<B: GfxBackend>(
+        &self,
+        query_set: QuerySetId,
+        buffer: BufferId,
+        offset: BufferAddress,
+        count: u32,
+        stride: Option<BufferAddress>,
+    ) -> Result<Vec<u64>, QueryReadError> {
+        let hub = B::hub(self);
+
+        let device_id = {
+            let mut token = Token::root();
+            let (query_set_guard, _) = hub.query_sets.read(&mut token);
+            let query_set = &query_set_guard[query_set];
+            if query_set.type_ != QuerySetType::Timestamp {
+                return Err(QueryReadError::NotTimestamps);
+            }
+            query_set.device_id.value
+        };
+        let period = self.device_timestamp_period::<B>(device_id)?;
+        match self.query_set_read_results::<B>(query_set, buffer, offset, count, stride)? {
+            QueryResults::Values(ticks) => Ok(ticks_to_nanoseconds(&ticks, period)),
+            QueryResults::PipelineStatistics(_) => unreachable!(),
+        }
+    }
+}
+
+/// Lays out `values` like they are resolved into a buffer.
+#[cfg(test)]
+fn encode(values: &[u64]) -> Vec<u8> {
+    values
+        .iter()
+        .flat_map(|value| value.to_ne_bytes().to_vec())
+        .collect()
+}
+
+#[test]
+fn test_decode_query_results() {
+    // timestamps take more than 32 bits
+    let timestamps = encode(&[100, 1, 1 << 40, 1]);
+    assert_eq!(
+        decode_query_results(&timestamps, 2, 16, &[]).unwrap(),
+        QueryResults::Values(vec![100, 1 << 40])
+    );
+    let timestamps = encode(&[100, 1, 250, 1]);
+    assert_eq!(
+        decode_query_results(&timestamps, 2, 16, &[]).unwrap(),
+        QueryResults::Values(vec![100, 250])
+    );
+    // a custom stride leaves a gap after each query
+    let timestamps = encode(&[100, 1, 0, 0, 250, 1, 0, 0]);
+    assert_eq!(
+        decode_query_results(&timestamps, 2, 32, &[]).unwrap(),
+        QueryResults::Values(vec![100, 250])
+    );
+    match decode_query_results(&encode(&[100, 1, 250, 0]), 2, 16, &[]) {
+        Err(QueryReadError::Unavailable(1)) => {}
+        other => panic!("Unexpected {:?}", other),
+    }
+
+    let names = [
+        wgt::PipelineStatisticName::FragmentShaderInvocations,
+        wgt::PipelineStatisticName::VertexShaderInvocations,
+        wgt::PipelineStatisticName::FragmentShaderInvocations,
+    ];
+    let statistics = resolved_pipeline_statistics(&names);
+    assert_eq!(
+        statistics,
+        [
+            wgt::PipelineStatisticName::VertexShaderInvocations,
+            wgt::PipelineStatisticName::FragmentShaderInvocations,
+        ]
+    );
+    let results = decode_query_results(&encode(&[3, 40, 1, 6, 80, 1]), 2, 24, &statistics);
+    let results = match results.unwrap() {
+        QueryResults::PipelineStatistics(results) => results,
+        other => panic!("Unexpected {:?}", other),
+    };
+    assert_eq!(results.len(), 2);
+    assert_eq!(
+        results[1].get(wgt::PipelineStatisticName::VertexShaderInvocations),
+        Some(6)
+    );
+    assert_eq!(
+        results[1].get(wgt::PipelineStatisticName::FragmentShaderInvocations),
+        Some(80)
+    );
+    assert_eq!(
+        results[0].get(wgt::PipelineStatisticName::ComputeShaderInvocations),
+        None
+    );
+}
+
+#[test]
+fn test_decode_pipeline_statistics() {
+    use wgt::PipelineStatisticName as N;
+
+    // the values are resolved in a fixed order, not in the order of the names
+    let statistics = resolved_pipeline_statistics(&[
+        N::FragmentShaderInvocations,
+        N::ClipperPrimitivesOut,
+        N::VertexShaderInvocations,
+    ]);
+    let stride = query_resolve_stride(&wgt::QueryType::PipelineStatistics(&statistics));
+    assert_eq!(stride, 32);
+    let bytes = encode(&[30, 10, 200, 1]);
+    let results = match decode_query_results(&bytes, 1, stride, &statistics).unwrap() {
+        QueryResults::PipelineStatistics(results) => results,
+        other => panic!("Unexpected {:?}", other),
+    };
+    let map = results[0].to_map();
+    assert_eq!(map.len(), 3);
+    assert_eq!(map[&N::VertexShaderInvocations], 30);
+    assert_eq!(map[&N::ClipperPrimitivesOut], 10);
+    assert_eq!(map[&N::FragmentShaderInvocations], 200);
+}
+
+#[test]
+fn test_decode_available_values() {
+    // an available zero is different from an unavailable query
+    let occlusion = encode(&[0, 1, 7, 0, 12, 3, 0, 0]);
+    let values = decode_available_values(&occlusion, 4, 16, 1);
+    assert_eq!(values, [Some(vec![0]), None, Some(vec![12]), None]);
+
+    // the values of queries resolved without waiting take 64 bits too
+    let timestamps = encode(&[1 << 40, 1, u64::MAX, 0]);
+    let values = decode_available_values(&timestamps, 2, 16, 1);
+    assert_eq!(values, [Some(vec![1 << 40]), None]);
+
+    let statistics = encode(&[3, 40, 0, 0, 0, 1, 6, 80, 1]);
+    let values = decode_available_values(&statistics, 3, 24, 2);
+    assert_eq!(values, [None, Some(vec![0, 0]), Some(vec![6, 80])]);
+}
+
+#[test]
+fn test_check_query_range() {
+    assert_eq!(check_query_range(0, 4, 4), Ok(()));
+    assert_eq!(check_query_range(4, 0, 4), Ok(()));
+    assert_eq!(
+        check_query_range(2, 3, 4),
+        Err(QueryError::QueryRangeOutOfBounds {
+            first_query: 2,
+            query_count: 3,
+            set_size: 4,
+        })
+    );
+    assert!(check_query_range(u32::MAX, 2, 4).is_err());
+}
+
+#[test]
+fn test_check_query_kind() {
+    let statistics =
+        QuerySetType::PipelineStatistics(vec![wgt::PipelineStatisticName::VertexShaderInvocations]);
+    assert_eq!(
+        check_query_kind(&QuerySetType::Timestamp, QueryKind::Timestamp),
+        Ok(())
+    );
+    assert_eq!(
+        check_query_kind(&statistics, QueryKind::PipelineStatistics),
+        Ok(())
+    );
+    // timestamps written into other sets
+    assert_eq!(
+        check_query_kind(&QuerySetType::Occlusion, QueryKind::Timestamp),
+        Err(QueryError::WrongQueryType {
+            expected: QueryKind::Timestamp,
+            actual: QueryKind::Occlusion,
+        })
+    );
+    assert_eq!(
+        check_query_kind(&statistics, QueryKind::Timestamp),
+        Err(QueryError::WrongQueryType {
+            expected: QueryKind::Timestamp,
+            actual: QueryKind::PipelineStatistics,
+        })
+    );
+    // statistics gathered into other sets
+    assert_eq!(
+        check_query_kind(&QuerySetType::Occlusion, QueryKind::PipelineStatistics),
+        Err(QueryError::WrongQueryType {
+            expected: QueryKind::PipelineStatistics,
+            actual: QueryKind::Occlusion,
+        })
+    );
+    assert_eq!(
+        check_query_kind(&QuerySetType::Timestamp, QueryKind::PipelineStatistics),
+        Err(QueryError::WrongQueryType {
+            expected: QueryKind::PipelineStatistics,
+            actual: QueryKind::Timestamp,
+        })
+    );
+}
+
+#[test]
+fn test_check_resolve_destination() {
+    let usage = BufferUsage::COPY_DST | BufferUsage::MAP_READ;
+    assert_eq!(check_resolve_destination(usage, 256, 0, 16, 16), Ok(()));
+    assert_eq!(
+        check_resolve_destination(BufferUsage::MAP_READ, 256, 0, 16, 16),
+        Err(QueryError::MissingCopyDst {
+            actual: BufferUsage::MAP_READ
+        })
+    );
+    assert_eq!(
+        check_resolve_destination(usage, 256, 8, 16, 1),
+        Err(QueryError::UnalignedDestinationOffset(8))
+    );
+    assert_eq!(
+        check_resolve_destination(usage, 256, 0, 16, 17),
+        Err(QueryError::DestinationBufferTooSmall {
+            query_count: 17,
+            offset: 0,
+            stride: 16,
+            buffer_size: 256,
+        })
+    );
+    // the end of the resolved queries overflowing
+    assert!(check_resolve_destination(usage, 256, 256, u64::MAX / 2, 4).is_err());
+}
+
+#[test]
+fn test_ticks_to_nanoseconds() {
+    assert_eq!(ticks_to_nanoseconds(&[0, 3, 1000], 1.0), [0, 3, 1000]);
+    assert_eq!(ticks_to_nanoseconds(&[0, 3, 1000], 52.08), [0, 156, 52080]);
+}
+
+#[test]
+fn test_resolve_stride() {
+    let names = [
+        wgt::PipelineStatisticName::VertexShaderInvocations,
+        wgt::PipelineStatisticName::FragmentShaderInvocations,
+    ];
+    assert_eq!(query_resolve_stride(&wgt::QueryType::Timestamp), 16);
+    assert_eq!(
+        query_resolve_stride(&wgt::QueryType::PipelineStatistics(&names)),
+        24
+    );
+    assert_eq!(resolve_stride(16, None), Ok(16));
+    assert_eq!(resolve_stride(16, Some(16)), Ok(16));
+    assert_eq!(resolve_stride(16, Some(256)), Ok(256));
+    assert_eq!(
+        resolve_stride(24, Some(16)),
+        Err(QueryError::StrideTooSmall {
+            stride: 16,
+            min_stride: 24
+        })
+    );
+    assert_eq!(
+        resolve_stride(16, Some(20)),
+        Err(QueryError::UnalignedStride(20))
+    );
 }
@@ -6,7 +6,8 @@ use crate::{
     binding_model::BindError,
     command::{
         bind::{Binder, LayoutChange},
-        BasePass, BasePassRef, RenderCommandError,
+        write_pass_timestamp, BasePass, BasePassRef, PassTimestampWrites, QueryError,
+        RenderCommandError,
     },
     conv,
     device::{
@@ -65,7 +66,7 @@ pub enum StoreOp {
 
 /// Describes an individual channel within a render pass, such as color, depth, or stencil.
 #[repr(C)]
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 #[cfg_attr(any(feature = "serial-pass", feature = "trace"), derive(Serialize))]
 #[cfg_attr(any(feature = "serial-pass", feature = "replay"), derive(Deserialize))]
 pub struct PassChannel<V> {
@@ -83,7 +84,7 @@ pub struct PassChannel<V> {
 
 /// Describes a color attachment to a render pass.
 #[repr(C)]
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 #[cfg_attr(any(feature = "serial-pass", feature = "trace"), derive(Serialize))]
 #[cfg_attr(any(feature = "serial-pass", feature = "replay"), derive(Deserialize))]
 pub struct ColorAttachmentDescriptor {
@@ -97,7 +98,7 @@ pub struct ColorAttachmentDescriptor {
 
 /// Describes a depth/stencil attachment to a render pass.
 #[repr(C)]
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 #[cfg_attr(any(feature = "serial-pass", feature = "trace"), derive(Serialize))]
 #[cfg_attr(any(feature = "serial-pass", feature = "replay"), derive(Deserialize))]
 pub struct DepthStencilAttachmentDescriptor {
@@ -131,7 +132,7 @@ fn is_depth_stencil_read_only(
 pub type RenderPassDescriptor<'a> =
     wgt::RenderPassDescriptor<'a, ColorAttachmentDescriptor, &'a DepthStencilAttachmentDescriptor>;
 
-#[derive(Clone, Copy, Debug, Default)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
 #[cfg_attr(any(feature = "serial-pass", feature = "trace"), derive(Serialize))]
 #[cfg_attr(any(feature = "serial-pass", feature = "replay"), derive(Deserialize))]
 pub struct Rect<T> {
@@ -142,7 +143,7 @@ pub struct Rect<T> {
 }
 
 #[doc(hidden)]
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 #[cfg_attr(any(feature = "serial-pass", feature = "trace"), derive(Serialize))]
 #[cfg_attr(any(feature = "serial-pass", feature = "replay"), derive(Deserialize))]
 pub enum RenderCommand {
@@ -227,6 +228,7 @@ pub struct RenderPass {
     parent_id: id::CommandEncoderId,
     color_targets: ArrayVec<[ColorAttachmentDescriptor; MAX_COLOR_TARGETS]>,
     depth_stencil_target: Option<DepthStencilAttachmentDescriptor>,
+    timestamp_writes: Option<PassTimestampWrites>,
 }
 
 impl RenderPass {
@@ -236,12 +238,18 @@ impl RenderPass {
             parent_id,
             color_targets: desc.color_attachments.iter().cloned().collect(),
             depth_stencil_target: desc.depth_stencil_attachment.cloned(),
+            timestamp_writes: None,
         }
     }
 
     pub fn parent_id(&self) -> id::CommandEncoderId {
         self.parent_id
     }
+
+    /// Sets the timestamps to write at the beginning and at the end of the pass.
+    pub fn set_timestamp_writes(&mut self, timestamp_writes: Option<PassTimestampWrites>) {
+        self.timestamp_writes = timestamp_writes;
+    }
 }
 
 impl fmt::Debug for RenderPass {
@@ -456,6 +464,8 @@ pub enum RenderPassError {
     Draw(#[from] DrawError),
     #[error(transparent)]
     Bind(#[from] BindError),
+    #[error(transparent)]
+    Query(#[from] QueryError),
 }
 
 impl From<MissingBufferUsageError> for RenderPassError {
@@ -494,6 +504,7 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
             pass.base.as_ref(),
             &pass.color_targets,
             pass.depth_stencil_target.as_ref(),
+            pass.timestamp_writes.as_ref(),
         )
     }
 
@@ -504,6 +515,7 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
         mut base: BasePassRef<RenderCommand>,
         color_attachments: &[ColorAttachmentDescriptor],
         depth_stencil_attachment: Option<&DepthStencilAttachmentDescriptor>,
+        timestamp_writes: Option<&PassTimestampWrites>,
     ) -> Result<(), RenderPassError> {
         span!(_guard, INFO, "CommandEncoder::run_render_pass");
 
@@ -525,6 +537,7 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
                     base: BasePass::from_ref(base),
                     target_colors: color_attachments.iter().cloned().collect(),
                     target_depth_stencil: depth_stencil_attachment.cloned(),
+                    timestamp_writes: timestamp_writes.cloned(),
                 });
             }
             None => {}
@@ -534,6 +547,7 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
             raw.begin_primary(hal::command::CommandBufferFlags::ONE_TIME_SUBMIT);
         }
 
+        let (query_set_guard, mut token) = hub.query_sets.read(&mut token);
         let (bundle_guard, mut token) = hub.render_bundles.read(&mut token);
         let (pipeline_layout_guard, mut token) = hub.pipeline_layouts.read(&mut token);
         let (bind_group_guard, mut token) = hub.bind_groups.read(&mut token);
@@ -542,6 +556,19 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
         let (texture_guard, mut token) = hub.textures.read(&mut token);
         let (view_guard, _) = hub.texture_views.read(&mut token);
 
+        if let Some(writes) = timestamp_writes {
+            let query_set = &query_set_guard[writes.query_set];
+            writes.validate(query_set)?;
+            unsafe {
+                write_pass_timestamp(
+                    &mut raw,
+                    query_set,
+                    writes.beginning_of_pass_write_index,
+                    hal::pso::PipelineStage::TOP_OF_PIPE,
+                );
+            }
+        }
+
         // We default to false intentionally, even if depth-stencil isn't used at all.
         // This allows us to use the primary raw pipeline in `RenderPipeline`,
         // instead of the special read-only one, which would be `None`.
@@ -1587,6 +1614,14 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
         log::trace!("Merging {:?} with the render pass", encoder_id);
         unsafe {
             raw.end_render_pass();
+            if let Some(writes) = timestamp_writes {
+                write_pass_timestamp(
+                    &mut raw,
+                    &query_set_guard[writes.query_set],
+                    writes.end_of_pass_write_index,
+                    hal::pso::PipelineStage::BOTTOM_OF_PIPE,
+                );
+            }
         }
 
         for ot in output_attachments {
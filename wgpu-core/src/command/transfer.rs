@@ -5,17 +5,21 @@
 #[cfg(feature = "trace")]
 use crate::device::trace::Command as TraceCommand;
 use crate::{
+    command::{validate_texture_copy_bounds, validate_texture_copy_view_bounds, CopyError},
     conv,
     device::{all_buffer_stages, all_image_stages},
     hub::{GfxBackend, Global, GlobalIdentityHandlerFactory, Storage, Token},
     id::{BufferId, CommandEncoderId, TextureId},
     resource::{BufferUse, Texture, TextureUse},
-    span,
+    span, PrivateFeatures,
 };
 
 use hal::command::CommandBuffer as _;
 use thiserror::Error;
-use wgt::{BufferAddress, BufferUsage, Extent3d, TextureDataLayout, TextureUsage};
+use wgt::{
+    BufferAddress, BufferSize, BufferUsage, Extent3d, ImageSubresourceRange, TextureAspect,
+    TextureDataLayout, TextureFormat, TextureUsage,
+};
 
 use std::iter;
 
@@ -36,6 +40,13 @@ pub enum TransferError {
     MissingCopyDstUsageFlag,
     #[error("copy would end up overruning the bounds of the destination buffer/texture")]
     BufferOverrun,
+    #[error("copy of {size} bytes at offset {offset} is out of bounds of the {buffer_size} bytes of {buffer}")]
+    BufferOutOfBounds {
+        buffer: BufferId,
+        buffer_size: BufferAddress,
+        offset: BufferAddress,
+        size: BufferAddress,
+    },
     #[error("buffer offset is not aligned to block size")]
     UnalignedBufferOffset,
     #[error("copy size is not a multiple of block size")]
@@ -54,8 +65,73 @@ pub enum TransferError {
     InvalidCopySize,
     #[error("number of rows per image is invalid")]
     InvalidRowsPerImage,
+    #[error("cannot copy from a {src_format:?} texture to a {dst_format:?} texture: {mismatch}")]
+    IncompatibleTextures {
+        src_format: TextureFormat,
+        dst_format: TextureFormat,
+        mismatch: TextureCopyMismatch,
+    },
+    #[error("subresource range is not contained in the texture")]
+    InvalidSubresourceRange,
+    #[error(transparent)]
+    Copy(#[from] CopyError),
+}
+
+/// Reason why two textures can't be copied between.
+#[derive(Copy, Clone, Debug, Error, Eq, PartialEq)]
+pub enum TextureCopyMismatch {
+    #[error("texel blocks are {src} and {dst} bytes in size")]
+    BlockSize { src: u32, dst: u32 },
     #[error("source and destination layers have different aspects")]
-    MismatchedAspects,
+    Aspects,
+    #[error("textures have {src} and {dst} samples")]
+    SampleCount { src: u8, dst: u8 },
+}
+
+/// Properties of a texture that need to match on both sides of a copy.
+#[derive(Clone, Copy, Debug)]
+struct TextureCopyTraits {
+    format: TextureFormat,
+    block_size: u32,
+    aspects: hal::format::Aspects,
+    samples: u8,
+}
+
+impl TextureCopyTraits {
+    fn new<B: hal::Backend>(texture: &Texture<B>, private_features: PrivateFeatures) -> Self {
+        TextureCopyTraits {
+            format: texture.format,
+            block_size: conv::map_texture_format(texture.format, private_features)
+                .surface_desc()
+                .bits as u32
+                / BITS_PER_BYTE,
+            aspects: texture.full_range.aspects,
+            samples: texture.kind.num_samples(),
+        }
+    }
+}
+
+fn validate_texture_copy_compatibility(src: &TextureCopyTraits, dst: &TextureCopyTraits) -> Result {
+    let mismatch = if src.block_size != dst.block_size {
+        TextureCopyMismatch::BlockSize {
+            src: src.block_size,
+            dst: dst.block_size,
+        }
+    } else if src.aspects != dst.aspects {
+        TextureCopyMismatch::Aspects
+    } else if src.samples != dst.samples {
+        TextureCopyMismatch::SampleCount {
+            src: src.samples,
+            dst: dst.samples,
+        }
+    } else {
+        return Ok(());
+    };
+    Err(TransferError::IncompatibleTextures {
+        src_format: src.format,
+        dst_format: dst.format,
+        mismatch,
+    })
 }
 
 //TODO: we currently access each texture twice for a transfer,
@@ -100,6 +176,69 @@ pub(crate) fn texture_copy_view_to_hal<B: hal::Backend>(
     )
 }
 
+/// Checks that the `size` bytes at `offset` are within the buffer, including
+/// when their end doesn't fit into a `BufferAddress`.
+pub(crate) fn validate_buffer_copy_range(
+    buffer: BufferId,
+    buffer_size: BufferAddress,
+    offset: BufferAddress,
+    size: BufferAddress,
+) -> Result {
+    match offset.checked_add(size) {
+        Some(end) if end <= buffer_size => Ok(()),
+        _ => Err(TransferError::BufferOutOfBounds {
+            buffer,
+            buffer_size,
+            offset,
+            size,
+        }),
+    }
+}
+
+/// Resolves the `subresource_range` of a clear against the `full_range` of
+/// its texture, checking that it's contained in the texture, including when
+/// its end doesn't fit into a `u32`.
+pub(crate) fn clear_subresource_range(
+    full_range: &hal::image::SubresourceRange,
+    subresource_range: &ImageSubresourceRange,
+) -> std::result::Result<hal::image::SubresourceRange, TransferError> {
+    let aspects = full_range.aspects
+        & match subresource_range.aspect {
+            TextureAspect::All => hal::format::Aspects::all(),
+            TextureAspect::DepthOnly => hal::format::Aspects::DEPTH,
+            TextureAspect::StencilOnly => hal::format::Aspects::STENCIL,
+        };
+    let end_level = if subresource_range.level_count == 0 {
+        full_range.levels.end as u32
+    } else {
+        subresource_range
+            .base_mip_level
+            .checked_add(subresource_range.level_count)
+            .ok_or(TransferError::InvalidSubresourceRange)?
+    };
+    let end_layer = if subresource_range.array_layer_count == 0 {
+        full_range.layers.end as u32
+    } else {
+        subresource_range
+            .base_array_layer
+            .checked_add(subresource_range.array_layer_count)
+            .ok_or(TransferError::InvalidSubresourceRange)?
+    };
+    if aspects.is_empty()
+        || subresource_range.base_mip_level >= end_level
+        || end_level > full_range.levels.end as u32
+        || subresource_range.base_array_layer >= end_layer
+        || end_layer > full_range.layers.end as u32
+    {
+        return Err(TransferError::InvalidSubresourceRange);
+    }
+    Ok(hal::image::SubresourceRange {
+        aspects,
+        levels: subresource_range.base_mip_level as u8..end_level as u8,
+        layers: subresource_range.base_array_layer as u16..end_layer as u16,
+    })
+}
+
 /// Function copied with minor modifications from webgpu standard https://gpuweb.github.io/gpuweb/#valid-texture-copy-range
 pub(crate) fn validate_linear_texture_data(
     layout: &TextureDataLayout,
@@ -166,8 +305,8 @@ pub(crate) fn validate_linear_texture_data(
 }
 
 /// Function copied with minor modifications from webgpu standard https://gpuweb.github.io/gpuweb/#valid-texture-copy-range
+/// The bounds of the copy are checked separately by `validate_texture_copy_bounds`.
 pub(crate) fn validate_texture_copy_range(
-    texture_copy_view: &TextureCopyView,
     texture_dimension: hal::image::Kind,
     copy_size: &Extent3d,
 ) -> Result {
@@ -175,30 +314,10 @@ pub(crate) fn validate_texture_copy_range(
     let block_width: u32 = 1;
     let block_height: u32 = 1;
 
-    let mut extent = texture_dimension.level_extent(texture_copy_view.mip_level as u8);
-    match texture_dimension {
-        hal::image::Kind::D1(..) => {
-            if (copy_size.height, copy_size.depth) != (1, 1) {
-                return Err(TransferError::InvalidCopySize);
-            }
+    if let hal::image::Kind::D1(..) = texture_dimension {
+        if (copy_size.height, copy_size.depth) != (1, 1) {
+            return Err(TransferError::InvalidCopySize);
         }
-        hal::image::Kind::D2(_, _, array_layers, _) => {
-            extent.depth = array_layers as u32;
-        }
-        hal::image::Kind::D3(..) => {}
-    };
-
-    let x_copy_max = texture_copy_view.origin.x + copy_size.width;
-    if x_copy_max > extent.width {
-        return Err(TransferError::BufferOverrun);
-    }
-    let y_copy_max = texture_copy_view.origin.y + copy_size.height;
-    if y_copy_max > extent.height {
-        return Err(TransferError::BufferOverrun);
-    }
-    let z_copy_max = texture_copy_view.origin.z + copy_size.depth;
-    if z_copy_max > extent.depth {
-        return Err(TransferError::BufferOverrun);
     }
 
     if copy_size.width % block_width != 0 {
@@ -277,14 +396,8 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
             return Err(TransferError::UnalignedBufferOffset);
         }
 
-        let source_end_offset = source_offset + size;
-        let destination_end_offset = destination_offset + size;
-        if source_end_offset > src_buffer.size {
-            return Err(TransferError::BufferOverrun);
-        }
-        if destination_end_offset > dst_buffer.size {
-            return Err(TransferError::BufferOverrun);
-        }
+        validate_buffer_copy_range(source, src_buffer.size, source_offset, size)?;
+        validate_buffer_copy_range(destination, dst_buffer.size, destination_offset, size)?;
 
         let region = hal::command::BufferCopy {
             src: source_offset,
@@ -331,7 +444,7 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
             None => (),
         }
 
-        if copy_size.width == 0 || copy_size.height == 0 || copy_size.width == 0 {
+        if copy_size.width == 0 || copy_size.height == 0 || copy_size.depth == 0 {
             log::trace!("Ignoring copy_buffer_to_texture of size 0");
             return Ok(());
         }
@@ -370,7 +483,8 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
         if src_bytes_per_row % bytes_per_row_alignment != 0 {
             return Err(TransferError::UnalignedBytesPerRow);
         }
-        validate_texture_copy_range(destination, dst_texture.kind, copy_size)?;
+        validate_texture_copy_view_bounds(destination, copy_size, dst_texture)?;
+        validate_texture_copy_range(dst_texture.kind, copy_size)?;
         validate_linear_texture_data(
             &source.layout,
             src_buffer.size,
@@ -431,7 +545,7 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
             None => (),
         }
 
-        if copy_size.width == 0 || copy_size.height == 0 || copy_size.width == 0 {
+        if copy_size.width == 0 || copy_size.height == 0 || copy_size.depth == 0 {
             log::trace!("Ignoring copy_texture_to_buffer of size 0");
             return Ok(());
         }
@@ -470,7 +584,8 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
         if dst_bytes_per_row % bytes_per_row_alignment != 0 {
             return Err(TransferError::UnalignedBytesPerRow);
         }
-        validate_texture_copy_range(source, src_texture.kind, copy_size)?;
+        validate_texture_copy_view_bounds(source, copy_size, src_texture)?;
+        validate_texture_copy_range(src_texture.kind, copy_size)?;
         validate_linear_texture_data(
             &destination.layout,
             dst_buffer.size,
@@ -526,9 +641,10 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
         let (src_layers, src_range, src_offset) = texture_copy_view_to_hal(source, &*texture_guard);
         let (dst_layers, dst_range, dst_offset) =
             texture_copy_view_to_hal(destination, &*texture_guard);
-        if src_layers.aspects != dst_layers.aspects {
-            return Err(TransferError::MismatchedAspects);
-        }
+        validate_texture_copy_compatibility(
+            &TextureCopyTraits::new(&texture_guard[source.texture], cmb.private_features),
+            &TextureCopyTraits::new(&texture_guard[destination.texture], cmb.private_features),
+        )?;
 
         #[cfg(feature = "trace")]
         match cmb.commands {
@@ -540,7 +656,7 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
             None => (),
         }
 
-        if copy_size.width == 0 || copy_size.height == 0 || copy_size.width == 0 {
+        if copy_size.width == 0 || copy_size.height == 0 || copy_size.depth == 0 {
             log::trace!("Ignoring copy_texture_to_texture of size 0");
             return Ok(());
         }
@@ -567,8 +683,9 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
         }
         barriers.extend(dst_pending.map(|pending| pending.into_hal(dst_texture)));
 
-        validate_texture_copy_range(source, src_texture.kind, copy_size)?;
-        validate_texture_copy_range(destination, dst_texture.kind, copy_size)?;
+        validate_texture_copy_bounds(source, destination, copy_size, src_texture, dst_texture)?;
+        validate_texture_copy_range(src_texture.kind, copy_size)?;
+        validate_texture_copy_range(dst_texture.kind, copy_size)?;
 
         let region = hal::command::ImageCopy {
             src_subresource: src_layers,
@@ -594,4 +711,273 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
         }
         Ok(())
     }
+
+    pub fn command_encoder_clear_buffer<B: GfxBackend>(
+        &self,
+        command_encoder_id: CommandEncoderId,
+        destination: BufferId,
+        offset: BufferAddress,
+        size: Option<BufferSize>,
+    ) -> Result {
+        span!(_guard, INFO, "CommandEncoder::clear_buffer");
+
+        let hub = B::hub(self);
+        let mut token = Token::root();
+
+        let (mut cmb_guard, mut token) = hub.command_buffers.write(&mut token);
+        let cmb = &mut cmb_guard[command_encoder_id];
+        let (buffer_guard, _) = hub.buffers.read(&mut token);
+
+        #[cfg(feature = "trace")]
+        match cmb.commands {
+            Some(ref mut list) => list.push(TraceCommand::ClearBuffer {
+                dst: destination,
+                offset,
+                size,
+            }),
+            None => (),
+        }
+
+        // the clear is validated before its buffer is tracked
+        let buffer = &buffer_guard[destination];
+        if !buffer.usage.contains(BufferUsage::COPY_DST) {
+            return Err(TransferError::MissingCopyDstUsageFlag);
+        }
+        if offset % wgt::COPY_BUFFER_ALIGNMENT != 0 {
+            return Err(TransferError::UnalignedBufferOffset);
+        }
+        let end_offset = match size {
+            Some(size) => {
+                if size.get() % wgt::COPY_BUFFER_ALIGNMENT != 0 {
+                    return Err(TransferError::UnalignedCopySize);
+                }
+                offset
+                    .checked_add(size.get())
+                    .ok_or(TransferError::BufferOverrun)?
+            }
+            None => buffer.size,
+        };
+        if offset > end_offset || end_offset > buffer.size {
+            return Err(TransferError::BufferOverrun);
+        }
+        if offset == end_offset {
+            log::trace!("Ignoring clear_buffer of size 0");
+            return Ok(());
+        }
+
+        let (dst_buffer, dst_pending) =
+            cmb.trackers
+                .buffers
+                .use_replace(&*buffer_guard, destination, (), BufferUse::COPY_DST);
+        let barriers = dst_pending.map(|pending| pending.into_hal(dst_buffer));
+
+        let cmb_raw = cmb.raw.last_mut().unwrap();
+        unsafe {
+            cmb_raw.pipeline_barrier(
+                all_buffer_stages()..hal::pso::PipelineStage::TRANSFER,
+                hal::memory::Dependencies::empty(),
+                barriers,
+            );
+            cmb_raw.fill_buffer(
+                &dst_buffer.raw,
+                hal::buffer::SubRange {
+                    offset,
+                    size: Some(end_offset - offset),
+                },
+                0,
+            );
+        }
+        Ok(())
+    }
+
+    pub fn command_encoder_clear_texture<B: GfxBackend>(
+        &self,
+        command_encoder_id: CommandEncoderId,
+        destination: TextureId,
+        subresource_range: &ImageSubresourceRange,
+    ) -> Result {
+        span!(_guard, INFO, "CommandEncoder::clear_texture");
+
+        let hub = B::hub(self);
+        let mut token = Token::root();
+
+        let (mut cmb_guard, mut token) = hub.command_buffers.write(&mut token);
+        let cmb = &mut cmb_guard[command_encoder_id];
+        let (_, mut token) = hub.buffers.read(&mut token); // skip token
+        let (texture_guard, _) = hub.textures.read(&mut token);
+
+        #[cfg(feature = "trace")]
+        match cmb.commands {
+            Some(ref mut list) => list.push(TraceCommand::ClearTexture {
+                dst: destination,
+                subresource_range: subresource_range.clone(),
+            }),
+            None => (),
+        }
+
+        // the clear is validated before its texture is tracked
+        let texture = &texture_guard[destination];
+        if !texture.usage.contains(TextureUsage::COPY_DST) {
+            return Err(TransferError::MissingCopyDstUsageFlag);
+        }
+        let range = clear_subresource_range(&texture.full_range, subresource_range)?;
+
+        let (dst_texture, dst_pending) = cmb.trackers.textures.use_replace(
+            &*texture_guard,
+            destination,
+            range.clone(),
+            TextureUse::COPY_DST,
+        );
+        let barriers = dst_pending.map(|pending| pending.into_hal(dst_texture));
+
+        // Zeroed color and depth-stencil values share the same bits,
+        // so this works for any aspect.
+        let value = hal::command::ClearValue {
+            color: hal::command::ClearColor { float32: [0.0; 4] },
+        };
+        let cmb_raw = cmb.raw.last_mut().unwrap();
+        unsafe {
+            cmb_raw.pipeline_barrier(
+                all_image_stages()..hal::pso::PipelineStage::TRANSFER,
+                hal::memory::Dependencies::empty(),
+                barriers,
+            );
+            cmb_raw.clear_image(
+                &dst_texture.raw,
+                hal::image::Layout::TransferDstOptimal,
+                value,
+                iter::once(range),
+            );
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn test_buffer_copy_range() {
+    let buffer = crate::id::TypedId::zip(0, 1, wgt::Backend::Empty);
+    let out_of_bounds = |offset, size| {
+        Err(TransferError::BufferOutOfBounds {
+            buffer,
+            buffer_size: 256,
+            offset,
+            size,
+        })
+    };
+    assert_eq!(validate_buffer_copy_range(buffer, 256, 0, 256), Ok(()));
+    assert_eq!(validate_buffer_copy_range(buffer, 256, 128, 128), Ok(()));
+    // source or destination range going past the end
+    assert_eq!(
+        validate_buffer_copy_range(buffer, 256, 0, 260),
+        out_of_bounds(0, 260)
+    );
+    assert_eq!(
+        validate_buffer_copy_range(buffer, 256, 252, 8),
+        out_of_bounds(252, 8)
+    );
+    // end of the range overflowing
+    assert_eq!(
+        validate_buffer_copy_range(buffer, 256, u64::MAX - 3, 8),
+        out_of_bounds(u64::MAX - 3, 8)
+    );
+}
+
+#[test]
+fn test_clear_subresource_range() {
+    let full_range = hal::image::SubresourceRange {
+        aspects: hal::format::Aspects::COLOR,
+        levels: 0..4,
+        layers: 0..6,
+    };
+    let subresource_range =
+        |base_mip_level, level_count, base_array_layer, array_layer_count| ImageSubresourceRange {
+            aspect: TextureAspect::All,
+            base_mip_level,
+            level_count,
+            base_array_layer,
+            array_layer_count,
+        };
+    assert_eq!(
+        clear_subresource_range(&full_range, &subresource_range(0, 0, 0, 0)),
+        Ok(full_range.clone())
+    );
+    assert_eq!(
+        clear_subresource_range(&full_range, &subresource_range(1, 2, 3, 0)),
+        Ok(hal::image::SubresourceRange {
+            aspects: hal::format::Aspects::COLOR,
+            levels: 1..3,
+            layers: 3..6,
+        })
+    );
+    // range going past the last level or layer
+    assert_eq!(
+        clear_subresource_range(&full_range, &subresource_range(2, 3, 0, 0)),
+        Err(TransferError::InvalidSubresourceRange)
+    );
+    assert_eq!(
+        clear_subresource_range(&full_range, &subresource_range(0, 0, 6, 0)),
+        Err(TransferError::InvalidSubresourceRange)
+    );
+    // end of the range overflowing
+    assert_eq!(
+        clear_subresource_range(&full_range, &subresource_range(1, u32::MAX, 0, 0)),
+        Err(TransferError::InvalidSubresourceRange)
+    );
+    assert_eq!(
+        clear_subresource_range(&full_range, &subresource_range(0, 0, 1, u32::MAX)),
+        Err(TransferError::InvalidSubresourceRange)
+    );
+    // no depth to clear in a color texture
+    assert_eq!(
+        clear_subresource_range(
+            &full_range,
+            &ImageSubresourceRange {
+                aspect: TextureAspect::DepthOnly,
+                ..subresource_range(0, 0, 0, 0)
+            }
+        ),
+        Err(TransferError::InvalidSubresourceRange)
+    );
+}
+
+#[test]
+fn test_texture_copy_compatibility() {
+    use hal::format::Aspects;
+    let traits = |format, block_size, aspects, samples| TextureCopyTraits {
+        format,
+        block_size,
+        aspects,
+        samples,
+    };
+    let rgba8 = traits(TextureFormat::Rgba8Unorm, 4, Aspects::COLOR, 1);
+    assert_eq!(validate_texture_copy_compatibility(&rgba8, &rgba8), Ok(()));
+    assert_eq!(
+        validate_texture_copy_compatibility(
+            &rgba8,
+            &traits(TextureFormat::Rgba16Float, 8, Aspects::COLOR, 1)
+        ),
+        Err(TransferError::IncompatibleTextures {
+            src_format: TextureFormat::Rgba8Unorm,
+            dst_format: TextureFormat::Rgba16Float,
+            mismatch: TextureCopyMismatch::BlockSize { src: 4, dst: 8 },
+        })
+    );
+    let depth = traits(TextureFormat::Depth32Float, 4, Aspects::DEPTH, 1);
+    assert_eq!(
+        validate_texture_copy_compatibility(&depth, &rgba8),
+        Err(TransferError::IncompatibleTextures {
+            src_format: TextureFormat::Depth32Float,
+            dst_format: TextureFormat::Rgba8Unorm,
+            mismatch: TextureCopyMismatch::Aspects,
+        })
+    );
+    let multisampled = traits(TextureFormat::Rgba8Unorm, 4, Aspects::COLOR, 4);
+    assert_eq!(
+        validate_texture_copy_compatibility(&multisampled, &rgba8),
+        Err(TransferError::IncompatibleTextures {
+            src_format: TextureFormat::Rgba8Unorm,
+            dst_format: TextureFormat::Rgba8Unorm,
+            mismatch: TextureCopyMismatch::SampleCount { src: 4, dst: 1 },
+        })
+    );
 }
@@ -5,7 +5,6 @@
 use crate::{
     binding_model::{self, CreateBindGroupError, PipelineLayoutError},
     command, conv,
-    device::life::WaitIdleError,
     hub::{GfxBackend, Global, GlobalIdentityHandlerFactory, Hub, Input, Token},
     id, pipeline, resource, span, swap_chain,
     track::{BufferState, TextureState, TrackerSet},
@@ -20,6 +19,7 @@ use gfx_memory::{Block, Heaps};
 use hal::{
     command::CommandBuffer as _,
     device::Device as _,
+    queue::CommandQueue as _,
     window::{PresentationSurface as _, Surface as _},
 };
 use parking_lot::{Mutex, MutexGuard};
@@ -37,19 +37,25 @@ mod queue;
 #[cfg(any(feature = "trace", feature = "replay"))]
 pub mod trace;
 
+pub use life::WaitIdleError;
+
 use smallvec::SmallVec;
 #[cfg(feature = "trace")]
 use trace::{Action, Trace};
 
 pub type Label = *const std::os::raw::c_char;
+/// Copies the label for the trace, keeping a missing label distinct
+/// from an empty one.
 #[cfg(feature = "trace")]
-fn own_label(label: &Label) -> String {
+fn own_label(label: &Label) -> Option<String> {
     if label.is_null() {
-        String::new()
+        None
     } else {
-        unsafe { ffi::CStr::from_ptr(*label) }
-            .to_string_lossy()
-            .to_string()
+        Some(
+            unsafe { ffi::CStr::from_ptr(*label) }
+                .to_string_lossy()
+                .to_string(),
+        )
     }
 }
 
@@ -57,6 +63,7 @@ pub const MAX_COLOR_TARGETS: usize = 4;
 pub const MAX_MIP_LEVELS: usize = 16;
 pub const MAX_VERTEX_BUFFERS: usize = 16;
 pub const MAX_ANISOTROPY: u8 = 16;
+pub const MAX_QUERY_SET_COUNT: u32 = 8192;
 pub const SHADER_STAGE_COUNT: usize = 3;
 
 pub fn all_buffer_stages() -> hal::pso::PipelineStage {
@@ -88,6 +95,24 @@ pub enum HostMap {
     Write,
 }
 
+impl From<wgt::MapMode> for HostMap {
+    fn from(mode: wgt::MapMode) -> Self {
+        match mode {
+            wgt::MapMode::Read => HostMap::Read,
+            wgt::MapMode::Write => HostMap::Write,
+        }
+    }
+}
+
+impl From<HostMap> for wgt::MapMode {
+    fn from(host: HostMap) -> Self {
+        match host {
+            HostMap::Read => wgt::MapMode::Read,
+            HostMap::Write => wgt::MapMode::Write,
+        }
+    }
+}
+
 #[derive(Clone, Debug, Hash, PartialEq)]
 #[cfg_attr(feature = "serial-pass", derive(serde::Deserialize, serde::Serialize))]
 pub(crate) struct AttachmentData<T> {
@@ -194,6 +219,8 @@ pub struct Device<B: hal::Backend> {
     life_tracker: Mutex<life::LifetimeTracker<B>>,
     temp_suspected: life::SuspectedResources,
     pub(crate) hal_limits: hal::Limits,
+    /// Nanoseconds per tick of the timestamp queries on `queue_group`.
+    timestamp_period: f32,
     pub(crate) private_features: PrivateFeatures,
     pub(crate) limits: wgt::Limits,
     pub(crate) features: wgt::Features,
@@ -216,6 +243,7 @@ impl<B: GfxBackend> Device<B> {
         trace_path: Option<&std::path::Path>,
     ) -> Self {
         let com_allocator = command::CommandAllocator::new(queue_group.family, &raw);
+        let timestamp_period = queue_group.queues[0].timestamp_period();
         let heaps = unsafe {
             Heaps::new(
                 &mem_props,
@@ -252,20 +280,16 @@ impl<B: GfxBackend> Device<B> {
             life_tracker: Mutex::new(life::LifetimeTracker::new()),
             temp_suspected: life::SuspectedResources::default(),
             #[cfg(feature = "trace")]
-            trace: trace_path.and_then(|path| match Trace::new(path) {
-                Ok(mut trace) => {
-                    trace.add(Action::Init {
-                        desc: desc.clone(),
-                        backend: B::VARIANT,
-                    });
-                    Some(Mutex::new(trace))
-                }
+            // `Action::Init` is added once the ID of the device is known
+            trace: trace_path.and_then(|path| match Trace::create(path, B::VARIANT) {
+                Ok(trace) => Some(Mutex::new(trace)),
                 Err(e) => {
                     log::error!("Unable to start a trace in '{:?}': {:?}", path, e);
                     None
                 }
             }),
             hal_limits,
+            timestamp_period,
             private_features,
             limits: desc.limits.clone(),
             features: desc.features.clone(),
@@ -664,6 +688,32 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
         device.limits.clone()
     }
 
+    /// Returns the number of nanoseconds it takes for the timestamp queries
+    /// of the device to increment by one. The period is the one of the queue
+    /// family of the device, since it may differ between queue families.
+    pub fn device_timestamp_period<B: GfxBackend>(
+        &self,
+        device_id: id::DeviceId,
+    ) -> Result<f32, command::TimestampPeriodError> {
+        span!(_guard, INFO, "Device::timestamp_period");
+
+        let hub = B::hub(self);
+        let mut token = Token::root();
+        let (device_guard, _) = hub.devices.read(&mut token);
+        let device = &device_guard[device_id];
+
+        if !device.hal_limits.timestamp_compute_and_graphics {
+            return Err(command::TimestampPeriodError::Unsupported);
+        }
+        if device.timestamp_period > 0.0 {
+            Ok(device.timestamp_period)
+        } else {
+            Err(command::TimestampPeriodError::InvalidPeriod(
+                device.timestamp_period,
+            ))
+        }
+    }
+
     pub fn device_create_buffer<B: GfxBackend>(
         &self,
         device_id: id::DeviceId,
@@ -821,11 +871,13 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
             Some(ref trace) => {
                 let mut trace = trace.lock();
                 let data_path = trace.make_binary("bin", data);
+                let compressed = trace.is_compressed();
                 trace.add(trace::Action::WriteBuffer {
                     id: buffer_id,
                     data: data_path,
                     range: offset..offset + data.len() as BufferAddress,
                     queued: false,
+                    compressed,
                 });
             }
             None => (),
@@ -1320,7 +1372,7 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
         match device.trace {
             Some(ref trace) => trace.lock().add(trace::Action::CreateBindGroupLayout {
                 id,
-                label: desc.label.map_or_else(String::new, str::to_string),
+                label: desc.label.map(str::to_string),
                 entries: desc.entries.to_owned(),
             }),
             None => (),
@@ -1868,7 +1920,7 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
         match device.trace {
             Some(ref trace) => trace.lock().add(trace::Action::CreateBindGroup {
                 id,
-                label: desc.label.map_or_else(String::new, str::to_string),
+                label: desc.label.map(str::to_string),
                 layout_id: desc.layout,
                 entries: desc
                     .entries
@@ -1883,7 +1935,7 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
                             Br::TextureView(id) => trace::BindingResource::TextureView(id),
                             Br::Sampler(id) => trace::BindingResource::Sampler(id),
                             Br::TextureViewArray(ref binding_array) => {
-                                trace::BindingResource::TextureViewArray(binding_array.to_vec())
+                                trace::BindingResource::TextureViewArray(Box::from(*binding_array))
                             }
                         };
                         (entry.binding, res)
@@ -1951,6 +2003,12 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
             naga::back::spv::WriterFlags::empty()
         };
 
+        #[cfg(feature = "trace")]
+        let wgsl_source = match source {
+            pipeline::ShaderModuleSource::Wgsl(code) => Some(code),
+            _ => None,
+        };
+
         let (spv, naga) = match source {
             pipeline::ShaderModuleSource::SpirV(spv) => {
                 let module = if device.private_features.shader_validation {
@@ -2009,10 +2067,18 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
         match device.trace {
             Some(ref trace) => {
                 let mut trace = trace.lock();
-                let data = trace.make_binary("spv", unsafe {
-                    std::slice::from_raw_parts(spv.as_ptr() as *const u8, spv.len() * 4)
+                let source = match wgsl_source {
+                    Some(code) => trace::ShaderModuleSource::Wgsl(trace.make_text("wgsl", code)),
+                    None => trace::ShaderModuleSource::SpirV(trace.make_binary("spv", unsafe {
+                        std::slice::from_raw_parts(spv.as_ptr() as *const u8, spv.len() * 4)
+                    })),
+                };
+                let compressed = trace.is_compressed();
+                trace.add(trace::Action::CreateShaderModule {
+                    id,
+                    source,
+                    compressed,
                 });
-                trace.add(trace::Action::CreateShaderModule { id, data });
             }
             None => {}
         };
@@ -2067,7 +2133,6 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
             #[cfg(feature = "trace")]
             device.trace.is_some(),
         );
-
         unsafe {
             let raw_command_buffer = command_buffer.raw.last_mut().unwrap();
             if !desc.label.is_null() {
@@ -2079,8 +2144,18 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
             raw_command_buffer.begin_primary(hal::command::CommandBufferFlags::ONE_TIME_SUBMIT);
         }
 
-        hub.command_buffers
-            .register_identity(id_in, command_buffer, &mut token)
+        let id = hub
+            .command_buffers
+            .register_identity(id_in, command_buffer, &mut token);
+        #[cfg(feature = "trace")]
+        match device.trace {
+            Some(ref trace) => trace.lock().add(trace::Action::CreateCommandEncoder {
+                id,
+                desc: desc.map_label(own_label),
+            }),
+            None => (),
+        };
+        id
     }
 
     pub fn command_encoder_destroy<B: GfxBackend>(&self, command_encoder_id: id::CommandEncoderId) {
@@ -2096,6 +2171,13 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
         };
 
         let device = &mut device_guard[comb.device_id.value];
+        #[cfg(feature = "trace")]
+        match device.trace {
+            Some(ref trace) => trace
+                .lock()
+                .add(trace::Action::DropCommandEncoder(command_encoder_id)),
+            None => (),
+        };
         device.untrack::<G>(&hub, &comb.trackers, &mut token);
         device.com_allocator.discard(comb);
     }
@@ -2140,7 +2222,7 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
         device_id: id::DeviceId,
         desc: &wgt::QuerySetDescriptor,
         id_in: Input<G, id::QuerySetId>,
-    ) -> id::QuerySetId {
+    ) -> Result<id::QuerySetId, resource::CreateQuerySetError> {
         let hub = B::hub(self);
         let mut token = Token::root();
 
@@ -2148,6 +2230,24 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
 
         let (device_guard, mut token) = hub.devices.read(&mut token);
         let device = &device_guard[device_id];
+        if desc.count == 0 {
+            return Err(resource::CreateQuerySetError::ZeroCount);
+        }
+        if desc.count > MAX_QUERY_SET_COUNT {
+            return Err(resource::CreateQuerySetError::TooManyQueries {
+                count: desc.count,
+                max: MAX_QUERY_SET_COUNT,
+            });
+        }
+        match desc.type_ {
+            wgt::QueryType::PipelineStatistics(&[]) => {
+                return Err(resource::CreateQuerySetError::NoPipelineStatistics);
+            }
+            wgt::QueryType::Timestamp if !device.hal_limits.timestamp_compute_and_graphics => {
+                return Err(resource::CreateQuerySetError::TimestampsUnsupported);
+            }
+            _ => {}
+        }
         let query_set = {
             use wgt::QueryType::*;
             use wgt::PipelineStatisticName::*;
@@ -2173,7 +2273,16 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
             };
 
             resource::QuerySet {
-                raw: unsafe { device.raw.create_query_pool(hal_query_type, desc.count).unwrap() },
+                raw: unsafe { device.raw.create_query_pool(hal_query_type, desc.count)? },
+                type_: match desc.type_ {
+                    Occlusion => resource::QuerySetType::Occlusion,
+                    PipelineStatistics(names) => resource::QuerySetType::PipelineStatistics(
+                        crate::command::resolved_pipeline_statistics(names),
+                    ),
+                    Timestamp => resource::QuerySetType::Timestamp,
+                },
+                count: desc.count,
+                resolve_stride: crate::command::query_resolve_stride(&desc.type_),
                 device_id: Stored {
                     value: device_id,
                     ref_count: device.life_guard.add_ref(),
@@ -2207,7 +2316,7 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
             .init(id, ref_count, PhantomData)
             .unwrap();
 
-        id
+        Ok(id)
     }
 
     pub fn query_set_destroy<B: GfxBackend>(&self, query_set_id: id::QuerySetId) {
@@ -2828,7 +2937,27 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
         }
 
         let sc_id = surface_id.to_swap_chain_id(B::VARIANT);
-        if let Some(sc) = swap_chain_guard.remove(sc_id) {
+        let old_swap_chain = swap_chain_guard.remove(sc_id);
+        #[cfg(feature = "trace")]
+        match device.trace {
+            Some(ref trace) => {
+                let mut trace = trace.lock();
+                // Surfaces are created before the device, so they are
+                // recorded once the device starts presenting to them.
+                if old_swap_chain.is_none() {
+                    trace.add(Action::CreateSurface {
+                        id: surface_id,
+                        backend: B::VARIANT,
+                    });
+                }
+                trace.add(Action::CreateSwapChain {
+                    id: sc_id,
+                    desc: desc.clone(),
+                });
+            }
+            None => (),
+        };
+        if let Some(sc) = old_swap_chain {
             assert!(
                 sc.acquired_view_id.is_none(),
                 "SwapChainOutput must be dropped before a new SwapChain is made."
@@ -2837,14 +2966,6 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
                 device.raw.destroy_semaphore(sc.semaphore);
             }
         }
-        #[cfg(feature = "trace")]
-        match device.trace {
-            Some(ref trace) => trace.lock().add(Action::CreateSwapChain {
-                id: sc_id,
-                desc: desc.clone(),
-            }),
-            None => (),
-        };
 
         let swap_chain = swap_chain::SwapChain {
             life_guard: LifeGuard::new(),
@@ -2891,7 +3012,13 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
         let mut token = Token::root();
         let callbacks = {
             let (device_guard, mut token) = hub.devices.read(&mut token);
-            device_guard[device_id].maintain(&hub, force_wait, &mut token)?
+            let device = &device_guard[device_id];
+            #[cfg(feature = "trace")]
+            match device.trace {
+                Some(ref trace) => trace.lock().add(trace::Action::Poll { force_wait }),
+                None => (),
+            }
+            device.maintain(&hub, force_wait, &mut token)?
         };
         fire_map_callbacks(callbacks);
         Ok(())
@@ -2908,6 +3035,11 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
         let mut token = Token::root();
         let (device_guard, mut token) = hub.devices.read(&mut token);
         for (_, device) in device_guard.iter(B::VARIANT) {
+            #[cfg(feature = "trace")]
+            match device.trace {
+                Some(ref trace) => trace.lock().add(trace::Action::Poll { force_wait }),
+                None => (),
+            }
             let cbs = device.maintain(&hub, force_wait, &mut token)?;
             callbacks.extend(cbs);
         }
@@ -2938,6 +3070,117 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
         Ok(())
     }
 
+    /// Records the resources of the device that are still held by the user
+    /// into its trace, if it's tracing.
+    ///
+    /// This helps with diagnosing the traces that end abruptly, without
+    /// the destruction of all their resources.
+    #[cfg(feature = "trace")]
+    pub fn device_trace_resource_snapshot<B: GfxBackend>(&self, device_id: id::DeviceId) {
+        span!(_guard, INFO, "Device::trace_resource_snapshot");
+
+        let hub = B::hub(self);
+        let mut token = Token::root();
+        let (device_guard, mut token) = hub.devices.read(&mut token);
+        let trace = match device_guard[device_id].trace {
+            Some(ref trace) => trace,
+            None => return,
+        };
+
+        // resources that were dropped by the user are kept until the device
+        // is done with them, but they don't have a ref count anymore
+        macro_rules! live_ids {
+            ($registry:ident) => {{
+                let (guard, _) = hub.$registry.read(&mut token);
+                guard
+                    .iter(B::VARIANT)
+                    .filter(|&(_, resource)| {
+                        resource.device_id.value == device_id
+                            && resource.life_guard.ref_count.is_some()
+                    })
+                    .map(|(id, _)| id)
+                    .collect()
+            }};
+        }
+        let live_shader_modules = {
+            let (guard, _) = hub.shader_modules.read(&mut token);
+            guard
+                .iter(B::VARIANT)
+                .filter(|&(_, module)| module.device_id.value == device_id)
+                .map(|(id, _)| id)
+                .collect()
+        };
+        let action = trace::Action::ResourceSnapshot {
+            live_buffers: live_ids!(buffers),
+            live_textures: live_ids!(textures),
+            live_samplers: live_ids!(samplers),
+            live_bind_groups: live_ids!(bind_groups),
+            live_shader_modules,
+            live_compute_pipelines: live_ids!(compute_pipelines),
+            live_render_pipelines: live_ids!(render_pipelines),
+            live_render_bundles: live_ids!(render_bundles),
+            live_query_sets: live_ids!(query_sets),
+        };
+        trace.lock().add(action);
+    }
+
+    /// Makes sure the actions recorded so far into the trace of the device,
+    /// if it's tracing, reach the file, see `Trace::flush`.
+    #[cfg(feature = "trace")]
+    pub fn device_flush_trace<B: GfxBackend>(
+        &self,
+        device_id: id::DeviceId,
+        sync: bool,
+    ) -> Result<(), std::io::Error> {
+        span!(_guard, INFO, "Device::flush_trace");
+
+        let hub = B::hub(self);
+        let mut token = Token::root();
+        let (device_guard, _) = hub.devices.read(&mut token);
+        match device_guard[device_id].trace {
+            Some(ref trace) => trace.lock().flush(sync),
+            None => Ok(()),
+        }
+    }
+
+    /// Marks the end of the frame `frame_index` in the trace of the device,
+    /// if it's tracing, for the applications that don't present to a swap chain.
+    #[cfg(feature = "trace")]
+    pub fn device_add_trace_frame_boundary<B: GfxBackend>(
+        &self,
+        device_id: id::DeviceId,
+        frame_index: u64,
+    ) {
+        span!(_guard, INFO, "Device::add_trace_frame_boundary");
+
+        let hub = B::hub(self);
+        let mut token = Token::root();
+        let (device_guard, _) = hub.devices.read(&mut token);
+        match device_guard[device_id].trace {
+            Some(ref trace) => trace.lock().add_frame_boundary(frame_index),
+            None => (),
+        }
+    }
+
+    /// Sets how the RON entries recorded from now on into the trace of the
+    /// device, if it's tracing, are formatted, see `Trace::set_ron_config`.
+    #[cfg(feature = "trace")]
+    pub fn device_set_trace_ron_config<B: GfxBackend>(
+        &self,
+        device_id: id::DeviceId,
+        config: Option<ron::ser::PrettyConfig>,
+    ) {
+        span!(_guard, INFO, "Device::set_trace_ron_config");
+
+        let hub = B::hub(self);
+        let mut token = Token::root();
+        let (device_guard, _) = hub.devices.read(&mut token);
+        match device_guard[device_id].trace {
+            Some(ref trace) => trace.lock().set_ron_config(config),
+            None => (),
+        }
+    }
+
     pub fn device_destroy<B: GfxBackend>(&self, device_id: id::DeviceId) {
         span!(_guard, INFO, "Device::drop");
 
@@ -2969,7 +3212,8 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
         let hub = B::hub(self);
         let mut token = Token::root();
         let (device_guard, mut token) = hub.devices.read(&mut token);
-        let (pub_usage, internal_use) = match op.host {
+        let host = op.host;
+        let (pub_usage, internal_use) = match host {
             HostMap::Read => (wgt::BufferUsage::MAP_READ, resource::BufferUse::MAP_READ),
             HostMap::Write => (wgt::BufferUsage::MAP_WRITE, resource::BufferUse::MAP_WRITE),
         };
@@ -3012,6 +3256,16 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
         };
 
         let device = &device_guard[device_id];
+        #[cfg(feature = "trace")]
+        match device.trace {
+            Some(ref trace) => trace.lock().add(trace::Action::MapBuffer {
+                id: buffer_id,
+                mode: host.into(),
+                range: range.clone(),
+            }),
+            None => (),
+        };
+
         device
             .trackers
             .lock()
@@ -3071,11 +3325,13 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
                         let data = trace.make_binary("bin", unsafe {
                             std::slice::from_raw_parts(ptr.as_ptr(), buffer.size as usize)
                         });
+                        let compressed = trace.is_compressed();
                         trace.add(trace::Action::WriteBuffer {
                             id: buffer_id,
                             data,
                             range: 0..buffer.size,
                             queued: true,
+                            compressed,
                         });
                     }
                     None => (),
@@ -3133,11 +3389,13 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
                             let data = trace.make_binary("bin", unsafe {
                                 std::slice::from_raw_parts(ptr.as_ptr(), size as usize)
                             });
+                            let compressed = trace.is_compressed();
                             trace.add(trace::Action::WriteBuffer {
                                 id: buffer_id,
                                 data,
                                 range: sub_range.offset..sub_range.offset + size,
                                 queued: false,
+                                compressed,
                             });
                         }
                         None => (),
@@ -3147,5 +3405,11 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
                 unmap_buffer(&device.raw, buffer);
             }
         }
+
+        #[cfg(feature = "trace")]
+        match device.trace {
+            Some(ref trace) => trace.lock().add(trace::Action::UnmapBuffer(buffer_id)),
+            None => (),
+        };
     }
 }
@@ -8,9 +8,9 @@ use crate::{
     command::{CommandAllocator, CommandBuffer, TextureCopyView, BITS_PER_BYTE},
     conv,
     device::WaitIdleError,
-    hub::{GfxBackend, Global, GlobalIdentityHandlerFactory, Token},
+    hub::{GfxBackend, Global, GlobalIdentityHandlerFactory, Storage, Token},
     id,
-    resource::{BufferMapState, BufferUse, TextureUse},
+    resource::{Buffer, BufferMapState, BufferUse, TextureUse},
     span,
 };
 
@@ -79,6 +79,94 @@ impl<B: hal::Backend> super::Device<B> {
         self.pending_writes.command_buffer.as_mut().unwrap()
     }
 
+    /// Copies `data` into the buffer through a staging buffer, recorded
+    /// in the pending writes of the device.
+    fn write_buffer_staged(
+        &mut self,
+        buffer_guard: &Storage<Buffer<B>, id::BufferId>,
+        buffer_id: id::BufferId,
+        buffer_offset: wgt::BufferAddress,
+        data: &[u8],
+    ) {
+        let data_size = data.len() as wgt::BufferAddress;
+        if data_size == 0 {
+            log::trace!("Ignoring write_buffer of size 0");
+            return;
+        }
+
+        let mut stage = self.prepare_stage(data_size);
+        {
+            let mut mapped = stage
+                .memory
+                .map(&self.raw, hal::memory::Segment::ALL)
+                .unwrap();
+            unsafe { mapped.write(&self.raw, hal::memory::Segment::ALL) }
+                .unwrap()
+                .slice[..data.len()]
+                .copy_from_slice(data);
+        }
+
+        let mut trackers = self.trackers.lock();
+        let (dst, transition) =
+            trackers
+                .buffers
+                .use_replace(buffer_guard, buffer_id, (), BufferUse::COPY_DST);
+        assert!(
+            dst.usage.contains(wgt::BufferUsage::COPY_DST),
+            "Write buffer usage {:?} must contain flag COPY_DST",
+            dst.usage
+        );
+        dst.life_guard.use_at(self.active_submission_index + 1);
+
+        assert_eq!(
+            data_size % wgt::COPY_BUFFER_ALIGNMENT,
+            0,
+            "Buffer write size {} must be a multiple of {}",
+            data_size,
+            wgt::COPY_BUFFER_ALIGNMENT,
+        );
+        assert_eq!(
+            buffer_offset % wgt::COPY_BUFFER_ALIGNMENT,
+            0,
+            "Buffer offset {} must be a multiple of {}",
+            buffer_offset,
+            wgt::COPY_BUFFER_ALIGNMENT,
+        );
+        let destination_start_offset = buffer_offset;
+        let destination_end_offset = buffer_offset + data_size;
+        assert!(
+            destination_end_offset <= dst.size,
+            "Write buffer with indices {}..{} overruns destination buffer of size {}",
+            destination_start_offset,
+            destination_end_offset,
+            dst.size
+        );
+
+        let region = hal::command::BufferCopy {
+            src: 0,
+            dst: buffer_offset,
+            size: data.len() as _,
+        };
+        unsafe {
+            stage.comb.pipeline_barrier(
+                super::all_buffer_stages()..hal::pso::PipelineStage::TRANSFER,
+                hal::memory::Dependencies::empty(),
+                iter::once(hal::memory::Barrier::Buffer {
+                    states: hal::buffer::Access::HOST_WRITE..hal::buffer::Access::TRANSFER_READ,
+                    target: &stage.buffer,
+                    range: hal::buffer::SubRange::WHOLE,
+                    families: None,
+                })
+                .chain(transition.map(|pending| pending.into_hal(dst))),
+            );
+            stage
+                .comb
+                .copy_buffer(&stage.buffer, &dst.raw, iter::once(region));
+        }
+
+        self.pending_writes.consume(stage);
+    }
+
     fn prepare_stage(&mut self, size: wgt::BufferAddress) -> StagingData<B> {
         let mut buffer = unsafe {
             self.raw
@@ -146,93 +234,140 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
             Some(ref trace) => {
                 let mut trace = trace.lock();
                 let data_path = trace.make_binary("bin", data);
+                let compressed = trace.is_compressed();
                 trace.add(Action::WriteBuffer {
                     id: buffer_id,
                     data: data_path,
                     range: buffer_offset..buffer_offset + data.len() as wgt::BufferAddress,
                     queued: true,
+                    compressed,
                 });
             }
             None => {}
         }
 
-        let data_size = data.len() as wgt::BufferAddress;
-        if data_size == 0 {
-            log::trace!("Ignoring write_buffer of size 0");
-            return;
+        device.write_buffer_staged(&*buffer_guard, buffer_id, buffer_offset, data);
+    }
+
+    /// Writes into sub-ranges of a buffer, as if `queue_write_buffer` was
+    /// called for each of the `writes`, but recorded as a single action.
+    pub fn queue_write_buffer_many<B: GfxBackend>(
+        &self,
+        queue_id: id::QueueId,
+        buffer_id: id::BufferId,
+        writes: &[(wgt::BufferAddress, &[u8])],
+    ) {
+        span!(_guard, INFO, "Queue::write_buffer_many");
+
+        let hub = B::hub(self);
+        let mut token = Token::root();
+        let (mut device_guard, mut token) = hub.devices.write(&mut token);
+        let device = &mut device_guard[queue_id];
+        let (buffer_guard, _) = hub.buffers.read(&mut token);
+
+        #[cfg(feature = "trace")]
+        match device.trace {
+            Some(ref trace) => trace.lock().add_buffer_writes(buffer_id, writes),
+            None => {}
         }
 
-        let mut stage = device.prepare_stage(data_size);
-        {
-            let mut mapped = stage
-                .memory
-                .map(&device.raw, hal::memory::Segment::ALL)
-                .unwrap();
-            unsafe { mapped.write(&device.raw, hal::memory::Segment::ALL) }
-                .unwrap()
-                .slice[..data.len()]
-                .copy_from_slice(data);
+        for &(buffer_offset, data) in writes {
+            device.write_buffer_staged(&*buffer_guard, buffer_id, buffer_offset, data);
         }
+    }
 
-        let mut trackers = device.trackers.lock();
-        let (dst, transition) =
-            trackers
-                .buffers
-                .use_replace(&*buffer_guard, buffer_id, (), BufferUse::COPY_DST);
-        assert!(
-            dst.usage.contains(wgt::BufferUsage::COPY_DST),
-            "Write buffer usage {:?} must contain flag COPY_DST",
-            dst.usage
-        );
-        dst.life_guard.use_at(device.active_submission_index + 1);
+    pub fn queue_clear_buffer<B: GfxBackend>(
+        &self,
+        queue_id: id::QueueId,
+        buffer_id: id::BufferId,
+        buffer_offset: wgt::BufferAddress,
+        size: Option<wgt::BufferAddress>,
+    ) {
+        span!(_guard, INFO, "Queue::clear_buffer");
 
+        let hub = B::hub(self);
+        let mut token = Token::root();
+        let (mut device_guard, mut token) = hub.devices.write(&mut token);
+        let device = &mut device_guard[queue_id];
+        let (buffer_guard, _) = hub.buffers.read(&mut token);
+
+        #[cfg(feature = "trace")]
+        match device.trace {
+            Some(ref trace) => trace.lock().add(Action::ClearBuffer {
+                id: buffer_id,
+                offset: buffer_offset,
+                size,
+            }),
+            None => {}
+        }
+
+        let dst_size = buffer_guard[buffer_id].size;
+        let end_offset = size.map_or(dst_size, |size| {
+            buffer_offset.checked_add(size).unwrap_or_else(|| {
+                panic!(
+                    "Clear buffer size {} at offset {} overflows",
+                    size, buffer_offset
+                )
+            })
+        });
         assert_eq!(
-            data_size % wgt::COPY_BUFFER_ALIGNMENT,
+            buffer_offset % wgt::COPY_BUFFER_ALIGNMENT,
             0,
-            "Buffer write size {} must be a multiple of {}",
-            data_size,
+            "Buffer offset {} must be a multiple of {}",
+            buffer_offset,
             wgt::COPY_BUFFER_ALIGNMENT,
         );
         assert_eq!(
-            buffer_offset % wgt::COPY_BUFFER_ALIGNMENT,
+            end_offset % wgt::COPY_BUFFER_ALIGNMENT,
             0,
-            "Buffer offset {} must be a multiple of {}",
-            buffer_offset,
+            "Buffer clear size {} must be a multiple of {}",
+            end_offset - buffer_offset,
             wgt::COPY_BUFFER_ALIGNMENT,
         );
-        let destination_start_offset = buffer_offset;
-        let destination_end_offset = buffer_offset + data_size;
         assert!(
-            destination_end_offset <= dst.size,
-            "Write buffer with indices {}..{} overruns destination buffer of size {}",
-            destination_start_offset,
-            destination_end_offset,
-            dst.size
+            buffer_offset <= end_offset && end_offset <= dst_size,
+            "Clear buffer with indices {}..{} overruns destination buffer of size {}",
+            buffer_offset,
+            end_offset,
+            dst_size
         );
+        if buffer_offset == end_offset {
+            log::trace!("Ignoring clear_buffer of size 0");
+            return;
+        }
 
-        let region = hal::command::BufferCopy {
-            src: 0,
-            dst: buffer_offset,
-            size: data.len() as _,
-        };
+        let mut trackers = device.trackers.lock();
+        let (dst, transition) =
+            trackers
+                .buffers
+                .use_replace(&*buffer_guard, buffer_id, (), BufferUse::COPY_DST);
+        assert!(
+            dst.usage.contains(wgt::BufferUsage::COPY_DST),
+            "Clear buffer usage {:?} must contain flag COPY_DST",
+            dst.usage
+        );
+        dst.life_guard.use_at(device.active_submission_index + 1);
+        let barriers = transition
+            .map(|pending| pending.into_hal(dst))
+            .collect::<SmallVec<[_; 1]>>();
+        drop(trackers);
+
+        let comb = device.borrow_pending_writes();
         unsafe {
-            stage.comb.pipeline_barrier(
+            comb.pipeline_barrier(
                 super::all_buffer_stages()..hal::pso::PipelineStage::TRANSFER,
                 hal::memory::Dependencies::empty(),
-                iter::once(hal::memory::Barrier::Buffer {
-                    states: hal::buffer::Access::HOST_WRITE..hal::buffer::Access::TRANSFER_READ,
-                    target: &stage.buffer,
-                    range: hal::buffer::SubRange::WHOLE,
-                    families: None,
-                })
-                .chain(transition.map(|pending| pending.into_hal(dst))),
+                barriers,
+            );
+            comb.fill_buffer(
+                &dst.raw,
+                hal::buffer::SubRange {
+                    offset: buffer_offset,
+                    size: Some(end_offset - buffer_offset),
+                },
+                0,
             );
-            stage
-                .comb
-                .copy_buffer(&stage.buffer, &dst.raw, iter::once(region));
         }
-
-        device.pending_writes.consume(stage);
     }
 
     pub fn queue_write_texture<B: GfxBackend>(
@@ -258,17 +393,19 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
             Some(ref trace) => {
                 let mut trace = trace.lock();
                 let data_path = trace.make_binary("bin", data);
+                let compressed = trace.is_compressed();
                 trace.add(Action::WriteTexture {
                     to: destination.clone(),
                     data: data_path,
                     layout: data_layout.clone(),
                     size: *size,
+                    compressed,
                 });
             }
             None => {}
         }
 
-        if size.width == 0 || size.height == 0 || size.width == 0 {
+        if size.width == 0 || size.height == 0 || size.depth == 0 {
             log::trace!("Ignoring write_texture of size 0");
             return;
         }
@@ -333,7 +470,8 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
             "Write texture usage {:?} must contain flag COPY_DST",
             dst.usage
         );
-        crate::command::validate_texture_copy_range(destination, dst.kind, size).unwrap();
+        crate::command::validate_texture_copy_view_bounds(destination, size, dst).unwrap();
+        crate::command::validate_texture_copy_range(dst.kind, size).unwrap();
 
         dst.life_guard.use_at(device.active_submission_index + 1);
 
@@ -421,9 +559,13 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
                         let comb = &mut command_buffer_guard[cmb_id];
                         #[cfg(feature = "trace")]
                         match device.trace {
-                            Some(ref trace) => trace
-                                .lock()
-                                .add(Action::Submit(submit_index, comb.commands.take().unwrap())),
+                            Some(ref trace) => trace.lock().add_submit(
+                                submit_index,
+                                // devices only have their default queue
+                                None,
+                                cmb_id,
+                                comb.commands.take().unwrap(),
+                            ),
                             None => (),
                         };
 
@@ -454,7 +596,7 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
                             } else {
                                 match buffer.map_state {
                                     BufferMapState::Idle => (),
-                                    _ => panic!("Buffer {:?} is still mapped", id),
+                                    _ => panic!("{} is still mapped", id),
                                 }
                             }
                         }
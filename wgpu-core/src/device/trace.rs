@@ -6,16 +6,82 @@ use crate::id;
 #[cfg(feature = "trace")]
 use std::io::Write as _;
 use std::ops::Range;
+use thiserror::Error;
 
 //TODO: consider a readable Id that doesn't include the backend
 
 type FileName = String;
 
 pub const FILE_NAME: &str = "trace.ron";
+pub const BINARY_FILE_NAME: &str = "trace.bin";
+pub const METADATA_FILE_NAME: &str = "trace_metadata.json";
 
+/// Serialization format of the trace file.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TraceFormat {
+    /// Human-readable list of entries in RON, written to `FILE_NAME`.
+    Ron,
+    /// Stream of bincode entries, each prefixed by its size
+    /// as a little-endian `u64`, written to `BINARY_FILE_NAME`.
+    Bincode,
+}
+
+impl Default for TraceFormat {
+    fn default() -> Self {
+        TraceFormat::Ron
+    }
+}
+
+impl TraceFormat {
+    pub fn file_name(self) -> &'static str {
+        match self {
+            TraceFormat::Ron => FILE_NAME,
+            TraceFormat::Bincode => BINARY_FILE_NAME,
+        }
+    }
+}
+
+/// Version of the trace format, to be bumped on every incompatible change
+/// of `Action`, `Command`, or any of the types they contain.
+pub const FORMAT_VERSION: u32 = 16;
+
+/// First entry of the trace, describing how it was recorded.
 #[derive(Debug)]
 #[cfg_attr(feature = "trace", derive(serde::Serialize))]
 #[cfg_attr(feature = "replay", derive(serde::Deserialize))]
+pub struct TraceHeader {
+    pub version: u32,
+    /// Git commit of `wgpu-core` that recorded the trace, or "unknown"
+    /// if it wasn't built from a git checkout.
+    pub wgpu_core_git_hash: String,
+    pub backend: wgt::Backend,
+    /// Whether the entries following the header are `TimedAction`s,
+    /// instead of plain `Action`s.
+    pub timed: bool,
+}
+
+/// Environment the trace was captured in, written in JSON next to the trace
+/// file by `Trace::write_metadata`, for the tools that don't read RON.
+///
+/// A trace with several devices describes the last one created.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "trace", derive(serde::Serialize))]
+#[cfg_attr(feature = "replay", derive(serde::Deserialize))]
+pub struct TraceMetadata {
+    pub wgpu_core_version: String,
+    pub adapter_info: crate::instance::AdapterInfo,
+    pub device_features: wgt::Features,
+    /// Operating system, as in `std::env::consts::OS`.
+    pub os: String,
+    /// CPU architecture, as in `std::env::consts::ARCH`.
+    pub arch: String,
+    /// Seconds since the Unix epoch when the device was created.
+    pub capture_time: u64,
+}
+
+#[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "trace", derive(serde::Serialize))]
+#[cfg_attr(feature = "replay", derive(serde::Deserialize))]
 pub enum BindingResource {
     Buffer {
         id: id::BufferId,
@@ -24,10 +90,10 @@ pub enum BindingResource {
     },
     Sampler(id::SamplerId),
     TextureView(id::TextureViewId),
-    TextureViewArray(Vec<id::TextureViewId>),
+    TextureViewArray(Box<[id::TextureViewId]>),
 }
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq)]
 #[cfg_attr(feature = "trace", derive(serde::Serialize))]
 #[cfg_attr(feature = "replay", derive(serde::Deserialize))]
 pub struct ProgrammableStageDescriptor {
@@ -38,6 +104,12 @@ pub struct ProgrammableStageDescriptor {
 #[cfg(feature = "trace")]
 impl ProgrammableStageDescriptor {
     pub fn new(desc: &crate::pipeline::ProgrammableStageDescriptor) -> Self {
+        if desc.entry_point.is_empty() {
+            log::warn!(
+                "Recording a stage of shader module {:?} without an entry point",
+                desc.module
+            );
+        }
         ProgrammableStageDescriptor {
             module: desc.module,
             entry_point: desc.entry_point.to_string(),
@@ -45,17 +117,40 @@ impl ProgrammableStageDescriptor {
     }
 }
 
+/// Stage of a recorded pipeline that has an empty entry point, which
+/// the backends would fail to compile with a less helpful error.
+#[derive(Clone, Debug, Error)]
+#[error("missing entry point of the stage using {module}")]
+pub struct MissingEntryPoint {
+    pub module: id::ShaderModuleId,
+}
+
 #[cfg(feature = "replay")]
 impl ProgrammableStageDescriptor {
-    pub fn to_core(&self) -> crate::pipeline::ProgrammableStageDescriptor {
-        crate::pipeline::ProgrammableStageDescriptor {
+    pub fn to_core(
+        &self,
+    ) -> Result<crate::pipeline::ProgrammableStageDescriptor, MissingEntryPoint> {
+        if self.entry_point.is_empty() {
+            return Err(MissingEntryPoint {
+                module: self.module,
+            });
+        }
+        Ok(crate::pipeline::ProgrammableStageDescriptor {
             module: self.module,
             entry_point: &self.entry_point,
-        }
+        })
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "trace", derive(serde::Serialize))]
+#[cfg_attr(feature = "replay", derive(serde::Deserialize))]
+pub enum ShaderModuleSource {
+    SpirV(FileName),
+    Wgsl(FileName),
+}
+
+#[derive(Debug, PartialEq)]
 #[cfg_attr(feature = "trace", derive(serde::Serialize))]
 #[cfg_attr(feature = "replay", derive(serde::Deserialize))]
 pub struct ComputePipelineDescriptor {
@@ -63,7 +158,7 @@ pub struct ComputePipelineDescriptor {
     pub compute_stage: ProgrammableStageDescriptor,
 }
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq)]
 #[cfg_attr(feature = "trace", derive(serde::Serialize))]
 #[cfg_attr(feature = "replay", derive(serde::Deserialize))]
 pub struct VertexBufferDescriptor {
@@ -72,7 +167,7 @@ pub struct VertexBufferDescriptor {
     pub attributes: Vec<wgt::VertexAttributeDescriptor>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq)]
 #[cfg_attr(feature = "trace", derive(serde::Serialize))]
 #[cfg_attr(feature = "replay", derive(serde::Deserialize))]
 pub struct VertexStateDescriptor {
@@ -80,7 +175,7 @@ pub struct VertexStateDescriptor {
     pub vertex_buffers: Vec<VertexBufferDescriptor>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq)]
 #[cfg_attr(feature = "trace", derive(serde::Serialize))]
 #[cfg_attr(feature = "replay", derive(serde::Deserialize))]
 pub struct RenderPipelineDescriptor {
@@ -97,11 +192,11 @@ pub struct RenderPipelineDescriptor {
     pub alpha_to_coverage_enabled: bool,
 }
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq)]
 #[cfg_attr(feature = "trace", derive(serde::Serialize))]
 #[cfg_attr(feature = "replay", derive(serde::Deserialize))]
 pub struct RenderBundleDescriptor {
-    pub label: String,
+    pub label: Option<String>,
     pub color_formats: Vec<wgt::TextureFormat>,
     pub depth_stencil_format: Option<wgt::TextureFormat>,
     pub sample_count: u32,
@@ -119,7 +214,7 @@ impl RenderBundleDescriptor {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq)]
 #[cfg_attr(feature = "trace", derive(serde::Serialize))]
 #[cfg_attr(feature = "replay", derive(serde::Deserialize))]
 pub struct QuerySetDescriptor {
@@ -127,7 +222,7 @@ pub struct QuerySetDescriptor {
     pub count: u32,
 }
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq)]
 #[cfg_attr(feature = "trace", derive(serde::Serialize))]
 #[cfg_attr(feature = "replay", derive(serde::Deserialize))]
 pub enum QueryType {
@@ -150,35 +245,50 @@ impl QueryType {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq)]
 #[cfg_attr(feature = "trace", derive(serde::Serialize))]
 #[cfg_attr(feature = "replay", derive(serde::Deserialize))]
 pub enum Action {
+    /// Creation of the device `device`, which the actions that follow
+    /// belong to, up to the next `Init` of another device.
     Init {
+        device: id::DeviceId,
         desc: wgt::DeviceDescriptor,
         backend: wgt::Backend,
+        /// Adapter the device was created on.
+        adapter_info: crate::instance::AdapterInfo,
+        /// Features and limits of the adapter, which are the defaults
+        /// if the trace was recorded without them.
+        #[serde(default)]
+        adapter_features: wgt::Features,
+        #[serde(default)]
+        adapter_limits: wgt::Limits,
     },
     CreateBuffer {
         id: id::BufferId,
-        desc: wgt::BufferDescriptor<String>,
+        desc: wgt::BufferDescriptor<Option<String>>,
     },
     DestroyBuffer(id::BufferId),
     CreateTexture {
         id: id::TextureId,
-        desc: wgt::TextureDescriptor<String>,
+        desc: wgt::TextureDescriptor<Option<String>>,
     },
     DestroyTexture(id::TextureId),
     CreateTextureView {
         id: id::TextureViewId,
         parent_id: id::TextureId,
-        desc: Option<wgt::TextureViewDescriptor<String>>,
+        desc: Option<wgt::TextureViewDescriptor<Option<String>>>,
     },
     DestroyTextureView(id::TextureViewId),
     CreateSampler {
         id: id::SamplerId,
-        desc: wgt::SamplerDescriptor<String>,
+        desc: wgt::SamplerDescriptor<Option<String>>,
     },
     DestroySampler(id::SamplerId),
+    CreateSurface {
+        id: id::SurfaceId,
+        backend: wgt::Backend,
+    },
     CreateSwapChain {
         id: id::SwapChainId,
         desc: wgt::SwapChainDescriptor,
@@ -190,7 +300,7 @@ pub enum Action {
     PresentSwapChain(id::SwapChainId),
     CreateBindGroupLayout {
         id: id::BindGroupLayoutId,
-        label: String,
+        label: Option<String>,
         entries: Vec<wgt::BindGroupLayoutEntry>,
     },
     DestroyBindGroupLayout(id::BindGroupLayoutId),
@@ -202,14 +312,17 @@ pub enum Action {
     DestroyPipelineLayout(id::PipelineLayoutId),
     CreateBindGroup {
         id: id::BindGroupId,
-        label: String,
+        label: Option<String>,
         layout_id: id::BindGroupLayoutId,
         entries: std::collections::BTreeMap<u32, BindingResource>,
     },
     DestroyBindGroup(id::BindGroupId),
     CreateShaderModule {
         id: id::ShaderModuleId,
-        data: FileName,
+        source: ShaderModuleSource,
+        /// Whether the source file is compressed with gzip.
+        #[serde(default)]
+        compressed: bool,
     },
     DestroyShaderModule(id::ShaderModuleId),
     CreateComputePipeline {
@@ -233,24 +346,138 @@ pub enum Action {
         desc: QuerySetDescriptor,
     },
     DestroyQuerySet(id::QuerySetId),
+    CreateCommandEncoder {
+        id: id::CommandEncoderId,
+        desc: wgt::CommandEncoderDescriptor<Option<String>>,
+    },
+    /// Destruction of an encoder, or of its command buffer, that wasn't submitted.
+    DropCommandEncoder(id::CommandEncoderId),
+    /// Bytes written into the `range` of a buffer, whichever way they were
+    /// uploaded, so that the player only has to reproduce the contents.
     WriteBuffer {
         id: id::BufferId,
         data: FileName,
         range: Range<wgt::BufferAddress>,
+        /// Whether the write goes through the queue, ordered with the
+        /// submissions, like staging uploads do. Otherwise the bytes were
+        /// written into a mapping of the buffer, once it was idle.
         queued: bool,
+        /// Whether the data file is compressed with gzip.
+        #[serde(default)]
+        compressed: bool,
+    },
+    /// Queued writes into sub-ranges of a buffer, recorded by
+    /// `Global::queue_write_buffer_many`, with the contiguous ones coalesced.
+    WriteBufferMany {
+        id: id::BufferId,
+        writes: Vec<(Range<wgt::BufferAddress>, FileName)>,
+        /// Whether the data files are compressed with gzip.
+        #[serde(default)]
+        compressed: bool,
+    },
+    ClearBuffer {
+        id: id::BufferId,
+        offset: wgt::BufferAddress,
+        size: Option<wgt::BufferAddress>,
     },
     WriteTexture {
         to: crate::command::TextureCopyView,
         data: FileName,
         layout: wgt::TextureDataLayout,
         size: wgt::Extent3d,
+        /// Whether the data file is compressed with gzip.
+        #[serde(default)]
+        compressed: bool,
+    },
+    MapBuffer {
+        id: id::BufferId,
+        mode: wgt::MapMode,
+        range: Range<wgt::BufferAddress>,
+    },
+    UnmapBuffer(id::BufferId),
+    WriteTimestamp {
+        query_set: id::QuerySetId,
+        query_index: u32,
+        /// Bits of the `PipelineStage`.
+        pipeline_stage: u32,
+    },
+    ResolveQuerySet {
+        query_set: id::QuerySetId,
+        first_query: u32,
+        query_count: u32,
+        destination: id::BufferId,
+        destination_offset: wgt::BufferAddress,
+        /// Distance between the resolved queries, or `None` for
+        /// the size of a resolved query.
+        destination_stride: Option<wgt::BufferAddress>,
+        /// Whether the resolve waits for the queries to be available,
+        /// instead of resolving an availability of zero.
+        wait: bool,
+    },
+    /// Submission of the command buffer encoded by the given encoder
+    /// to a queue, or to the default queue of the device if it's `None`.
+    /// Devices only have their default queue for now, so it's always `None`.
+    Submit(
+        crate::SubmissionIndex,
+        Option<id::QueueId>,
+        id::CommandEncoderId,
+        Vec<Command>,
+    ),
+    /// End of the frame `frame_index` of the application, and start of the next one.
+    FrameBoundary {
+        frame_index: u64,
+    },
+    /// Poll of the device, waiting for the submitted work to be done
+    /// if `force_wait` is set.
+    ///
+    /// There are no fences in `wgpu-core`, so this is where the application
+    /// waits for the GPU, along with mapping buffers. Fence actions would
+    /// be added here once the core exposes them.
+    Poll {
+        force_wait: bool,
+    },
+    /// Resources of the device that were held by the user at this point,
+    /// as recorded by `Global::device_trace_resource_snapshot`.
+    ResourceSnapshot {
+        live_buffers: Vec<id::BufferId>,
+        live_textures: Vec<id::TextureId>,
+        live_samplers: Vec<id::SamplerId>,
+        live_bind_groups: Vec<id::BindGroupId>,
+        live_shader_modules: Vec<id::ShaderModuleId>,
+        live_compute_pipelines: Vec<id::ComputePipelineId>,
+        live_render_pipelines: Vec<id::RenderPipelineId>,
+        live_render_bundles: Vec<id::RenderBundleId>,
+        live_query_sets: Vec<id::QuerySetId>,
     },
-    Submit(crate::SubmissionIndex, Vec<Command>),
 }
 
+/// Trace entry written instead of a plain `Action` when timing is enabled.
 #[derive(Debug)]
 #[cfg_attr(feature = "trace", derive(serde::Serialize))]
 #[cfg_attr(feature = "replay", derive(serde::Deserialize))]
+pub struct TimedAction {
+    pub action: Action,
+    /// Time of recording, in microseconds since the start of the trace.
+    pub cpu_time_us: u64,
+}
+
+/// Command recorded by a command encoder.
+///
+/// Push constants only exist within a pass, so they aren't commands of
+/// their own: each `SetPushConstant` of a compute or render pass, or of
+/// a render bundle, is recorded in its `BasePass`, with the values in
+/// `BasePass::push_constant_data`. Bind groups are set in the same way,
+/// by the `SetBindGroup` commands of the passes, with their dynamic offsets
+/// in `BasePass::dynamic_offsets`. The same goes for the rest of the state
+/// of a render pass: the vertex and index buffers, set by
+/// `RenderCommand::SetVertexBuffer` and `SetIndexBuffer`, the viewport and
+/// the scissor rectangle, set by `SetViewport` and `SetScissor`, and the
+/// blend color and the stencil reference, set by `SetBlendColor` and
+/// `SetStencilReference`. The index format of an indexed draw comes from
+/// the render pipeline, so it's recorded with `Action::CreateRenderPipeline`.
+#[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "trace", derive(serde::Serialize))]
+#[cfg_attr(feature = "replay", derive(serde::Deserialize))]
 pub enum Command {
     CopyBufferToBuffer {
         src: id::BufferId,
@@ -274,14 +501,87 @@ pub enum Command {
         dst: crate::command::TextureCopyView,
         size: wgt::Extent3d,
     },
+    ClearBuffer {
+        dst: id::BufferId,
+        offset: wgt::BufferAddress,
+        size: Option<wgt::BufferSize>,
+    },
+    ClearTexture {
+        dst: id::TextureId,
+        subresource_range: wgt::ImageSubresourceRange,
+    },
     RunComputePass {
         base: crate::command::BasePass<crate::command::ComputeCommand>,
+        timestamp_writes: Option<crate::command::PassTimestampWrites>,
     },
     RunRenderPass {
         base: crate::command::BasePass<crate::command::RenderCommand>,
         target_colors: Vec<crate::command::ColorAttachmentDescriptor>,
         target_depth_stencil: Option<crate::command::DepthStencilAttachmentDescriptor>,
+        timestamp_writes: Option<crate::command::PassTimestampWrites>,
+    },
+    PushDebugGroup(String),
+    PopDebugGroup,
+    InsertDebugMarker(String),
+    WriteTimestamp {
+        query_set: id::QuerySetId,
+        query_index: u32,
+        /// Bits of the `PipelineStage`.
+        pipeline_stage: u32,
+    },
+    ResolveQuerySet {
+        query_set: id::QuerySetId,
+        first_query: u32,
+        query_count: u32,
+        destination: id::BufferId,
+        destination_offset: wgt::BufferAddress,
+        /// Distance between the resolved queries, or `None` for
+        /// the size of a resolved query.
+        destination_stride: Option<wgt::BufferAddress>,
+        /// Whether the resolve waits for the queries to be available,
+        /// instead of resolving an availability of zero.
+        wait: bool,
     },
+    ResetQuerySet {
+        query_set: id::QuerySetId,
+        first_query: u32,
+        query_count: u32,
+    },
+}
+
+/// Returns the standalone action doing the same as the query `command`,
+/// or `None` if it's not a query command.
+#[cfg(feature = "trace")]
+fn standalone_query(command: &Command) -> Option<Action> {
+    match *command {
+        Command::WriteTimestamp {
+            query_set,
+            query_index,
+            pipeline_stage,
+        } => Some(Action::WriteTimestamp {
+            query_set,
+            query_index,
+            pipeline_stage,
+        }),
+        Command::ResolveQuerySet {
+            query_set,
+            first_query,
+            query_count,
+            destination,
+            destination_offset,
+            destination_stride,
+            wait,
+        } => Some(Action::ResolveQuerySet {
+            query_set,
+            first_query,
+            query_count,
+            destination,
+            destination_offset,
+            destination_stride,
+            wait,
+        }),
+        _ => None,
+    }
 }
 
 #[cfg(feature = "trace")]
@@ -289,46 +589,1721 @@ pub enum Command {
 pub struct Trace {
     path: std::path::PathBuf,
     file: std::fs::File,
-    config: ron::ser::PrettyConfig,
+    format: TraceFormat,
+    /// Formatting of the RON entries, or `None` to write each on one line.
+    config: Option<ron::ser::PrettyConfig>,
     binary_id: usize,
+    /// Files written by `make_binary`, by the hash of their contents and
+    /// their kind, in the order of their collision numbers.
+    binaries: std::collections::HashMap<(u64, String), Vec<FileName>>,
+    start_time: Option<std::time::Instant>,
+    compress: bool,
+}
+
+/// Reads a binary file of the trace, decompressing it if the name ends
+/// with `.gz` and the "trace-decompression" feature is enabled.
+#[cfg(any(feature = "trace", feature = "replay"))]
+pub fn read_binary(path: &std::path::Path) -> std::io::Result<Vec<u8>> {
+    #[cfg(feature = "trace-decompression")]
+    {
+        if path.extension() == Some(std::ffi::OsStr::new("gz")) {
+            use std::io::Read as _;
+            let mut data = Vec::new();
+            flate2::read::GzDecoder::new(std::fs::File::open(path)?).read_to_end(&mut data)?;
+            return Ok(data);
+        }
+    }
+    std::fs::read(path)
+}
+
+/// Writes a binary file of the trace, compressing it if the name asks for it.
+#[cfg(feature = "trace")]
+fn write_binary(path: &std::path::Path, data: &[u8]) -> std::io::Result<()> {
+    #[cfg(feature = "trace-compression")]
+    {
+        if path.extension() == Some(std::ffi::OsStr::new("gz")) {
+            let file = std::fs::File::create(path)?;
+            let mut encoder = flate2::write::GzEncoder::new(file, flate2::Compression::default());
+            encoder.write_all(data)?;
+            return encoder.finish().map(|_| ());
+        }
+    }
+    std::fs::write(path, data)
+}
+
+/// Size of the chunks in which `Trace::make_binary_from_reader` copies data.
+#[cfg(feature = "trace")]
+const BINARY_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Copies all the data from `reader` to `writer`, returning its CRC-32.
+#[cfg(feature = "trace")]
+fn copy_binary<R: std::io::Read, W: std::io::Write>(
+    mut reader: R,
+    mut writer: W,
+) -> std::io::Result<u32> {
+    let mut hasher = crc32fast::Hasher::new();
+    let mut chunk = vec![0; BINARY_CHUNK_SIZE];
+    loop {
+        let count = match reader.read(&mut chunk) {
+            Ok(0) => break,
+            Ok(count) => count,
+            Err(ref e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        hasher.update(&chunk[..count]);
+        writer.write_all(&chunk[..count])?;
+    }
+    writer.flush()?;
+    Ok(hasher.finalize())
 }
 
 #[cfg(feature = "trace")]
 impl Trace {
-    pub fn new(path: &std::path::Path) -> Result<Self, std::io::Error> {
-        log::info!("Tracing into '{:?}'", path);
-        let mut file = std::fs::File::create(path.join(FILE_NAME))?;
-        file.write_all(b"[\n")?;
-        Ok(Trace {
+    pub fn new(
+        path: &std::path::Path,
+        backend: wgt::Backend,
+        format: TraceFormat,
+    ) -> Result<Self, std::io::Error> {
+        Self::with_start_time(path, backend, format, None)
+    }
+
+    fn with_start_time(
+        path: &std::path::Path,
+        backend: wgt::Backend,
+        format: TraceFormat,
+        start_time: Option<std::time::Instant>,
+    ) -> Result<Self, std::io::Error> {
+        log::info!("Tracing into '{:?}' as {:?}", path, format);
+        let mut file = std::fs::File::create(path.join(format.file_name()))?;
+        if format == TraceFormat::Ron {
+            file.write_all(b"[\n")?;
+        }
+        let mut trace = Trace {
             path: path.to_path_buf(),
             file,
-            config: ron::ser::PrettyConfig::default(),
+            format,
+            config: Some(ron::ser::PrettyConfig::default()),
             binary_id: 0,
+            binaries: std::collections::HashMap::new(),
+            start_time,
+            compress: false,
+        };
+        trace.write_entry(&TraceHeader {
+            version: FORMAT_VERSION,
+            wgpu_core_git_hash: env!("WGPU_CORE_GIT_HASH").to_string(),
+            backend,
+            timed: start_time.is_some(),
+        })?;
+        Ok(trace)
+    }
+
+    /// Creates a trace that writes binary data compressed with gzip,
+    /// into files ending with `.gz`. The actions referring to them are
+    /// recorded as `compressed`.
+    #[cfg(feature = "trace-compression")]
+    pub fn new_compressed(
+        path: &std::path::Path,
+        backend: wgt::Backend,
+        format: TraceFormat,
+    ) -> Result<Self, std::io::Error> {
+        let mut trace = Self::new(path, backend, format)?;
+        trace.compress = true;
+        Ok(trace)
+    }
+
+    /// Creates a trace that records the CPU time of every action.
+    pub fn new_with_timing(
+        path: &std::path::Path,
+        backend: wgt::Backend,
+        format: TraceFormat,
+    ) -> Result<Self, std::io::Error> {
+        Self::with_start_time(path, backend, format, Some(std::time::Instant::now()))
+    }
+
+    /// Reopens an existing RON trace at `path` to continue recording into it.
+    ///
+    /// The closing bracket written when the previous `Trace` was dropped
+    /// is overwritten, and new binaries are numbered after the existing ones.
+    /// If the trace was recorded with timing, the new actions are timed too,
+    /// continuing from the last recorded time. Needs the "replay" feature
+    /// to read the recorded entries back.
+    #[cfg(feature = "replay")]
+    pub fn open_append(path: &std::path::Path) -> Result<Self, std::io::Error> {
+        use std::io::{Read as _, Seek as _};
+
+        log::info!("Appending to the trace in '{:?}'", path);
+        let mut file = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(path.join(FILE_NAME))?;
+        let mut contents = String::new();
+        file.read_to_string(&mut contents)?;
+        let end = match contents.trim_end().strip_suffix(']') {
+            Some(list) => list.len(),
+            None => {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    "trace is not terminated by ']'",
+                ))
+            }
+        };
+        let start_time = last_cpu_time_us(&contents).map(|last_us| {
+            let now = std::time::Instant::now();
+            now.checked_sub(std::time::Duration::from_micros(last_us))
+                .unwrap_or(now)
+        });
+        file.set_len(end as u64)?;
+        file.seek(std::io::SeekFrom::Start(end as u64))?;
+
+        let mut binary_id = 0;
+        for entry in std::fs::read_dir(path)? {
+            let name = entry?.file_name();
+            let id = name
+                .to_str()
+                .and_then(|name| name.strip_prefix("data"))
+                .and_then(|rest| rest.split('.').next())
+                .and_then(|digits| digits.parse().ok());
+            if let Some(id) = id {
+                binary_id = binary_id.max(id);
+            }
+        }
+
+        Ok(Trace {
+            path: path.to_path_buf(),
+            file,
+            format: TraceFormat::Ron,
+            config: Some(ron::ser::PrettyConfig::default()),
+            binary_id,
+            binaries: std::collections::HashMap::new(),
+            start_time,
+            compress: false,
         })
     }
 
+    pub(crate) fn create(
+        path: &std::path::Path,
+        backend: wgt::Backend,
+    ) -> Result<Self, std::io::Error> {
+        let format = if cfg!(feature = "trace-bincode") {
+            TraceFormat::Bincode
+        } else {
+            TraceFormat::Ron
+        };
+        if cfg!(feature = "trace-timing") {
+            Self::new_with_timing(path, backend, format)
+        } else {
+            Self::new(path, backend, format)
+        }
+    }
+
+    /// Whether the binary files are compressed, see `Trace::new_compressed`.
+    pub fn is_compressed(&self) -> bool {
+        self.compress
+    }
+
+    /// Writes the data into a binary file named after the hash of its
+    /// contents, like `shader_0123456789ABCDEF.spv`, unless the trace
+    /// already has a file with the same contents.
+    ///
+    /// A file that can't be written is logged, and written again by the next
+    /// call with the same data.
     pub fn make_binary(&mut self, kind: &str, data: &[u8]) -> String {
+        use std::hash::{Hash as _, Hasher as _};
+
+        let mut hasher = fxhash::FxHasher64::default();
+        data.hash(&mut hasher);
+        let hash = hasher.finish();
+        let names = self.binaries.entry((hash, kind.to_string())).or_default();
+        // compare the contents, in case of a hash collision
+        for name in names.iter() {
+            if read_binary(&self.path.join(name)).map_or(false, |old| old == data) {
+                return name.clone();
+            }
+        }
+
+        let prefix = match kind {
+            "spv" | "wgsl" => "shader",
+            _ => "data",
+        };
+        let base = format!("{}_{:016X}", prefix, hash);
+        let mut collision = names.len();
+        loop {
+            let name = match collision {
+                0 => format!("{}.{}", base, kind),
+                _ => format!("{}_{}.{}", base, collision, kind),
+            };
+            #[cfg(feature = "trace-compression")]
+            let name = if self.compress {
+                format!("{}.gz", name)
+            } else {
+                name
+            };
+            let path = self.path.join(&name);
+            // the file may be left by the recording appended to
+            if path.exists() {
+                if read_binary(&path).map_or(false, |old| old == data) {
+                    names.push(name.clone());
+                    return name;
+                }
+                collision += 1;
+                continue;
+            }
+            match write_binary(&path, data) {
+                Ok(()) => names.push(name.clone()),
+                Err(e) => log::warn!("Unable to write the trace binary {:?}: {:?}", path, e),
+            }
+            return name;
+        }
+    }
+
+    /// Writes the data coming from `reader` into a new binary file, without
+    /// holding all of it in memory. The CRC-32 of the data is put into
+    /// the file name, like `data1.crc32_AABBCCDD.bin`.
+    ///
+    /// Unlike `make_binary`, the data isn't shared with identical binaries.
+    pub fn make_binary_from_reader<R: std::io::Read>(
+        &mut self,
+        kind: &str,
+        reader: R,
+    ) -> std::io::Result<String> {
         self.binary_id += 1;
-        let name = format!("data{}.{}", self.binary_id, kind);
-        let _ = std::fs::write(self.path.join(&name), data);
-        name
+        // the name isn't known until all the data is read
+        let partial_path = self
+            .path
+            .join(format!("data{}.{}.partial", self.binary_id, kind));
+        let file = std::fs::File::create(&partial_path)?;
+        #[cfg(feature = "trace-compression")]
+        let crc = if self.compress {
+            let mut encoder = flate2::write::GzEncoder::new(file, flate2::Compression::default());
+            let crc = copy_binary(reader, &mut encoder)?;
+            encoder.finish()?;
+            crc
+        } else {
+            copy_binary(reader, file)?
+        };
+        #[cfg(not(feature = "trace-compression"))]
+        let crc = copy_binary(reader, file)?;
+
+        let name = format!("data{}.crc32_{:08X}.{}", self.binary_id, crc, kind);
+        #[cfg(feature = "trace-compression")]
+        let name = if self.compress {
+            format!("{}.gz", name)
+        } else {
+            name
+        };
+        std::fs::rename(&partial_path, self.path.join(&name))?;
+        Ok(name)
+    }
+
+    pub fn make_text(&mut self, kind: &str, text: &str) -> String {
+        self.make_binary(kind, text.as_bytes())
+    }
+
+    /// Records a submission of `commands` encoded by `encoder` to `queue`.
+    /// If they are only working with queries, they are recorded as standalone
+    /// actions instead, followed by dropping the encoder.
+    pub(crate) fn add_submit(
+        &mut self,
+        index: crate::SubmissionIndex,
+        queue: Option<id::QueueId>,
+        encoder: id::CommandEncoderId,
+        commands: Vec<Command>,
+    ) {
+        match commands
+            .iter()
+            .map(standalone_query)
+            .collect::<Option<Vec<_>>>()
+        {
+            Some(actions) if !actions.is_empty() => {
+                for action in actions {
+                    self.add(action);
+                }
+                self.add(Action::DropCommandEncoder(encoder));
+            }
+            _ => self.add(Action::Submit(index, queue, encoder, commands)),
+        }
+    }
+
+    fn write_entry<T: serde::Serialize>(&mut self, entry: &T) -> Result<(), std::io::Error> {
+        let to_io_error = |e: String| std::io::Error::new(std::io::ErrorKind::Other, e);
+        match self.format {
+            TraceFormat::Ron => {
+                let string = match self.config {
+                    Some(ref config) => ron::ser::to_string_pretty(entry, config.clone()),
+                    None => ron::ser::to_string(entry),
+                }
+                .map_err(|e| to_io_error(e.to_string()))?;
+                // written at once, so that a crash doesn't cut the entry
+                // in the middle as easily
+                self.file.write_all(format!("{},\n", string).as_bytes())
+            }
+            TraceFormat::Bincode => {
+                let bytes = bincode::serialize(entry).map_err(|e| to_io_error(e.to_string()))?;
+                self.file.write_all(&(bytes.len() as u64).to_le_bytes())?;
+                self.file.write_all(&bytes)
+            }
+        }
     }
 
-    pub(crate) fn add(&mut self, action: Action) {
-        match ron::ser::to_string_pretty(&action, self.config.clone()) {
-            Ok(string) => {
-                let _ = writeln!(self.file, "{},", string);
+    pub fn add(&mut self, action: Action) {
+        let result = match self.start_time {
+            Some(start_time) => self.write_entry(&TimedAction {
+                action,
+                cpu_time_us: start_time.elapsed().as_micros() as u64,
+            }),
+            None => self.write_entry(&action),
+        };
+        if let Err(e) = result {
+            log::warn!("Trace serialization failure: {:?}", e);
+        }
+    }
+
+    /// Sets how the RON entries added from now on are formatted. With `None`,
+    /// each entry is written on a single line, which makes the trace smaller
+    /// and faster to parse. Entries are pretty-printed by default.
+    ///
+    /// The traces of devices are configured with
+    /// `Global::device_set_trace_ron_config`.
+    pub fn set_ron_config(&mut self, config: Option<ron::ser::PrettyConfig>) {
+        self.config = config;
+    }
+
+    /// Writes the `TraceMetadata` of the device created with `device_desc`
+    /// on the adapter described by `adapter_info` into `METADATA_FILE_NAME`.
+    pub fn write_metadata(
+        &self,
+        device_desc: &wgt::DeviceDescriptor,
+        adapter_info: &crate::instance::AdapterInfo,
+    ) -> Result<(), std::io::Error> {
+        let metadata = TraceMetadata {
+            wgpu_core_version: env!("CARGO_PKG_VERSION").to_string(),
+            adapter_info: adapter_info.clone(),
+            device_features: device_desc.features,
+            os: std::env::consts::OS.to_string(),
+            arch: std::env::consts::ARCH.to_string(),
+            capture_time: std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map_or(0, |time| time.as_secs()),
+        };
+        let json = serde_json::to_string_pretty(&metadata)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
+        std::fs::write(self.path.join(METADATA_FILE_NAME), json)
+    }
+
+    /// Makes sure the entries added so far reach the file, so that the trace
+    /// can be replayed up to here if the process crashes later on. With
+    /// `sync`, they are also synchronized to the disk, which is slower.
+    ///
+    /// The trace isn't terminated until it's dropped, but the player
+    /// reads an unterminated RON trace until its end. The traces of devices
+    /// are flushed with `Global::device_flush_trace`.
+    pub fn flush(&mut self, sync: bool) -> Result<(), std::io::Error> {
+        self.file.flush()?;
+        if sync {
+            self.file.sync_data()?;
+        }
+        Ok(())
+    }
+
+    /// Records queued writes of `data` at the given offsets of the buffer `id`.
+    ///
+    /// Writes starting where the previous one ended share a binary file.
+    pub fn add_buffer_writes(&mut self, id: id::BufferId, writes: &[(wgt::BufferAddress, &[u8])]) {
+        let writes = coalesce_buffer_writes(writes)
+            .into_iter()
+            .map(|(range, bytes)| (range, self.make_binary("bin", &bytes)))
+            .collect();
+        let compressed = self.compress;
+        self.add(Action::WriteBufferMany {
+            id,
+            writes,
+            compressed,
+        });
+    }
+
+    /// Marks the end of the frame `frame_index`, for the applications
+    /// that don't present to a swap chain. The traces of devices are marked
+    /// with `Global::device_add_trace_frame_boundary`.
+    pub fn add_frame_boundary(&mut self, frame_index: u64) {
+        self.add(Action::FrameBoundary { frame_index });
+    }
+
+    /// Appends an entry that is already serialized in RON.
+    ///
+    /// The entry has to match the other ones: a `TimedAction` if the trace
+    /// records timing, and an `Action` otherwise.
+    pub fn add_raw_ron(&mut self, ron: &str) -> Result<(), std::io::Error> {
+        match self.format {
+            TraceFormat::Ron => writeln!(self.file, "{},", ron.trim_end()),
+            TraceFormat::Bincode => Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "RON entries can't be added to a bincode trace",
+            )),
+        }
+    }
+}
+
+/// Parses a RON trace, returning the recording time of its last action,
+/// or `None` if its header says that the actions aren't timed.
+#[cfg(all(feature = "trace", feature = "replay"))]
+fn last_cpu_time_us(trace: &str) -> Option<u64> {
+    use serde::de::{Deserializer as _, Error as _, SeqAccess, Visitor};
+
+    struct LastTime;
+    impl<'de> Visitor<'de> for LastTime {
+        type Value = Option<u64>;
+
+        fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+            formatter.write_str("a trace header followed by timed actions")
+        }
+
+        fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Option<u64>, A::Error> {
+            match seq.next_element::<TraceHeader>()? {
+                Some(header) if header.timed => {}
+                Some(_) => return Ok(None),
+                None => return Err(A::Error::invalid_length(0, &self)),
+            }
+            let mut last_us = 0;
+            while let Some(entry) = seq.next_element::<TimedAction>()? {
+                last_us = entry.cpu_time_us;
             }
-            Err(e) => {
-                log::warn!("RON serialization failure: {:?}", e);
+            Ok(Some(last_us))
+        }
+    }
+
+    let mut deserializer = ron::de::Deserializer::from_str(trace).ok()?;
+    deserializer.deserialize_seq(LastTime).ok().flatten()
+}
+
+/// Merges each buffer write with the previous one if it starts where that one ends.
+#[cfg(feature = "trace")]
+fn coalesce_buffer_writes(
+    writes: &[(wgt::BufferAddress, &[u8])],
+) -> Vec<(Range<wgt::BufferAddress>, Vec<u8>)> {
+    let mut coalesced = Vec::<(Range<wgt::BufferAddress>, Vec<u8>)>::new();
+    for &(offset, data) in writes {
+        let end = offset + data.len() as wgt::BufferAddress;
+        match coalesced.last_mut() {
+            Some(&mut (ref mut range, ref mut bytes)) if range.end == offset => {
+                range.end = end;
+                bytes.extend_from_slice(data);
             }
+            _ => coalesced.push((offset..end, data.to_vec())),
         }
     }
+    coalesced
 }
 
 #[cfg(feature = "trace")]
 impl Drop for Trace {
     fn drop(&mut self) {
-        let _ = self.file.write_all(b"]");
+        if self.format == TraceFormat::Ron {
+            let _ = self.file.write_all(b"]");
+        }
+    }
+}
+
+/// Builds one of each `Action` and `Command`, filling in the nested types.
+#[cfg(all(test, feature = "trace", feature = "replay"))]
+fn sample_actions() -> Vec<Action> {
+    use crate::command::{
+        BasePass, BufferCopyView, ColorAttachmentDescriptor, ComputeCommand,
+        DepthStencilAttachmentDescriptor, LoadOp, PassChannel, PassTimestampWrites, Rect,
+        RenderCommand, StoreOp, TextureCopyView,
+    };
+
+    fn id<I: id::TypedId>(index: u32) -> I {
+        I::zip(index, 1, wgt::Backend::Vulkan)
     }
+    let stage = |index| ProgrammableStageDescriptor {
+        module: id(index),
+        entry_point: "main".to_string(),
+    };
+    let texture_copy = || TextureCopyView {
+        texture: id(0),
+        mip_level: 2,
+        origin: wgt::Origin3d { x: 1, y: 2, z: 3 },
+    };
+    let buffer_copy = || BufferCopyView {
+        buffer: id(0),
+        layout: wgt::TextureDataLayout {
+            offset: 16,
+            bytes_per_row: 256,
+            rows_per_image: 4,
+        },
+    };
+    let size = wgt::Extent3d {
+        width: 4,
+        height: 4,
+        depth: 1,
+    };
+    let mut entries = std::collections::BTreeMap::new();
+    entries.insert(
+        0,
+        BindingResource::Buffer {
+            id: id(0),
+            offset: 256,
+            size: wgt::BufferSize::new(64),
+        },
+    );
+    entries.insert(1, BindingResource::Sampler(id(0)));
+    entries.insert(2, BindingResource::TextureView(id(0)));
+    entries.insert(
+        3,
+        BindingResource::TextureViewArray(Box::new([id(0), id(1)])),
+    );
+
+    let commands = vec![
+        Command::CopyBufferToBuffer {
+            src: id(0),
+            src_offset: 4,
+            dst: id(1),
+            dst_offset: 8,
+            size: 16,
+        },
+        Command::CopyBufferToTexture {
+            src: buffer_copy(),
+            dst: texture_copy(),
+            size,
+        },
+        Command::CopyTextureToBuffer {
+            src: texture_copy(),
+            dst: buffer_copy(),
+            size,
+        },
+        Command::CopyTextureToTexture {
+            src: texture_copy(),
+            dst: texture_copy(),
+            size,
+        },
+        Command::ClearBuffer {
+            dst: id(0),
+            offset: 4,
+            size: wgt::BufferSize::new(8),
+        },
+        Command::ClearBuffer {
+            dst: id(0),
+            offset: 0,
+            size: None,
+        },
+        Command::ClearTexture {
+            dst: id(0),
+            subresource_range: wgt::ImageSubresourceRange {
+                aspect: wgt::TextureAspect::All,
+                base_mip_level: 1,
+                level_count: 0,
+                base_array_layer: 2,
+                array_layer_count: 1,
+            },
+        },
+        Command::RunComputePass {
+            base: BasePass {
+                commands: vec![
+                    ComputeCommand::SetBindGroup {
+                        index: 0,
+                        num_dynamic_offsets: 1,
+                        bind_group_id: id(0),
+                    },
+                    ComputeCommand::SetPipeline(id(0)),
+                    ComputeCommand::SetPushConstant {
+                        offset: 4,
+                        size_bytes: 8,
+                        values_offset: 1,
+                    },
+                    ComputeCommand::Dispatch([1, 2, 3]),
+                    ComputeCommand::DispatchIndirect {
+                        buffer_id: id(0),
+                        offset: 12,
+                    },
+                ],
+                dynamic_offsets: vec![256],
+                string_data: Vec::new(),
+                push_constant_data: vec![0, 1, 0xDEAD_BEEF],
+            },
+            timestamp_writes: None,
+        },
+        Command::RunRenderPass {
+            base: BasePass {
+                commands: vec![
+                    RenderCommand::SetPipeline(id(0)),
+                    RenderCommand::SetBindGroup {
+                        index: 1,
+                        num_dynamic_offsets: 2,
+                        bind_group_id: id(1),
+                    },
+                    RenderCommand::SetViewport {
+                        rect: Rect {
+                            x: 0.0,
+                            y: 0.0,
+                            w: 640.0,
+                            h: 480.0,
+                        },
+                        depth_min: 0.0,
+                        depth_max: 1.0,
+                    },
+                    RenderCommand::SetScissor(Rect {
+                        x: 16,
+                        y: 16,
+                        w: 128,
+                        h: 64,
+                    }),
+                    RenderCommand::SetBlendColor(wgt::Color {
+                        r: 0.25,
+                        g: 0.5,
+                        b: 0.75,
+                        a: 1.0,
+                    }),
+                    RenderCommand::SetStencilReference(0x80),
+                    RenderCommand::SetVertexBuffer {
+                        slot: 1,
+                        buffer_id: id(0),
+                        offset: 0,
+                        size: None,
+                    },
+                    RenderCommand::Draw {
+                        vertex_count: 3,
+                        instance_count: 1,
+                        first_vertex: 0,
+                        first_instance: 0,
+                    },
+                    RenderCommand::SetVertexBuffer {
+                        slot: 0,
+                        buffer_id: id(1),
+                        offset: 64,
+                        size: wgt::BufferSize::new(192),
+                    },
+                    RenderCommand::SetIndexBuffer {
+                        buffer_id: id(2),
+                        offset: 256,
+                        size: wgt::BufferSize::new(1024),
+                    },
+                    RenderCommand::DrawIndexed {
+                        index_count: 6,
+                        instance_count: 1,
+                        first_index: 0,
+                        base_vertex: -2,
+                        first_instance: 0,
+                    },
+                    RenderCommand::SetPushConstant {
+                        stages: wgt::ShaderStage::VERTEX | wgt::ShaderStage::FRAGMENT,
+                        offset: 0,
+                        size_bytes: 4,
+                        values_offset: Some(0),
+                    },
+                    RenderCommand::SetPushConstant {
+                        stages: wgt::ShaderStage::VERTEX,
+                        offset: 4,
+                        size_bytes: 4,
+                        values_offset: None,
+                    },
+                    RenderCommand::ExecuteBundle(id(0)),
+                ],
+                dynamic_offsets: vec![0, 512],
+                string_data: Vec::new(),
+                push_constant_data: vec![7],
+            },
+            target_colors: vec![ColorAttachmentDescriptor {
+                attachment: id(0),
+                resolve_target: Some(id(1)),
+                channel: PassChannel {
+                    load_op: LoadOp::Clear,
+                    store_op: StoreOp::Store,
+                    clear_value: wgt::Color {
+                        r: 0.25,
+                        g: 0.5,
+                        b: 0.75,
+                        a: 1.0,
+                    },
+                    read_only: false,
+                },
+            }],
+            target_depth_stencil: Some(DepthStencilAttachmentDescriptor {
+                attachment: id(2),
+                depth: PassChannel {
+                    load_op: LoadOp::Load,
+                    store_op: StoreOp::Store,
+                    clear_value: 1.0,
+                    read_only: true,
+                },
+                stencil: PassChannel {
+                    load_op: LoadOp::Clear,
+                    store_op: StoreOp::Clear,
+                    clear_value: 0,
+                    read_only: false,
+                },
+            }),
+            timestamp_writes: Some(PassTimestampWrites {
+                query_set: id(1),
+                beginning_of_pass_write_index: Some(0),
+                end_of_pass_write_index: Some(1),
+            }),
+        },
+        Command::PushDebugGroup("group".to_string()),
+        Command::InsertDebugMarker("marker".to_string()),
+        Command::PopDebugGroup,
+        Command::WriteTimestamp {
+            query_set: id(0),
+            query_index: 1,
+            pipeline_stage: 4,
+        },
+        Command::ResolveQuerySet {
+            query_set: id(0),
+            first_query: 0,
+            query_count: 2,
+            destination: id(0),
+            destination_offset: 256,
+            destination_stride: None,
+            wait: true,
+        },
+        Command::ResolveQuerySet {
+            query_set: id(0),
+            first_query: 0,
+            query_count: 2,
+            destination: id(0),
+            destination_offset: 512,
+            destination_stride: Some(16),
+            wait: false,
+        },
+        Command::ResetQuerySet {
+            query_set: id(0),
+            first_query: 0,
+            query_count: 4,
+        },
+    ];
+
+    vec![
+        Action::Init {
+            device: id(0),
+            desc: wgt::DeviceDescriptor::default(),
+            backend: wgt::Backend::Vulkan,
+            adapter_info: crate::instance::AdapterInfo {
+                name: "adapter".to_string(),
+                vendor: 0x10DE,
+                device: 0x1B80,
+                device_type: crate::instance::DeviceType::DiscreteGpu,
+                backend: wgt::Backend::Vulkan,
+            },
+            adapter_features: wgt::Features::PUSH_CONSTANTS,
+            adapter_limits: wgt::Limits::default(),
+        },
+        Action::CreateBuffer {
+            id: id(0),
+            desc: wgt::BufferDescriptor {
+                label: Some("buffer".to_string()),
+                size: 256,
+                usage: wgt::BufferUsage::MAP_READ | wgt::BufferUsage::COPY_DST,
+                mapped_at_creation: true,
+            },
+        },
+        Action::DestroyBuffer(id(0)),
+        Action::CreateTexture {
+            id: id(0),
+            desc: wgt::TextureDescriptor {
+                label: None,
+                size: wgt::Extent3d {
+                    width: 8,
+                    height: 8,
+                    depth: 4,
+                },
+                mip_level_count: 3,
+                sample_count: 1,
+                dimension: wgt::TextureDimension::D2,
+                format: wgt::TextureFormat::Rgba8Unorm,
+                usage: wgt::TextureUsage::COPY_DST | wgt::TextureUsage::SAMPLED,
+            },
+        },
+        Action::DestroyTexture(id(0)),
+        Action::CreateTextureView {
+            id: id(0),
+            parent_id: id(0),
+            desc: Some(wgt::TextureViewDescriptor {
+                label: Some("view".to_string()),
+                format: wgt::TextureFormat::Rgba8Unorm,
+                dimension: wgt::TextureViewDimension::D2Array,
+                aspect: wgt::TextureAspect::All,
+                base_mip_level: 1,
+                level_count: 2,
+                base_array_layer: 1,
+                array_layer_count: 3,
+            }),
+        },
+        Action::CreateTextureView {
+            id: id(1),
+            parent_id: id(0),
+            desc: None,
+        },
+        Action::DestroyTextureView(id(0)),
+        Action::CreateSampler {
+            id: id(0),
+            desc: wgt::SamplerDescriptor {
+                lod_max_clamp: 8.0,
+                compare: Some(wgt::CompareFunction::Less),
+                anisotropy_clamp: Some(16),
+                ..Default::default()
+            },
+        },
+        Action::DestroySampler(id(0)),
+        Action::CreateSurface {
+            id: id(0),
+            backend: wgt::Backend::Vulkan,
+        },
+        Action::CreateSwapChain {
+            id: id(0),
+            desc: wgt::SwapChainDescriptor {
+                usage: wgt::TextureUsage::OUTPUT_ATTACHMENT,
+                format: wgt::TextureFormat::Bgra8UnormSrgb,
+                width: 640,
+                height: 480,
+                present_mode: wgt::PresentMode::Fifo,
+            },
+        },
+        Action::GetSwapChainTexture {
+            id: Some(id(2)),
+            parent_id: id(0),
+        },
+        Action::GetSwapChainTexture {
+            id: None,
+            parent_id: id(0),
+        },
+        Action::PresentSwapChain(id(0)),
+        Action::CreateBindGroupLayout {
+            id: id(0),
+            label: Some("layout".to_string()),
+            entries: vec![wgt::BindGroupLayoutEntry::new(
+                0,
+                wgt::ShaderStage::VERTEX | wgt::ShaderStage::FRAGMENT,
+                wgt::BindingType::UniformBuffer {
+                    dynamic: true,
+                    min_binding_size: wgt::BufferSize::new(16),
+                },
+            )],
+        },
+        Action::DestroyBindGroupLayout(id(0)),
+        Action::CreatePipelineLayout {
+            id: id(0),
+            bind_group_layouts: vec![id(0), id(1)],
+            push_constant_ranges: vec![wgt::PushConstantRange {
+                stages: wgt::ShaderStage::COMPUTE,
+                range: 0..16,
+            }],
+        },
+        Action::DestroyPipelineLayout(id(0)),
+        Action::CreateBindGroup {
+            id: id(0),
+            label: None,
+            layout_id: id(0),
+            entries,
+        },
+        Action::DestroyBindGroup(id(0)),
+        Action::CreateShaderModule {
+            id: id(0),
+            source: ShaderModuleSource::SpirV("data1.spv".to_string()),
+            compressed: false,
+        },
+        Action::CreateShaderModule {
+            id: id(1),
+            source: ShaderModuleSource::Wgsl("data2.wgsl".to_string()),
+            compressed: false,
+        },
+        Action::DestroyShaderModule(id(0)),
+        Action::CreateComputePipeline {
+            id: id(0),
+            desc: ComputePipelineDescriptor {
+                layout: id(0),
+                compute_stage: stage(0),
+            },
+        },
+        Action::DestroyComputePipeline(id(0)),
+        Action::CreateRenderPipeline {
+            id: id(0),
+            desc: RenderPipelineDescriptor {
+                layout: id(0),
+                vertex_stage: stage(0),
+                fragment_stage: Some(stage(1)),
+                primitive_topology: wgt::PrimitiveTopology::TriangleStrip,
+                rasterization_state: Some(wgt::RasterizationStateDescriptor {
+                    front_face: wgt::FrontFace::Cw,
+                    cull_mode: wgt::CullMode::Back,
+                    depth_bias: 2,
+                    depth_bias_slope_scale: 0.5,
+                    depth_bias_clamp: 0.0,
+                }),
+                color_states: vec![wgt::ColorStateDescriptor {
+                    format: wgt::TextureFormat::Rgba8Unorm,
+                    alpha_blend: wgt::BlendDescriptor::REPLACE,
+                    color_blend: wgt::BlendDescriptor {
+                        src_factor: wgt::BlendFactor::SrcAlpha,
+                        dst_factor: wgt::BlendFactor::OneMinusSrcAlpha,
+                        operation: wgt::BlendOperation::Add,
+                    },
+                    write_mask: wgt::ColorWrite::ALL,
+                }],
+                depth_stencil_state: Some(wgt::DepthStencilStateDescriptor {
+                    format: wgt::TextureFormat::Depth24PlusStencil8,
+                    depth_write_enabled: true,
+                    depth_compare: wgt::CompareFunction::LessEqual,
+                    stencil_front: wgt::StencilStateFaceDescriptor::default(),
+                    stencil_back: wgt::StencilStateFaceDescriptor::default(),
+                    stencil_read_mask: 0xFF,
+                    stencil_write_mask: 0x0F,
+                }),
+                vertex_state: VertexStateDescriptor {
+                    index_format: wgt::IndexFormat::Uint32,
+                    vertex_buffers: vec![VertexBufferDescriptor {
+                        stride: 16,
+                        step_mode: wgt::InputStepMode::Instance,
+                        attributes: vec![wgt::VertexAttributeDescriptor {
+                            offset: 0,
+                            format: wgt::VertexFormat::Float4,
+                            shader_location: 0,
+                        }],
+                    }],
+                },
+                sample_count: 4,
+                sample_mask: !0,
+                alpha_to_coverage_enabled: true,
+            },
+        },
+        Action::DestroyRenderPipeline(id(0)),
+        Action::CreateRenderBundle {
+            id: id(0),
+            desc: RenderBundleDescriptor {
+                label: Some("bundle".to_string()),
+                color_formats: vec![wgt::TextureFormat::Rgba8Unorm],
+                depth_stencil_format: Some(wgt::TextureFormat::Depth32Float),
+                sample_count: 1,
+            },
+            base: BasePass {
+                commands: vec![
+                    RenderCommand::SetPipeline(id(0)),
+                    RenderCommand::Draw {
+                        vertex_count: 3,
+                        instance_count: 2,
+                        first_vertex: 1,
+                        first_instance: 0,
+                    },
+                ],
+                dynamic_offsets: Vec::new(),
+                string_data: Vec::new(),
+                push_constant_data: Vec::new(),
+            },
+        },
+        Action::DestroyRenderBundle(id(0)),
+        Action::CreateQuerySet {
+            id: id(0),
+            desc: QuerySetDescriptor {
+                type_: QueryType::PipelineStatistics(vec![
+                    wgt::PipelineStatisticName::VertexShaderInvocations,
+                    wgt::PipelineStatisticName::ComputeShaderInvocations,
+                ]),
+                count: 4,
+            },
+        },
+        Action::CreateQuerySet {
+            id: id(1),
+            desc: QuerySetDescriptor {
+                type_: QueryType::Timestamp,
+                count: 2,
+            },
+        },
+        Action::DestroyQuerySet(id(0)),
+        Action::CreateCommandEncoder {
+            id: id(0),
+            desc: wgt::CommandEncoderDescriptor {
+                label: Some("encoder".to_string()),
+            },
+        },
+        Action::CreateCommandEncoder {
+            id: id(1),
+            desc: wgt::CommandEncoderDescriptor { label: None },
+        },
+        Action::CreateCommandEncoder {
+            id: id(2),
+            desc: wgt::CommandEncoderDescriptor { label: None },
+        },
+        Action::DropCommandEncoder(id(2)),
+        Action::WriteBuffer {
+            id: id(0),
+            data: "data3.bin".to_string(),
+            range: 16..32,
+            queued: true,
+            compressed: false,
+        },
+        Action::WriteBufferMany {
+            id: id(0),
+            writes: vec![
+                (0..16, "data4.bin".to_string()),
+                (32..36, "data5.bin".to_string()),
+            ],
+            compressed: true,
+        },
+        Action::ClearBuffer {
+            id: id(0),
+            offset: 4,
+            size: Some(8),
+        },
+        Action::ClearBuffer {
+            id: id(0),
+            offset: 16,
+            size: None,
+        },
+        Action::WriteTexture {
+            to: texture_copy(),
+            data: "data4.bin".to_string(),
+            layout: wgt::TextureDataLayout {
+                offset: 0,
+                bytes_per_row: 16,
+                rows_per_image: 4,
+            },
+            size,
+            compressed: false,
+        },
+        Action::MapBuffer {
+            id: id(0),
+            mode: wgt::MapMode::Read,
+            range: 0..64,
+        },
+        Action::UnmapBuffer(id(0)),
+        Action::WriteTimestamp {
+            query_set: id(1),
+            query_index: 0,
+            pipeline_stage: 1,
+        },
+        Action::ResolveQuerySet {
+            query_set: id(1),
+            first_query: 0,
+            query_count: 2,
+            destination: id(0),
+            destination_offset: 0,
+            destination_stride: Some(12),
+            wait: true,
+        },
+        Action::Submit(1, None, id(0), commands),
+        Action::Submit(2, Some(id(2)), id(1), Vec::new()),
+        Action::FrameBoundary { frame_index: 0 },
+        Action::Poll { force_wait: true },
+        Action::ResourceSnapshot {
+            live_buffers: vec![id(0)],
+            live_textures: vec![id(0), id(1)],
+            live_samplers: Vec::new(),
+            live_bind_groups: vec![id(0)],
+            live_shader_modules: vec![id(0)],
+            live_compute_pipelines: vec![id(0)],
+            live_render_pipelines: Vec::new(),
+            live_render_bundles: Vec::new(),
+            live_query_sets: vec![id(1)],
+        },
+    ]
+}
+
+/// Parses back the RON of the `action`.
+#[cfg(all(test, feature = "trace", feature = "replay"))]
+fn ron_round_trip(action: &Action) -> Action {
+    let string = ron::ser::to_string_pretty(action, ron::ser::PrettyConfig::default())
+        .unwrap_or_else(|e| panic!("Unable to serialize {:?}: {}", action, e));
+    ron::de::from_str::<Action>(&string)
+        .unwrap_or_else(|e| panic!("Unable to deserialize {}: {}", string, e))
+}
+
+#[cfg(all(feature = "trace", feature = "replay"))]
+#[test]
+fn test_action_round_trip() {
+    for action in sample_actions() {
+        assert_eq!(ron_round_trip(&action), action);
+    }
+}
+
+/// Strategies generating arbitrary actions and commands, along with their
+/// IDs and descriptors, for the property-based tests.
+#[cfg(all(test, feature = "trace", feature = "replay"))]
+mod strategies {
+    use super::{Action, Command, QuerySetDescriptor, QueryType};
+    use crate::id;
+    use proptest::{option, prelude::*};
+
+    pub fn id<I: id::TypedId + std::fmt::Debug>() -> impl Strategy<Value = I> {
+        let backend = prop_oneof![
+            Just(wgt::Backend::Vulkan),
+            Just(wgt::Backend::Metal),
+            Just(wgt::Backend::Dx12),
+            Just(wgt::Backend::Dx11),
+        ];
+        (0..1024u32, 1..1024u32, backend)
+            .prop_map(|(index, epoch, backend)| I::zip(index, epoch, backend))
+    }
+
+    fn label() -> impl Strategy<Value = Option<String>> {
+        option::of("[a-zA-Z0-9 _]{0,12}")
+    }
+
+    fn range() -> impl Strategy<Value = std::ops::Range<wgt::BufferAddress>> {
+        (any::<u64>(), any::<u64>()).prop_map(|(start, end)| start..end)
+    }
+
+    fn buffer_desc() -> impl Strategy<Value = wgt::BufferDescriptor<Option<String>>> {
+        (label(), any::<u64>(), any::<u32>(), any::<bool>()).prop_map(
+            |(label, size, usage, mapped_at_creation)| wgt::BufferDescriptor {
+                label,
+                size,
+                usage: wgt::BufferUsage::from_bits_truncate(usage),
+                mapped_at_creation,
+            },
+        )
+    }
+
+    fn query_set_desc() -> impl Strategy<Value = QuerySetDescriptor> {
+        let statistic = prop_oneof![
+            Just(wgt::PipelineStatisticName::VertexShaderInvocations),
+            Just(wgt::PipelineStatisticName::ClipperInvocations),
+            Just(wgt::PipelineStatisticName::ClipperPrimitivesOut),
+            Just(wgt::PipelineStatisticName::FragmentShaderInvocations),
+            Just(wgt::PipelineStatisticName::ComputeShaderInvocations),
+        ];
+        let type_ = prop_oneof![
+            Just(()).prop_map(|()| QueryType::Occlusion),
+            Just(()).prop_map(|()| QueryType::Timestamp),
+            proptest::collection::vec(statistic, 0..5).prop_map(QueryType::PipelineStatistics),
+        ];
+        (type_, any::<u32>()).prop_map(|(type_, count)| QuerySetDescriptor { type_, count })
+    }
+
+    pub fn command() -> impl Strategy<Value = Command> {
+        prop_oneof![
+            (id(), any::<u64>(), id(), any::<u64>(), any::<u64>()).prop_map(
+                |(src, src_offset, dst, dst_offset, size)| Command::CopyBufferToBuffer {
+                    src,
+                    src_offset,
+                    dst,
+                    dst_offset,
+                    size,
+                }
+            ),
+            (id(), any::<u64>(), any::<u64>()).prop_map(|(dst, offset, size)| {
+                Command::ClearBuffer {
+                    dst,
+                    offset,
+                    size: wgt::BufferSize::new(size),
+                }
+            }),
+            "[a-zA-Z0-9 _]{0,12}".prop_map(Command::PushDebugGroup),
+            Just(()).prop_map(|()| Command::PopDebugGroup),
+            "[a-zA-Z0-9 _]{0,12}".prop_map(Command::InsertDebugMarker),
+            (id(), any::<u32>(), any::<u32>()).prop_map(
+                |(query_set, query_index, pipeline_stage)| Command::WriteTimestamp {
+                    query_set,
+                    query_index,
+                    pipeline_stage,
+                }
+            ),
+            (
+                id(),
+                any::<u32>(),
+                any::<u32>(),
+                id(),
+                any::<u64>(),
+                option::of(any::<u64>()),
+                any::<bool>()
+            )
+                .prop_map(
+                    |(
+                        query_set,
+                        first_query,
+                        query_count,
+                        destination,
+                        destination_offset,
+                        destination_stride,
+                        wait,
+                    )| Command::ResolveQuerySet {
+                        query_set,
+                        first_query,
+                        query_count,
+                        destination,
+                        destination_offset,
+                        destination_stride,
+                        wait,
+                    }
+                ),
+            (id(), any::<u32>(), any::<u32>()).prop_map(|(query_set, first_query, query_count)| {
+                Command::ResetQuerySet {
+                    query_set,
+                    first_query,
+                    query_count,
+                }
+            }),
+        ]
+    }
+
+    fn resource_action() -> impl Strategy<Value = Action> {
+        prop_oneof![
+            (id(), buffer_desc()).prop_map(|(id, desc)| Action::CreateBuffer { id, desc }),
+            id().prop_map(Action::DestroyBuffer),
+            (id(), query_set_desc()).prop_map(|(id, desc)| Action::CreateQuerySet { id, desc }),
+            id().prop_map(Action::DestroyQuerySet),
+            (id(), label()).prop_map(|(id, label)| Action::CreateCommandEncoder {
+                id,
+                desc: wgt::CommandEncoderDescriptor { label },
+            }),
+            id().prop_map(Action::DropCommandEncoder),
+        ]
+    }
+
+    fn buffer_action() -> impl Strategy<Value = Action> {
+        let mode = prop_oneof![Just(wgt::MapMode::Read), Just(wgt::MapMode::Write)];
+        prop_oneof![
+            (
+                id(),
+                "data[0-9]{1,3}\\.bin",
+                range(),
+                any::<bool>(),
+                any::<bool>()
+            )
+                .prop_map(|(id, data, range, queued, compressed)| {
+                    Action::WriteBuffer {
+                        id,
+                        data,
+                        range,
+                        queued,
+                        compressed,
+                    }
+                }),
+            (id(), any::<u64>(), option::of(any::<u64>()))
+                .prop_map(|(id, offset, size)| Action::ClearBuffer { id, offset, size }),
+            (id(), mode, range()).prop_map(|(id, mode, range)| Action::MapBuffer {
+                id,
+                mode,
+                range
+            }),
+            id().prop_map(Action::UnmapBuffer),
+        ]
+    }
+
+    pub fn action() -> impl Strategy<Value = Action> {
+        prop_oneof![
+            resource_action(),
+            buffer_action(),
+            (
+                any::<usize>(),
+                option::of(id()),
+                id(),
+                proptest::collection::vec(command(), 0..8)
+            )
+                .prop_map(|(index, queue, encoder, commands)| {
+                    Action::Submit(index, queue, encoder, commands)
+                }),
+            any::<u64>().prop_map(|frame_index| Action::FrameBoundary { frame_index }),
+            any::<bool>().prop_map(|force_wait| Action::Poll { force_wait }),
+        ]
+    }
+}
+
+#[cfg(all(test, feature = "trace", feature = "replay"))]
+proptest::proptest! {
+    #[test]
+    fn test_arbitrary_action_round_trip(action in strategies::action()) {
+        proptest::prop_assert_eq!(ron_round_trip(&action), action);
+    }
+
+    #[test]
+    fn test_arbitrary_command_round_trip(command in strategies::command()) {
+        let action = Action::Submit(
+            1,
+            None,
+            id::TypedId::zip(0, 1, wgt::Backend::Vulkan),
+            vec![command],
+        );
+        proptest::prop_assert_eq!(ron_round_trip(&action), action);
+    }
+}
+
+#[cfg(all(feature = "trace", feature = "replay"))]
+#[test]
+fn test_init_without_adapter_capabilities() {
+    let init = sample_actions().remove(0);
+    let string = ron::ser::to_string(&init).unwrap();
+    // the features and limits are the last fields of `Init`
+    let cut = string.find("adapter_features").unwrap();
+    let old = format!(
+        "{})",
+        string[..cut].trim_end_matches(|c: char| c == ',' || c.is_whitespace())
+    );
+    match ron::de::from_str::<Action>(&old) {
+        Ok(Action::Init {
+            adapter_features,
+            adapter_limits,
+            ..
+        }) => {
+            assert_eq!(adapter_features, wgt::Features::empty());
+            assert_eq!(adapter_limits, wgt::Limits::default());
+        }
+        other => panic!("Unexpected {:?} parsed from {}", other, old),
+    }
+}
+
+#[cfg(feature = "trace")]
+#[test]
+fn test_standalone_query() {
+    let query_set = id::TypedId::zip(0, 1, wgt::Backend::Vulkan);
+    let timestamp = Command::WriteTimestamp {
+        query_set,
+        query_index: 1,
+        pipeline_stage: 4,
+    };
+    let action = standalone_query(&timestamp).unwrap();
+    assert_eq!(
+        format!("{:?}", action),
+        format!(
+            "{:?}",
+            Action::WriteTimestamp {
+                query_set,
+                query_index: 1,
+                pipeline_stage: 4,
+            }
+        )
+    );
+    let clear = Command::ClearBuffer {
+        dst: id::TypedId::zip(0, 1, wgt::Backend::Vulkan),
+        offset: 0,
+        size: None,
+    };
+    assert!(standalone_query(&clear).is_none());
+}
+
+#[cfg(all(feature = "trace", feature = "replay"))]
+#[test]
+fn test_stage_entry_point() {
+    let module = id::TypedId::zip(0, 1, wgt::Backend::Vulkan);
+    let stage = ProgrammableStageDescriptor::new(&crate::pipeline::ProgrammableStageDescriptor {
+        module,
+        entry_point: "cs_main",
+    });
+    let string = ron::ser::to_string_pretty(&stage, ron::ser::PrettyConfig::default()).unwrap();
+    let parsed = ron::de::from_str::<ProgrammableStageDescriptor>(&string).unwrap();
+    assert_eq!(parsed.to_core().unwrap().entry_point, "cs_main");
+
+    let empty = ProgrammableStageDescriptor {
+        module,
+        entry_point: String::new(),
+    };
+    let error = empty.to_core().unwrap_err();
+    assert!(
+        error.to_string().contains("missing entry point"),
+        "{}",
+        error
+    );
+}
+
+#[cfg(all(feature = "trace", feature = "replay"))]
+#[test]
+fn test_attachment_round_trip() {
+    use crate::command::{
+        BasePass, ColorAttachmentDescriptor, DepthStencilAttachmentDescriptor, LoadOp, PassChannel,
+        PassTimestampWrites, StoreOp,
+    };
+
+    // values that have no short decimal representation
+    let clear_color = wgt::Color {
+        r: 0.1,
+        g: 1.0 / 3.0,
+        b: std::f64::consts::PI,
+        a: 1e-10,
+    };
+    let clear_depth = 0.7_f32;
+    // only the end of the pass is timed
+    let timestamp_writes = PassTimestampWrites {
+        query_set: id::TypedId::zip(6, 1, wgt::Backend::Vulkan),
+        beginning_of_pass_write_index: None,
+        end_of_pass_write_index: Some(3),
+    };
+    let command = Command::RunRenderPass {
+        base: BasePass {
+            commands: Vec::new(),
+            dynamic_offsets: Vec::new(),
+            string_data: Vec::new(),
+            push_constant_data: Vec::new(),
+        },
+        target_colors: vec![ColorAttachmentDescriptor {
+            attachment: id::TypedId::zip(3, 1, wgt::Backend::Vulkan),
+            resolve_target: Some(id::TypedId::zip(4, 2, wgt::Backend::Vulkan)),
+            channel: PassChannel {
+                load_op: LoadOp::Clear,
+                store_op: StoreOp::Clear,
+                clear_value: clear_color,
+                read_only: false,
+            },
+        }],
+        target_depth_stencil: Some(DepthStencilAttachmentDescriptor {
+            attachment: id::TypedId::zip(5, 1, wgt::Backend::Vulkan),
+            depth: PassChannel {
+                load_op: LoadOp::Clear,
+                store_op: StoreOp::Store,
+                clear_value: clear_depth,
+                read_only: false,
+            },
+            stencil: PassChannel {
+                load_op: LoadOp::Load,
+                store_op: StoreOp::Clear,
+                clear_value: 0xAB,
+                read_only: true,
+            },
+        }),
+        timestamp_writes: Some(timestamp_writes.clone()),
+    };
+
+    let string = ron::ser::to_string_pretty(&command, ron::ser::PrettyConfig::default()).unwrap();
+    let bytes = bincode::serialize(&command).unwrap();
+    for parsed in vec![
+        ron::de::from_str::<Command>(&string).unwrap(),
+        bincode::deserialize::<Command>(&bytes).unwrap(),
+    ] {
+        let (colors, depth_stencil, writes) = match parsed {
+            Command::RunRenderPass {
+                target_colors,
+                target_depth_stencil,
+                timestamp_writes,
+                ..
+            } => (
+                target_colors,
+                target_depth_stencil.unwrap(),
+                timestamp_writes,
+            ),
+            other => panic!("Unexpected command {:?}", other),
+        };
+        assert_eq!(colors.len(), 1);
+        let color = &colors[0];
+        assert_eq!(
+            color.attachment,
+            id::TypedId::zip(3, 1, wgt::Backend::Vulkan)
+        );
+        assert_eq!(
+            color.resolve_target,
+            Some(id::TypedId::zip(4, 2, wgt::Backend::Vulkan))
+        );
+        assert_eq!(color.channel.load_op, LoadOp::Clear);
+        assert_eq!(color.channel.store_op, StoreOp::Clear);
+        assert!(!color.channel.read_only);
+        let value = color.channel.clear_value;
+        assert_eq!(value.r.to_bits(), clear_color.r.to_bits());
+        assert_eq!(value.g.to_bits(), clear_color.g.to_bits());
+        assert_eq!(value.b.to_bits(), clear_color.b.to_bits());
+        assert_eq!(value.a.to_bits(), clear_color.a.to_bits());
+
+        assert_eq!(
+            depth_stencil.attachment,
+            id::TypedId::zip(5, 1, wgt::Backend::Vulkan)
+        );
+        assert_eq!(depth_stencil.depth.load_op, LoadOp::Clear);
+        assert_eq!(depth_stencil.depth.store_op, StoreOp::Store);
+        assert_eq!(
+            depth_stencil.depth.clear_value.to_bits(),
+            clear_depth.to_bits()
+        );
+        assert!(!depth_stencil.depth.read_only);
+        assert_eq!(depth_stencil.stencil.load_op, LoadOp::Load);
+        assert_eq!(depth_stencil.stencil.store_op, StoreOp::Clear);
+        assert_eq!(depth_stencil.stencil.clear_value, 0xAB);
+        assert!(depth_stencil.stencil.read_only);
+
+        assert_eq!(writes.as_ref(), Some(&timestamp_writes));
+    }
+}
+
+/// Creates an empty directory for the test files of `name`, named after
+/// the process too, so that concurrent runs don't share their files.
+#[cfg(all(test, feature = "trace"))]
+fn test_dir(name: &str) -> std::path::PathBuf {
+    let dir = std::env::temp_dir().join(format!("{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[cfg(feature = "trace")]
+#[test]
+fn test_binary_deduplication() {
+    let dir = test_dir("wgpu-trace-binaries");
+    let mut trace = Trace::new(&dir, wgt::Backend::Empty, TraceFormat::Ron).unwrap();
+    let shader = [0x0723_0203u32.to_le_bytes(), [1, 2, 3, 4]].concat();
+    let first = trace.make_binary("spv", &shader);
+    let second = trace.make_binary("spv", &shader);
+    assert_eq!(first, second);
+    assert!(first.starts_with("shader_"), "{}", first);
+    let data = trace.make_binary("bin", &[5, 6, 7, 8]);
+    assert_ne!(first, data);
+    drop(trace);
+
+    let mut binaries = std::fs::read_dir(&dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+        .filter(|name| name != FILE_NAME)
+        .collect::<Vec<_>>();
+    binaries.sort();
+    assert_eq!(binaries, [data, first]);
+}
+
+#[cfg(feature = "trace")]
+#[test]
+fn test_binary_write_failure() {
+    let dir = test_dir("wgpu-trace-binary-failure");
+    let mut trace = Trace::new(&dir, wgt::Backend::Empty, TraceFormat::Ron).unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+    let name = trace.make_binary("bin", &[1, 2, 3, 4]);
+    assert!(!dir.join(&name).exists());
+
+    // the failed file isn't reused, but written again
+    std::fs::create_dir_all(&dir).unwrap();
+    assert_eq!(trace.make_binary("bin", &[1, 2, 3, 4]), name);
+    assert_eq!(std::fs::read(dir.join(&name)).unwrap(), [1, 2, 3, 4]);
+}
+
+#[cfg(feature = "trace-compression")]
+#[test]
+fn test_compressed_binaries() {
+    let dir = test_dir("wgpu-trace-compressed");
+    let mut trace = Trace::new(&dir, wgt::Backend::Empty, TraceFormat::Ron).unwrap();
+    assert!(!trace.make_binary("bin", &[1, 2, 3, 4]).ends_with(".gz"));
+    drop(trace);
+
+    let mut trace = Trace::new_compressed(&dir, wgt::Backend::Empty, TraceFormat::Ron).unwrap();
+    let name = trace.make_binary("bin", &[5, 6, 7, 8]);
+    assert!(name.ends_with(".bin.gz"), "{}", name);
+    assert_eq!(read_binary(&dir.join(&name)).unwrap(), [5, 6, 7, 8]);
+    trace.add_buffer_writes(
+        id::TypedId::zip(0, 1, wgt::Backend::Empty),
+        &[(0, &[5, 6, 7, 8][..])],
+    );
+    drop(trace);
+
+    let contents = std::fs::read_to_string(dir.join(FILE_NAME)).unwrap();
+    assert!(contents.contains("compressed: true"), "{}", contents);
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[cfg(feature = "trace")]
+#[test]
+fn test_buffer_writes_coalescing() {
+    // eight contiguous writes, then four with gaps in between
+    let data = (0..12u8).map(|i| [i; 4]).collect::<Vec<_>>();
+    let offsets = [0, 4, 8, 12, 16, 20, 24, 28, 64, 72, 76, 128];
+    let writes = offsets
+        .iter()
+        .zip(data.iter())
+        .map(|(&offset, bytes)| (offset, &bytes[..]))
+        .collect::<Vec<_>>();
+    let coalesced = coalesce_buffer_writes(&writes);
+    let ranges = coalesced
+        .iter()
+        .map(|&(ref range, _)| range.clone())
+        .collect::<Vec<_>>();
+    assert_eq!(ranges, [0..32, 64..68, 72..80, 128..132]);
+    assert_eq!(coalesced[0].1, data[..8].concat());
+    assert_eq!(coalesced[1].1, data[8]);
+    assert_eq!(coalesced[2].1, data[9..11].concat());
+    assert_eq!(coalesced[3].1, data[11]);
+}
+
+#[cfg(all(feature = "trace", feature = "replay"))]
+#[test]
+fn test_open_append() {
+    let dir = test_dir("wgpu-trace-append");
+    let mut trace = Trace::new_with_timing(&dir, wgt::Backend::Empty, TraceFormat::Ron).unwrap();
+    trace.add_frame_boundary(0);
+    drop(trace);
+    let contents = std::fs::read_to_string(dir.join(FILE_NAME)).unwrap();
+    assert!(last_cpu_time_us(&contents).is_some());
+
+    let mut trace = Trace::open_append(&dir).unwrap();
+    assert!(trace.start_time.is_some());
+    trace.add_frame_boundary(1);
+    drop(trace);
+    let contents = std::fs::read_to_string(dir.join(FILE_NAME)).unwrap();
+    assert_eq!(contents.matches("FrameBoundary").count(), 2);
+    assert!(last_cpu_time_us(&contents).is_some());
+
+    let untimed = format!(
+        "[\n{},\n{},\n]",
+        ron::ser::to_string(&TraceHeader {
+            version: FORMAT_VERSION,
+            wgpu_core_git_hash: String::new(),
+            backend: wgt::Backend::Empty,
+            timed: false,
+        })
+        .unwrap(),
+        ron::ser::to_string(&Action::FrameBoundary { frame_index: 0 }).unwrap()
+    );
+    assert_eq!(last_cpu_time_us(&untimed), None);
+}
+
+/// Compares the cost of serializing a frame with thousands of draws into
+/// the entries of a RON and of a bincode trace. Run it with
+/// `cargo test -p wgpu-core --release --features trace,replay -- --ignored --nocapture bench_trace_formats`.
+#[cfg(all(feature = "trace", feature = "replay"))]
+#[test]
+#[ignore]
+fn bench_trace_formats() {
+    use crate::command::{BasePass, RenderCommand};
+
+    const DRAW_COUNT: u32 = 5000;
+    const ROUNDS: u32 = 20;
+    let action = {
+        let mut commands = vec![RenderCommand::SetPipeline(id::TypedId::zip(
+            0,
+            1,
+            wgt::Backend::Vulkan,
+        ))];
+        for i in 0..DRAW_COUNT {
+            commands.push(RenderCommand::SetVertexBuffer {
+                slot: 0,
+                buffer_id: id::TypedId::zip(i % 16, 1, wgt::Backend::Vulkan),
+                offset: 256 * i as wgt::BufferAddress,
+                size: None,
+            });
+            commands.push(RenderCommand::Draw {
+                vertex_count: 3,
+                instance_count: 1,
+                first_vertex: 0,
+                first_instance: i,
+            });
+        }
+        let command = Command::RunRenderPass {
+            base: BasePass {
+                commands,
+                dynamic_offsets: Vec::new(),
+                string_data: Vec::new(),
+                push_constant_data: Vec::new(),
+            },
+            target_colors: Vec::new(),
+            target_depth_stencil: None,
+            timestamp_writes: None,
+        };
+        Action::Submit(
+            1,
+            None,
+            id::TypedId::zip(0, 1, wgt::Backend::Vulkan),
+            vec![command],
+        )
+    };
+    let measure = |name: &str, serialize: &dyn Fn(&Action) -> usize| {
+        let start = std::time::Instant::now();
+        let mut size = 0;
+        for _ in 0..ROUNDS {
+            size = serialize(&action);
+        }
+        println!(
+            "{:<12} {:>10.3} ms per frame, {:>10} bytes",
+            name,
+            start.elapsed().as_secs_f64() * 1000.0 / ROUNDS as f64,
+            size
+        );
+    };
+    measure("RON pretty", &|action| {
+        ron::ser::to_string_pretty(action, ron::ser::PrettyConfig::default())
+            .unwrap()
+            .len()
+    });
+    measure("RON compact", &|action| {
+        ron::ser::to_string(action).unwrap().len()
+    });
+    measure("bincode", &|action| {
+        bincode::serialize(action).unwrap().len()
+    });
 }
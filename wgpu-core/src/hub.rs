@@ -25,9 +25,13 @@ use wgt::Backend;
 
 #[cfg(debug_assertions)]
 use std::cell::Cell;
-use std::{fmt::Debug, marker::PhantomData, ops, thread};
+use std::{convert::TryFrom as _, fmt::Debug, marker::PhantomData, ops, thread};
 
 /// A simple structure to manage identities of objects.
+///
+/// The index of a freed ID is reused right away, with the next epoch,
+/// so that the stale IDs are caught by `Storage`. An index whose epochs
+/// are exhausted is never reused, instead of wrapping around.
 #[derive(Debug)]
 pub struct IdentityManager {
     free: Vec<Index>,
@@ -56,7 +60,7 @@ impl IdentityManager {
             Some(index) => I::zip(index, self.epochs[index as usize], backend),
             None => {
                 let epoch = 1;
-                let id = I::zip(self.epochs.len() as Index, epoch, backend);
+                let id = I::zip(new_index(self.epochs.len()), epoch, backend);
                 self.epochs.push(epoch);
                 id
             }
@@ -71,11 +75,21 @@ impl IdentityManager {
         }
         let pe = &mut self.epochs[index as usize];
         assert_eq!(*pe, epoch);
+        if *pe == crate::id::EPOCH_MASK {
+            log::warn!("Retiring {:?}, the epochs of its index are exhausted", id);
+            return;
+        }
         *pe += 1;
         self.free.push(index);
     }
 }
 
+/// Index of a new ID, after `count` indices were handed out.
+fn new_index(count: usize) -> Index {
+    Index::try_from(count)
+        .unwrap_or_else(|_| panic!("All the {} indices of IDs are in use or retired", count))
+}
+
 #[derive(Debug)]
 enum Element<T> {
     Vacant,
@@ -223,6 +237,7 @@ impl<B: hal::Backend> Access<CommandBuffer<B>> for Device<B> {}
 impl<B: hal::Backend> Access<CommandBuffer<B>> for SwapChain<B> {}
 impl<B: hal::Backend> Access<RenderBundle> for Device<B> {}
 impl<B: hal::Backend> Access<RenderBundle> for CommandBuffer<B> {}
+impl<B: hal::Backend> Access<RenderBundle> for QuerySet<B> {}
 impl<B: hal::Backend> Access<ComputePipeline<B>> for Device<B> {}
 impl<B: hal::Backend> Access<ComputePipeline<B>> for BindGroup<B> {}
 impl<B: hal::Backend> Access<RenderPipeline<B>> for Device<B> {}
@@ -760,6 +775,37 @@ impl GfxBackend for backend::Dx11 {
     }
 }
 
+#[test]
+fn test_identity_epochs() {
+    let mut manager = IdentityManager::default();
+    let first = manager.alloc::<BufferId>(Backend::Empty);
+    manager.free(first);
+    let second = manager.alloc::<BufferId>(Backend::Empty);
+    assert_eq!(second.unzip(), (0, 2, Backend::Empty));
+
+    // the last epoch of an index is the last use of it
+    manager.free(second);
+    manager.epochs[0] = crate::id::EPOCH_MASK;
+    let last = manager.alloc::<BufferId>(Backend::Empty);
+    assert_eq!(last.unzip(), (0, crate::id::EPOCH_MASK, Backend::Empty));
+    manager.free(last);
+    let next = manager.alloc::<BufferId>(Backend::Empty);
+    assert_eq!(next.unzip(), (1, 1, Backend::Empty));
+}
+
+#[test]
+fn test_new_index() {
+    assert_eq!(new_index(0), 0);
+    assert_eq!(new_index(u32::MAX as usize), u32::MAX);
+}
+
+#[cfg(target_pointer_width = "64")]
+#[test]
+#[should_panic(expected = "indices of IDs are in use or retired")]
+fn test_new_index_exhausted() {
+    new_index(u32::MAX as usize + 1);
+}
+
 #[cfg(test)]
 fn _test_send_sync(global: &Global<IdentityManagerFactory>) {
     fn test_internal<T: Send + Sync>(_: T) {}
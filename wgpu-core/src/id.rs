@@ -7,7 +7,7 @@ use std::{fmt, marker::PhantomData, num::NonZeroU64};
 use wgt::Backend;
 
 const BACKEND_BITS: usize = 3;
-const EPOCH_MASK: u32 = (1 << (32 - BACKEND_BITS)) - 1;
+pub(crate) const EPOCH_MASK: u32 = (1 << (32 - BACKEND_BITS)) - 1;
 type Dummy = crate::backend::Empty;
 
 #[repr(transparent)]
@@ -84,6 +84,28 @@ impl<T> fmt::Debug for Id<T> {
     }
 }
 
+/// Short form for messages, like `Buffer#1v2(Vk)` for the index 1
+/// and the epoch 2 of a buffer on Vulkan.
+impl<T> fmt::Display for Id<T> {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        // the type name is like `wgpu_core::resource::Buffer<wgpu_core::backend::Empty>`
+        let type_name = std::any::type_name::<T>();
+        let path = type_name.split('<').next().unwrap_or(type_name);
+        let kind = path.rsplit("::").next().unwrap_or(path);
+        let (index, epoch, backend) = self.unzip();
+        let backend = match backend {
+            Backend::Empty => "Empty",
+            Backend::Vulkan => "Vk",
+            Backend::Metal => "Mtl",
+            Backend::Dx12 => "Dx12",
+            Backend::Dx11 => "Dx11",
+            Backend::Gl => "Gl",
+            Backend::BrowserWebGpu => "WebGpu",
+        };
+        write!(formatter, "{}#{}v{}({})", kind, index, epoch, backend)
+    }
+}
+
 impl<T> std::hash::Hash for Id<T> {
     fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
         self.0.hash(state);
@@ -175,3 +197,11 @@ fn test_id_backend() {
         assert_eq!(id.backend(), b);
     }
 }
+
+#[test]
+fn test_id_display() {
+    let buffer = BufferId::zip(1, 2, Backend::Vulkan);
+    assert_eq!(buffer.to_string(), "Buffer#1v2(Vk)");
+    let bundle = RenderBundleId::zip(0, 1, Backend::Dx12);
+    assert_eq!(bundle.to_string(), "RenderBundle#0v1(Dx12)");
+}
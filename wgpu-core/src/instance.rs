@@ -379,6 +379,17 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
         &self,
         desc: &RequestAdapterOptions,
         inputs: AdapterInputs<Input<G, AdapterId>>,
+    ) -> Option<AdapterId> {
+        self.pick_adapter_filtered(desc, inputs, |_| true)
+    }
+
+    /// Picks an adapter like `pick_adapter` does, but only out of
+    /// the adapters for which `filter` returns true.
+    pub fn pick_adapter_filtered(
+        &self,
+        desc: &RequestAdapterOptions,
+        inputs: AdapterInputs<Input<G, AdapterId>>,
+        filter: impl Fn(&AdapterInfo) -> bool,
     ) -> Option<AdapterId> {
         span!(_guard, INFO, "Instance::pick_adapter");
 
@@ -394,10 +405,13 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
         let mut id_dx11 = inputs.find(Backend::Dx11);
 
         backends_map! {
-            let map = |(instance_backend, id_backend, surface_backend)| {
+            let map = |(instance_backend, backend, id_backend, surface_backend)| {
                 match instance_backend {
                     Some(ref inst) if id_backend.is_some() => {
                         let mut adapters = inst.enumerate_adapters();
+                        adapters.retain(|a| {
+                            filter(&AdapterInfo::from_gfx(a.info.clone(), backend))
+                        });
                         if let Some(surface_backend) = compatible_surface.and_then(surface_backend) {
                             adapters.retain(|a| {
                                 a.queue_families
@@ -416,28 +430,28 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
             // NB: The internal function definitions are a workaround for Rust
             // being weird with lifetimes for closure literals...
             #[vulkan]
-            let adapters_vk = map((&instance.vulkan, &id_vulkan, {
+            let adapters_vk = map((&instance.vulkan, Backend::Vulkan, &id_vulkan, {
                 fn surface_vulkan(surf: &Surface) -> Option<&GfxSurface<backend::Vulkan>> {
                     surf.vulkan.as_ref()
                 }
                 surface_vulkan
             }));
             #[metal]
-            let adapters_mtl = map((&instance.metal, &id_metal, {
+            let adapters_mtl = map((&instance.metal, Backend::Metal, &id_metal, {
                 fn surface_metal(surf: &Surface) -> Option<&GfxSurface<backend::Metal>> {
                     surf.metal.as_ref()
                 }
                 surface_metal
             }));
             #[dx12]
-            let adapters_dx12 = map((&instance.dx12, &id_dx12, {
+            let adapters_dx12 = map((&instance.dx12, Backend::Dx12, &id_dx12, {
                 fn surface_dx12(surf: &Surface) -> Option<&GfxSurface<backend::Dx12>> {
                     surf.dx12.as_ref()
                 }
                 surface_dx12
             }));
             #[dx11]
-            let adapters_dx11 = map((&instance.dx11, &id_dx11, {
+            let adapters_dx11 = map((&instance.dx11, Backend::Dx11, &id_dx11, {
                 fn surface_dx11(surf: &Surface) -> Option<&GfxSurface<backend::Dx11>> {
                     surf.dx11.as_ref()
                 }
@@ -720,6 +734,31 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
             )
         };
 
-        Ok(hub.devices.register_identity(id_in, device, &mut token))
+        let id = hub.devices.register_identity(id_in, device, &mut token);
+        #[cfg(feature = "trace")]
+        {
+            let (adapter_guard, mut token) = hub.adapters.read(&mut token);
+            let adapter = &adapter_guard[adapter_id];
+            let (device_guard, _) = hub.devices.read(&mut token);
+            match device_guard[id].trace {
+                Some(ref trace) => {
+                    let adapter_info = AdapterInfo::from_gfx(adapter.raw.info.clone(), B::VARIANT);
+                    let mut trace = trace.lock();
+                    if let Err(e) = trace.write_metadata(desc, &adapter_info) {
+                        log::warn!("Unable to write the trace metadata: {:?}", e);
+                    }
+                    trace.add(crate::device::trace::Action::Init {
+                        device: id,
+                        desc: desc.clone(),
+                        backend: B::VARIANT,
+                        adapter_info,
+                        adapter_features: adapter.features,
+                        adapter_limits: adapter.limits.clone(),
+                    });
+                }
+                None => (),
+            }
+        }
+        Ok(id)
     }
 }
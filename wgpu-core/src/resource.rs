@@ -12,6 +12,7 @@ use gfx_memory::MemoryBlock;
 use wgt::{BufferAddress, BufferUsage, TextureFormat, TextureUsage};
 
 use std::{borrow::Borrow, ptr::NonNull};
+use thiserror::Error;
 
 bitflags::bitflags! {
     /// The internal enum mirrored from `BufferUsage`. The values don't have to match!
@@ -231,9 +232,48 @@ impl<B: hal::Backend> Borrow<()> for Sampler<B> {
     }
 }
 
+#[derive(Clone, Debug, Error)]
+pub enum CreateQuerySetError {
+    #[error(transparent)]
+    QueryPoolCreation(#[from] hal::query::CreationError),
+    #[error("query sets must have at least one query")]
+    ZeroCount,
+    #[error("query set of {count} queries exceeds the limit of {max}")]
+    TooManyQueries { count: u32, max: u32 },
+    #[error("pipeline statistics query sets must collect at least one statistic")]
+    NoPipelineStatistics,
+    #[error("timestamp queries are not supported by the device")]
+    TimestampsUnsupported,
+}
+
+/// Type of the queries of a set.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum QuerySetType {
+    Occlusion,
+    /// Statistics gathered by each query, in the order they are resolved.
+    PipelineStatistics(Vec<wgt::PipelineStatisticName>),
+    Timestamp,
+}
+
+impl QuerySetType {
+    pub(crate) fn kind(&self) -> crate::command::QueryKind {
+        use crate::command::QueryKind;
+        match *self {
+            QuerySetType::Occlusion => QueryKind::Occlusion,
+            QuerySetType::PipelineStatistics(_) => QueryKind::PipelineStatistics,
+            QuerySetType::Timestamp => QueryKind::Timestamp,
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct QuerySet<B: hal::Backend> {
     pub(crate) raw: B::QueryPool,
+    pub(crate) type_: QuerySetType,
+    /// Number of queries in the set.
+    pub(crate) count: u32,
+    /// Size of a resolved query of the type of the set.
+    pub(crate) resolve_stride: wgt::BufferAddress,
     pub(crate) device_id: Stored<DeviceId>,
     pub(crate) life_guard: LifeGuard,
 }
@@ -314,7 +314,7 @@ impl Default for Limits {
 
 /// Describes a [`Device`].
 #[repr(C)]
-#[derive(Clone, Debug, Default)]
+#[derive(Clone, Debug, Default, PartialEq)]
 #[cfg_attr(feature = "trace", derive(Serialize))]
 #[cfg_attr(feature = "replay", derive(Deserialize))]
 pub struct DeviceDescriptor {
@@ -1014,6 +1014,18 @@ bitflags::bitflags! {
     }
 }
 
+/// Type of access to a mapped [`Buffer`].
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "trace", derive(Serialize))]
+#[cfg_attr(feature = "replay", derive(Deserialize))]
+pub enum MapMode {
+    /// Map the buffer for reading, which needs [`BufferUsage::MAP_READ`].
+    Read = 0,
+    /// Map the buffer for writing, which needs [`BufferUsage::MAP_WRITE`].
+    Write = 1,
+}
+
 /// Describes a [`Buffer`].
 #[repr(C)]
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
@@ -1046,6 +1058,8 @@ impl<L> BufferDescriptor<L> {
 /// Describes a [`CommandEncoder`].
 #[repr(C)]
 #[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "trace", derive(Serialize))]
+#[cfg_attr(feature = "replay", derive(Deserialize))]
 pub struct CommandEncoderDescriptor<L> {
     /// Debug label for the command encoder. This will show up in graphics debuggers for easy identification.
     pub label: L,
@@ -1344,6 +1358,24 @@ impl<L> TextureViewDescriptor<L> {
     }
 }
 
+/// Subresources of a texture affected by an operation, such as a clear.
+#[repr(C)]
+#[derive(Clone, Debug, Default, Hash, Eq, PartialEq)]
+#[cfg_attr(feature = "trace", derive(Serialize))]
+#[cfg_attr(feature = "replay", derive(Deserialize))]
+pub struct ImageSubresourceRange {
+    /// Aspect of the texture. Color textures must be [`TextureAspect::All`].
+    pub aspect: TextureAspect,
+    /// Base mip level.
+    pub base_mip_level: u32,
+    /// Mip level count. Zero means all the levels starting from `base_mip_level`.
+    pub level_count: u32,
+    /// Base array layer.
+    pub base_array_layer: u32,
+    /// Layer count. Zero means all the layers starting from `base_array_layer`.
+    pub array_layer_count: u32,
+}
+
 /// How edges should be handled in texture addressing.
 #[repr(C)]
 #[derive(Copy, Clone, Debug, Hash, Eq, PartialEq)]
@@ -1697,7 +1729,7 @@ impl From<TextureFormat> for TextureComponentType {
 
 /// Layout of a texture in a buffer's memory.
 #[repr(C)]
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 #[cfg_attr(feature = "trace", derive(serde::Serialize))]
 #[cfg_attr(feature = "replay", derive(serde::Deserialize))]
 pub struct TextureDataLayout {
@@ -1876,7 +1908,7 @@ pub struct BindGroupLayoutDescriptor<'a> {
 }
 
 /// View of a buffer which can be used to copy to/from a texture.
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 #[cfg_attr(feature = "trace", derive(serde::Serialize))]
 #[cfg_attr(feature = "replay", derive(serde::Deserialize))]
 pub struct BufferCopyView<B> {
@@ -1887,7 +1919,7 @@ pub struct BufferCopyView<B> {
 }
 
 /// View of a texture which can be used to copy to/from a buffer/texture.
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 #[cfg_attr(feature = "trace", derive(serde::Serialize))]
 #[cfg_attr(feature = "replay", derive(serde::Deserialize))]
 pub struct TextureCopyView<T> {